@@ -0,0 +1,82 @@
+use std::fmt;
+
+use serde_json::Value;
+
+/// A Bot API version, like 7.4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ApiVersion {
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Methods this crate wraps, by the Bot API version that added them.
+const METHODS: &[(&str, ApiVersion)] = &[
+    ("setMessageReaction", ApiVersion::new(7, 0)),
+    ("sendPaidMedia", ApiVersion::new(7, 6)),
+    ("setUserEmojiStatus", ApiVersion::new(8, 0)),
+    ("savePreparedInlineMessage", ApiVersion::new(8, 0)),
+    ("editUserStarSubscription", ApiVersion::new(8, 0)),
+    ("verifyUser", ApiVersion::new(8, 2)),
+    ("verifyChat", ApiVersion::new(8, 2)),
+    ("removeUserVerification", ApiVersion::new(8, 2)),
+    ("removeChatVerification", ApiVersion::new(8, 2)),
+    ("readBusinessMessage", ApiVersion::new(9, 0)),
+    ("deleteBusinessMessages", ApiVersion::new(9, 0)),
+    ("setBusinessAccountName", ApiVersion::new(9, 0)),
+    ("setBusinessAccountBio", ApiVersion::new(9, 0)),
+    ("setBusinessAccountProfilePhoto", ApiVersion::new(9, 0)),
+    ("removeBusinessAccountProfilePhoto", ApiVersion::new(9, 0)),
+    ("postStory", ApiVersion::new(9, 0)),
+    ("editStory", ApiVersion::new(9, 0)),
+    ("deleteStory", ApiVersion::new(9, 0)),
+    ("sendChecklist", ApiVersion::new(9, 1)),
+    ("editMessageChecklist", ApiVersion::new(9, 1)),
+];
+
+/// Optional parameters, of any method, by the Bot API version that added them.
+const PARAMETERS: &[(&str, ApiVersion)] = &[
+    ("reply_parameters", ApiVersion::new(7, 0)),
+    ("link_preview_options", ApiVersion::new(7, 0)),
+    ("business_connection_id", ApiVersion::new(7, 2)),
+    ("message_effect_id", ApiVersion::new(7, 4)),
+    ("show_caption_above_media", ApiVersion::new(7, 4)),
+    ("allow_paid_broadcast", ApiVersion::new(7, 11)),
+];
+
+// Fails calls to methods the server is too old for, and strips parameters it is too old for, since older servers reject unknown parameters or silently misbehave.
+pub(crate) fn gate(server: ApiVersion, method: &str, args: &mut Value) -> anyhow::Result<()> {
+    if let Some((_, added)) = METHODS.iter().find(|(name, _)| *name == method) {
+        if *added > server {
+            anyhow::bail!("{method} needs Bot API {added}, but the server only supports {server}")
+        }
+    }
+    let Some(args) = args.as_object_mut() else {
+        return Ok(());
+    };
+    // before link_preview_options, previews could only be turned off
+    if server < ApiVersion::new(7, 0) {
+        if let Some(options) = args.remove("link_preview_options") {
+            if options["is_disabled"] == true {
+                args.insert("disable_web_page_preview".into(), true.into());
+            }
+        }
+    }
+    for (param, added) in PARAMETERS {
+        if *added > server && args.remove(*param).is_some() {
+            tracing::warn!("dropping {param} from {method}: it needs Bot API {added}, but the server only supports {server}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use serde_json::Value;
+use smol::lock::Semaphore;
+
+use crate::{BotHandle, Inner, TelegramBot};
+
+/// How many calls of a batch may be in flight at once.
+const MAX_IN_FLIGHT: usize = 16;
+
+impl Inner {
+    async fn call_batch(
+        self: &Arc<Self>,
+        calls: Vec<(String, Value)>,
+    ) -> Vec<anyhow::Result<Value>> {
+        let permits = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+        let tasks: Vec<_> = calls
+            .into_iter()
+            .map(|(method, args)| {
+                let inner = self.clone();
+                let permits = permits.clone();
+                smol::spawn(async move {
+                    let _permit = permits.acquire_arc().await;
+                    inner.call_api(&method, args).await
+                })
+            })
+            .collect();
+        let mut results = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            results.push(task.await);
+        }
+        results
+    }
+}
+
+impl TelegramBot {
+    /// Makes independent API calls concurrently, up to 16 at a time, returning each call's result in the order the calls were given. One call failing does not stop the others.
+    pub async fn call_batch(
+        &self,
+        calls: Vec<(impl Into<String>, Value)>,
+    ) -> Vec<anyhow::Result<Value>> {
+        self.inner.call_batch(into_owned(calls)).await
+    }
+}
+
+impl BotHandle {
+    /// Makes independent API calls concurrently, up to 16 at a time, returning each call's result in the order the calls were given. One call failing does not stop the others.
+    pub async fn call_batch(
+        &self,
+        calls: Vec<(impl Into<String>, Value)>,
+    ) -> Vec<anyhow::Result<Value>> {
+        self.inner.call_batch(into_owned(calls)).await
+    }
+}
+
+fn into_owned(calls: Vec<(impl Into<String>, Value)>) -> Vec<(String, Value)> {
+    calls
+        .into_iter()
+        .map(|(method, args)| (method.into(), args))
+        .collect()
+}
@@ -0,0 +1,273 @@
+use std::{fmt::Write, process::ExitCode};
+
+use anyhow::Context;
+use serde_json::Value;
+
+const USAGE: &str = "usage: tg-codegen SCHEMA.json > src/generated.rs
+
+Generates typed method wrappers and models from a Bot API schema in the format of https://github.com/PaulSonOfLars/telegram-bot-api-spec (api.json), for inclusion in this crate as `pub mod generated`. The output is not formatted; run rustfmt on it. Methods go on an Api type reached through BotHandle::api, so they never clash with the hand-written wrappers.";
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "static", "struct", "super", "trait", "true", "type", "use",
+    "where", "while",
+];
+
+// How a schema field maps to Rust: its type as an argument, as a struct field, and how to read it from JSON.
+struct FieldType {
+    arg: &'static str,
+    field: &'static str,
+    parse: &'static str,
+}
+
+fn field_type(types: &[&str]) -> FieldType {
+    match types {
+        ["Integer"] => FieldType {
+            arg: "i64",
+            field: "i64",
+            parse: ".as_i64()",
+        },
+        ["Float"] => FieldType {
+            arg: "f64",
+            field: "f64",
+            parse: ".as_f64()",
+        },
+        ["String"] => FieldType {
+            arg: "&str",
+            field: "String",
+            parse: ".as_str().map(str::to_owned)",
+        },
+        ["Boolean"] | ["True"] => FieldType {
+            arg: "bool",
+            field: "bool",
+            parse: ".as_bool()",
+        },
+        _ => FieldType {
+            arg: "impl Into<Value>",
+            field: "Value",
+            parse: "",
+        },
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if !out.is_empty() {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn ident(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_owned()
+    }
+}
+
+fn doc(out: &mut String, indent: &str, description: &Value) {
+    let text = match description {
+        Value::Array(lines) => lines
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" "),
+        Value::String(line) => line.clone(),
+        _ => return,
+    };
+    if !text.is_empty() {
+        let _ = writeln!(out, "{indent}/// {}", text.replace('\n', " "));
+    }
+}
+
+fn types_of(field: &Value) -> Vec<&str> {
+    field["types"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect()
+}
+
+fn generate_type(out: &mut String, name: &str, schema: &Value) {
+    let Some(fields) = schema["fields"].as_array() else {
+        // union types like ChatMember have subtypes instead of fields
+        doc(out, "", &schema["description"]);
+        let _ = writeln!(out, "pub type {name} = Value;\n");
+        return;
+    };
+    doc(out, "", &schema["description"]);
+    let _ = writeln!(
+        out,
+        "#[derive(Clone, Debug, PartialEq)]\npub struct {name} {{"
+    );
+    for field in fields {
+        let field_name = ident(field["name"].as_str().unwrap_or_default());
+        let ty = field_type(&types_of(field)).field;
+        let required = field["required"].as_bool() == Some(true);
+        doc(out, "    ", &field["description"]);
+        if required || ty == "Value" {
+            let _ = writeln!(out, "    pub {field_name}: {ty},");
+        } else {
+            let _ = writeln!(out, "    pub {field_name}: Option<{ty}>,");
+        }
+    }
+    let _ = writeln!(
+        out,
+        "    /// The whole object, including fields added after this was generated.\n    pub raw: Value,\n}}\n"
+    );
+    let _ = writeln!(out, "impl {name} {{");
+    let _ = writeln!(
+        out,
+        "    /// Parses a {name} from its JSON representation.\n    pub fn from_json(value: &Value) -> Option<Self> {{\n        Some(Self {{"
+    );
+    for field in fields {
+        let json_name = field["name"].as_str().unwrap_or_default();
+        let field_name = ident(json_name);
+        let parse = field_type(&types_of(field)).parse;
+        let required = field["required"].as_bool() == Some(true);
+        if parse.is_empty() {
+            let _ = writeln!(
+                out,
+                "            {field_name}: value[\"{json_name}\"].clone(),"
+            );
+        } else if required {
+            let _ = writeln!(
+                out,
+                "            {field_name}: value[\"{json_name}\"]{parse}?,"
+            );
+        } else {
+            let _ = writeln!(
+                out,
+                "            {field_name}: value[\"{json_name}\"]{parse},"
+            );
+        }
+    }
+    let _ = writeln!(
+        out,
+        "            raw: value.clone(),\n        }})\n    }}\n}}\n"
+    );
+}
+
+fn generate_method(out: &mut String, name: &str, schema: &Value) {
+    let fields = schema["fields"].as_array().cloned().unwrap_or_default();
+    let (required, optional): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .partition(|field| field["required"].as_bool() == Some(true));
+    doc(out, "    ", &schema["description"]);
+    if !optional.is_empty() {
+        let names: Vec<_> = optional
+            .iter()
+            .filter_map(|field| field["name"].as_str())
+            .map(|name| format!("`{name}`"))
+            .collect();
+        let _ = writeln!(
+            out,
+            "    ///\n    /// Optional parameters go in `optional`, a JSON object: {}.",
+            names.join(", ")
+        );
+    }
+    let mut params = String::new();
+    for field in &required {
+        let field_name = ident(field["name"].as_str().unwrap_or_default());
+        let _ = write!(
+            params,
+            ", {field_name}: {}",
+            field_type(&types_of(field)).arg
+        );
+    }
+    if !optional.is_empty() {
+        params.push_str(", optional: Value");
+    }
+    let _ = writeln!(
+        out,
+        "    pub async fn {}(&self{params}) -> anyhow::Result<Value> {{",
+        ident(&snake_case(name))
+    );
+    let optional_mut = if optional.is_empty() { "" } else { "mut " };
+    let _ = writeln!(out, "        let {optional_mut}args = json!({{");
+    for field in &required {
+        let json_name = field["name"].as_str().unwrap_or_default();
+        let field_name = ident(json_name);
+        if field_type(&types_of(field)).field == "Value" {
+            let _ = writeln!(
+                out,
+                "            \"{json_name}\": Into::<Value>::into({field_name}),"
+            );
+        } else {
+            let _ = writeln!(out, "            \"{json_name}\": {field_name},");
+        }
+    }
+    let _ = writeln!(out, "        }});");
+    if !optional.is_empty() {
+        let _ = writeln!(out, "        merge_optional(&mut args, optional);");
+    }
+    let _ = writeln!(
+        out,
+        "        self.bot.call_api(\"{name}\", args).await\n    }}\n"
+    );
+}
+
+fn generate(schema: &Value) -> anyhow::Result<String> {
+    let methods = schema["methods"]
+        .as_object()
+        .context("schema has no methods")?;
+    let types = schema["types"].as_object().context("schema has no types")?;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "// Generated by tg-codegen from {}. Do not edit by hand; regenerate instead.\n",
+        schema["version"]
+            .as_str()
+            .unwrap_or("an unknown Bot API version")
+    );
+    out.push_str("#![allow(clippy::too_many_arguments, clippy::doc_markdown)]\n\n");
+    out.push_str("use serde_json::{json, Value};\n\nuse crate::BotHandle;\n\n");
+    for (name, schema) in types {
+        generate_type(&mut out, name, schema);
+    }
+    out.push_str(
+        "/// Every Bot API method, with its required parameters as arguments. Returned by [BotHandle::api].\npub struct Api<'a> {\n    bot: &'a BotHandle,\n}\n\n",
+    );
+    out.push_str("impl BotHandle {\n    /// Generated wrappers for every Bot API method.\n    pub fn api(&self) -> Api<'_> {\n        Api { bot: self }\n    }\n}\n\n");
+    out.push_str("impl Api<'_> {\n");
+    for (name, schema) in methods {
+        generate_method(&mut out, name, schema);
+    }
+    out.push_str("}\n\n");
+    out.push_str("fn merge_optional(args: &mut Value, optional: Value) {\n    if let (Some(args), Value::Object(optional)) = (args.as_object_mut(), optional) {\n        args.extend(optional);\n    }\n}\n");
+    Ok(out)
+}
+
+fn run() -> anyhow::Result<String> {
+    let path = match std::env::args().nth(1) {
+        Some(path) if path != "-h" && path != "--help" => path,
+        _ => anyhow::bail!("{USAGE}"),
+    };
+    let schema = std::fs::read_to_string(&path).with_context(|| format!("cannot read {path}"))?;
+    let schema: Value =
+        serde_json::from_str(&schema).with_context(|| format!("cannot parse {path}"))?;
+    generate(&schema)
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(generated) => {
+            print!("{generated}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("tg-codegen: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{io::Read, path::Path, process::ExitCode};
+
+use anyhow::Context;
+use serde_json::Value;
+use telegram_bot::{
+    format::ParseMode,
+    outgoing::{InputFile, MediaKind},
+    source::NoUpdates,
+    OutgoingMessage, Response, TelegramBot,
+};
+
+const USAGE: &str = "usage: tgsend [--token TOKEN] --chat CHAT_ID [--photo FILE | --document FILE] [--html | --markdown] [--silent] [TEXT | -]
+
+Sends TEXT, or standard input if TEXT is -, to the chat. With --photo or --document, TEXT is the caption and FILE is a local path or an http(s) URL. The token defaults to $TELEGRAM_BOT_TOKEN.";
+
+#[derive(Default)]
+struct Args {
+    token: Option<String>,
+    chat_id: Option<i64>,
+    media: Option<(MediaKind, String)>,
+    parse_mode: Option<ParseMode>,
+    silent: bool,
+    text: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Args> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--token" => parsed.token = Some(value()?),
+            "--chat" => parsed.chat_id = Some(value()?.parse().context("invalid chat ID")?),
+            "--photo" => parsed.media = Some((MediaKind::Photo, value()?)),
+            "--document" => parsed.media = Some((MediaKind::Document, value()?)),
+            "--html" => parsed.parse_mode = Some(ParseMode::Html),
+            "--markdown" => parsed.parse_mode = Some(ParseMode::MarkdownV2),
+            "--silent" => parsed.silent = true,
+            "-h" | "--help" => anyhow::bail!("{USAGE}"),
+            flag if flag.starts_with("--") => anyhow::bail!("unknown option {flag}\n\n{USAGE}"),
+            _ if parsed.text.is_some() => anyhow::bail!("more than one TEXT given\n\n{USAGE}"),
+            _ => parsed.text = Some(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn input_file(source: &str) -> anyhow::Result<InputFile> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return Ok(InputFile::url(source));
+    }
+    let data = std::fs::read(source).with_context(|| format!("cannot read {source}"))?;
+    let file_name = Path::new(source)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("file");
+    Ok(InputFile::bytes(file_name, data))
+}
+
+fn build_message(args: Args, chat_id: i64) -> anyhow::Result<OutgoingMessage> {
+    let text = match args.text.as_deref() {
+        Some("-") => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("cannot read standard input")?;
+            Some(text)
+        }
+        text => text.map(str::to_owned),
+    };
+    let mut builder = Response::builder(chat_id);
+    match args.media {
+        Some((kind, source)) => {
+            builder = builder.media(kind, input_file(&source)?);
+            if let Some(caption) = &text {
+                builder = builder.caption(caption);
+            }
+        }
+        None => {
+            builder = builder.text(&text.with_context(|| format!("nothing to send\n\n{USAGE}"))?)
+        }
+    }
+    if let Some(parse_mode) = args.parse_mode {
+        builder = builder.parse_mode(parse_mode);
+    }
+    if args.silent {
+        builder = builder.silent();
+    }
+    Ok(builder.build())
+}
+
+async fn run() -> anyhow::Result<Value> {
+    let args = parse_args(std::env::args().skip(1))?;
+    let token = match &args.token {
+        Some(token) => token.clone(),
+        None => std::env::var("TELEGRAM_BOT_TOKEN")
+            .with_context(|| format!("no token given\n\n{USAGE}"))?,
+    };
+    let chat_id = args
+        .chat_id
+        .with_context(|| format!("no chat given\n\n{USAGE}"))?;
+    let msg = build_message(args, chat_id)?;
+    let bot = TelegramBot::builder(&token)
+        .update_source(NoUpdates)
+        .build(|_| async { anyhow::Ok(Vec::<Response>::new()) });
+    bot.send(msg).await
+}
+
+fn main() -> ExitCode {
+    match smol::block_on(run()) {
+        Ok(sent) => {
+            println!("{}", sent["message_id"]);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("tgsend: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
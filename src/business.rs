@@ -0,0 +1,114 @@
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::{outgoing::InputFile, BotHandle};
+
+impl BotHandle {
+    /// Marks a message in a chat of a connected business account as read.
+    pub async fn read_business_message(
+        &self,
+        business_connection_id: &str,
+        chat_id: i64,
+        message_id: i64,
+    ) -> anyhow::Result<()> {
+        self.business_call(
+            "readBusinessMessage",
+            business_connection_id,
+            json!({ "chat_id": chat_id, "message_id": message_id }),
+        )
+        .await
+    }
+
+    /// Deletes up to 100 messages on behalf of a connected business account.
+    pub async fn delete_business_messages(
+        &self,
+        business_connection_id: &str,
+        message_ids: &[i64],
+    ) -> anyhow::Result<()> {
+        self.business_call(
+            "deleteBusinessMessages",
+            business_connection_id,
+            json!({ "message_ids": message_ids }),
+        )
+        .await
+    }
+
+    /// Changes the name of a connected business account.
+    pub async fn set_business_account_name(
+        &self,
+        business_connection_id: &str,
+        first_name: &str,
+        last_name: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.business_call(
+            "setBusinessAccountName",
+            business_connection_id,
+            json!({ "first_name": first_name, "last_name": last_name }),
+        )
+        .await
+    }
+
+    /// Changes the bio of a connected business account.
+    pub async fn set_business_account_bio(
+        &self,
+        business_connection_id: &str,
+        bio: &str,
+    ) -> anyhow::Result<()> {
+        self.business_call(
+            "setBusinessAccountBio",
+            business_connection_id,
+            json!({ "bio": bio }),
+        )
+        .await
+    }
+
+    /// Changes the profile photo of a connected business account. A public photo is shown to users who cannot see the main one. Telegram only accepts new uploads here.
+    pub async fn set_business_account_photo(
+        &self,
+        business_connection_id: &str,
+        photo: &InputFile,
+        is_public: bool,
+    ) -> anyhow::Result<()> {
+        let mut uploads = vec![];
+        let args = json!({
+            "business_connection_id": business_connection_id,
+            "photo": {
+                "type": "static",
+                "photo": photo.reference("profile_photo", &mut uploads),
+            },
+            "is_public": is_public,
+        });
+        self.inner
+            .call_api_with_uploads("setBusinessAccountProfilePhoto", args, &uploads)
+            .await
+            .context("cannot call setBusinessAccountProfilePhoto")?;
+        Ok(())
+    }
+
+    /// Removes the main or public profile photo of a connected business account.
+    pub async fn remove_business_account_photo(
+        &self,
+        business_connection_id: &str,
+        is_public: bool,
+    ) -> anyhow::Result<()> {
+        self.business_call(
+            "removeBusinessAccountProfilePhoto",
+            business_connection_id,
+            json!({ "is_public": is_public }),
+        )
+        .await
+    }
+
+    async fn business_call(
+        &self,
+        method: &str,
+        business_connection_id: &str,
+        mut args: Value,
+    ) -> anyhow::Result<()> {
+        args["business_connection_id"] = json!(business_connection_id);
+        self.call_api(method, args)
+            .await
+            .with_context(|| format!("cannot call {method}"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::BotHandle;
+
+/// An answer to a callback query, shown to the user who pressed the button.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallbackAnswer {
+    text: Option<String>,
+    show_alert: bool,
+    url: Option<String>,
+    cache_time: Option<Duration>,
+}
+
+impl CallbackAnswer {
+    /// An empty answer, which only stops the button's loading animation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows a notification with the text at the top of the chat, up to 200 characters.
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_owned());
+        self
+    }
+
+    /// Shows the text in a dialog the user must dismiss, instead of a notification.
+    pub fn alert(mut self) -> Self {
+        self.show_alert = true;
+        self
+    }
+
+    /// Opens a URL, such as a game's URL or a `t.me/yourbot?start=...` deep link.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_owned());
+        self
+    }
+
+    /// Lets Telegram clients reuse the answer for the same button for a while.
+    pub fn cache_time(mut self, cache_time: Duration) -> Self {
+        self.cache_time = Some(cache_time);
+        self
+    }
+
+    fn to_args(&self, callback_query_id: &str) -> Value {
+        let mut args = json!({ "callback_query_id": callback_query_id });
+        if let Some(text) = &self.text {
+            args["text"] = json!(text);
+        }
+        if self.show_alert {
+            args["show_alert"] = json!(true);
+        }
+        if let Some(url) = &self.url {
+            args["url"] = json!(url);
+        }
+        if let Some(cache_time) = self.cache_time {
+            args["cache_time"] = json!(cache_time.as_secs());
+        }
+        args
+    }
+}
+
+/// A press of an inline keyboard button, given to the handler set with [crate::TelegramBotBuilder::on_callback_query].
+#[derive(Clone)]
+pub struct CallbackQuery {
+    /// The `callback_query` object of the update.
+    pub query: Value,
+    pub bot: BotHandle,
+}
+
+impl CallbackQuery {
+    /// The ID to answer the query with.
+    pub fn id(&self) -> &str {
+        self.query["id"].as_str().unwrap_or_default()
+    }
+
+    /// The `callback_data` of the button.
+    pub fn data(&self) -> Option<&str> {
+        self.query["data"].as_str()
+    }
+
+    /// The message the button is attached to, or Null if it is too old or was sent inline.
+    pub fn message(&self) -> &Value {
+        &self.query["message"]
+    }
+
+    /// The ID of the user who pressed the button.
+    pub fn user_id(&self) -> Option<i64> {
+        self.query["from"]["id"].as_i64()
+    }
+
+    /// Answers the query. Telegram clients show a loading animation on the button until it is answered.
+    pub async fn answer(&self, answer: CallbackAnswer) -> anyhow::Result<()> {
+        self.bot.answer_callback_query(self.id(), answer).await
+    }
+}
+
+impl BotHandle {
+    /// Answers a callback query by its ID.
+    pub async fn answer_callback_query(
+        &self,
+        callback_query_id: &str,
+        answer: CallbackAnswer,
+    ) -> anyhow::Result<()> {
+        self.call_api("answerCallbackQuery", answer.to_args(callback_query_id))
+            .await
+            .context("cannot answer callback query")?;
+        Ok(())
+    }
+}
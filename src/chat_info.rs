@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::{json, Map, Value};
+
+use crate::{unknown_fields, with_unknown_fields, BotHandle, TelegramBot};
+
+/// Everything getChat returns about a chat. Fields not modeled here are in `raw`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatFullInfo {
+    pub id: i64,
+    /// `private`, `group`, `supergroup`, or `channel`.
+    pub kind: String,
+    pub title: Option<String>,
+    pub username: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub description: Option<String>,
+    pub invite_link: Option<String>,
+    /// The latest pinned message, as raw JSON.
+    pub pinned_message: Option<Value>,
+    /// Default permissions of members in groups.
+    pub permissions: Option<ChatPermissions>,
+    /// How long members must wait between messages in supergroups.
+    pub slow_mode_delay: Option<Duration>,
+    /// The reactions allowed in the chat, or None if all emoji reactions are.
+    pub available_reactions: Option<Vec<ReactionType>>,
+    pub max_reaction_count: i64,
+    /// The discussion group of a channel, or the channel of a discussion group.
+    pub linked_chat_id: Option<i64>,
+    pub accent_color_id: Option<i64>,
+    pub background_custom_emoji_id: Option<String>,
+    pub profile_accent_color_id: Option<i64>,
+    pub profile_background_custom_emoji_id: Option<String>,
+    pub has_protected_content: bool,
+    pub is_forum: bool,
+    pub raw: Value,
+}
+
+impl ChatFullInfo {
+    /// Parses the result of getChat.
+    pub fn from_json(chat: &Value) -> Option<Self> {
+        let str_field = |name: &str| chat[name].as_str().map(|s| s.to_owned());
+        let flag = |name: &str| chat[name].as_bool() == Some(true);
+        Some(Self {
+            id: chat["id"].as_i64()?,
+            kind: str_field("type")?,
+            title: str_field("title"),
+            username: str_field("username"),
+            first_name: str_field("first_name"),
+            last_name: str_field("last_name"),
+            description: str_field("description"),
+            invite_link: str_field("invite_link"),
+            pinned_message: Some(chat["pinned_message"].clone()).filter(|msg| !msg.is_null()),
+            permissions: chat["permissions"]
+                .is_object()
+                .then(|| ChatPermissions::from_json(&chat["permissions"])),
+            slow_mode_delay: chat["slow_mode_delay"].as_u64().map(Duration::from_secs),
+            available_reactions: chat["available_reactions"].as_array().map(|reactions| {
+                reactions
+                    .iter()
+                    .filter_map(ReactionType::from_json)
+                    .collect()
+            }),
+            max_reaction_count: chat["max_reaction_count"].as_i64().unwrap_or_default(),
+            linked_chat_id: chat["linked_chat_id"].as_i64(),
+            accent_color_id: chat["accent_color_id"].as_i64(),
+            background_custom_emoji_id: str_field("background_custom_emoji_id"),
+            profile_accent_color_id: chat["profile_accent_color_id"].as_i64(),
+            profile_background_custom_emoji_id: str_field("profile_background_custom_emoji_id"),
+            has_protected_content: flag("has_protected_content"),
+            is_forum: flag("is_forum"),
+            raw: chat.clone(),
+        })
+    }
+}
+
+const KNOWN_PERMISSIONS: &[&str] = &[
+    "can_send_messages",
+    "can_send_audios",
+    "can_send_documents",
+    "can_send_photos",
+    "can_send_videos",
+    "can_send_video_notes",
+    "can_send_voice_notes",
+    "can_send_polls",
+    "can_send_other_messages",
+    "can_add_web_page_previews",
+    "can_change_info",
+    "can_invite_users",
+    "can_pin_messages",
+    "can_manage_topics",
+];
+
+/// What members of a group may do. Missing permissions are false.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChatPermissions {
+    pub can_send_messages: bool,
+    pub can_send_audios: bool,
+    pub can_send_documents: bool,
+    pub can_send_photos: bool,
+    pub can_send_videos: bool,
+    pub can_send_video_notes: bool,
+    pub can_send_voice_notes: bool,
+    pub can_send_polls: bool,
+    pub can_send_other_messages: bool,
+    pub can_add_web_page_previews: bool,
+    pub can_change_info: bool,
+    pub can_invite_users: bool,
+    pub can_pin_messages: bool,
+    pub can_manage_topics: bool,
+    /// Permissions this crate does not know about yet, which [ChatPermissions::to_json] sends back.
+    pub extra: Map<String, Value>,
+}
+
+impl ChatPermissions {
+    /// Parses permissions from their JSON representation.
+    pub fn from_json(permissions: &Value) -> Self {
+        let flag = |name: &str| permissions[name].as_bool() == Some(true);
+        Self {
+            can_send_messages: flag("can_send_messages"),
+            can_send_audios: flag("can_send_audios"),
+            can_send_documents: flag("can_send_documents"),
+            can_send_photos: flag("can_send_photos"),
+            can_send_videos: flag("can_send_videos"),
+            can_send_video_notes: flag("can_send_video_notes"),
+            can_send_voice_notes: flag("can_send_voice_notes"),
+            can_send_polls: flag("can_send_polls"),
+            can_send_other_messages: flag("can_send_other_messages"),
+            can_add_web_page_previews: flag("can_add_web_page_previews"),
+            can_change_info: flag("can_change_info"),
+            can_invite_users: flag("can_invite_users"),
+            can_pin_messages: flag("can_pin_messages"),
+            can_manage_topics: flag("can_manage_topics"),
+            extra: unknown_fields(permissions, KNOWN_PERMISSIONS),
+        }
+    }
+
+    /// Converts the permissions to their JSON representation, for restrictChatMember or setChatPermissions.
+    pub fn to_json(&self) -> Value {
+        let permissions = json!({
+            "can_send_messages": self.can_send_messages,
+            "can_send_audios": self.can_send_audios,
+            "can_send_documents": self.can_send_documents,
+            "can_send_photos": self.can_send_photos,
+            "can_send_videos": self.can_send_videos,
+            "can_send_video_notes": self.can_send_video_notes,
+            "can_send_voice_notes": self.can_send_voice_notes,
+            "can_send_polls": self.can_send_polls,
+            "can_send_other_messages": self.can_send_other_messages,
+            "can_add_web_page_previews": self.can_add_web_page_previews,
+            "can_change_info": self.can_change_info,
+            "can_invite_users": self.can_invite_users,
+            "can_pin_messages": self.can_pin_messages,
+            "can_manage_topics": self.can_manage_topics,
+        });
+        with_unknown_fields(permissions, &self.extra)
+    }
+}
+
+/// A kind of reaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReactionType {
+    Emoji(String),
+    CustomEmoji(String),
+    /// A reaction paid for with Telegram Stars.
+    Paid,
+}
+
+impl ReactionType {
+    /// Parses a reaction type from its JSON representation.
+    pub fn from_json(reaction: &Value) -> Option<Self> {
+        match reaction["type"].as_str()? {
+            "emoji" => Some(Self::Emoji(reaction["emoji"].as_str()?.to_owned())),
+            "custom_emoji" => Some(Self::CustomEmoji(
+                reaction["custom_emoji_id"].as_str()?.to_owned(),
+            )),
+            "paid" => Some(Self::Paid),
+            _ => None,
+        }
+    }
+}
+
+async fn get_chat(bot: &BotHandle, chat_id: i64) -> anyhow::Result<ChatFullInfo> {
+    let chat = bot
+        .call_api("getChat", json!({ "chat_id": chat_id }))
+        .await
+        .with_context(|| format!("cannot get chat {chat_id}"))?;
+    ChatFullInfo::from_json(&chat).context("getChat returned an invalid chat")
+}
+
+impl BotHandle {
+    /// Gets everything about a chat with getChat.
+    pub async fn get_chat(&self, chat_id: i64) -> anyhow::Result<ChatFullInfo> {
+        get_chat(self, chat_id).await
+    }
+}
+
+impl TelegramBot {
+    /// Gets everything about a chat with getChat.
+    pub async fn get_chat(&self, chat_id: i64) -> anyhow::Result<ChatFullInfo> {
+        get_chat(&self.handle(), chat_id).await
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::{BotHandle, Inner, TelegramBot};
+
+/// How long a resolved username stays cached, since usernames can change hands.
+const RESOLVE_TTL: Duration = Duration::from_secs(3600);
+
+/// A cache of usernames resolved to chat IDs.
+#[derive(Default)]
+pub(crate) struct ResolveCache {
+    resolved: Mutex<HashMap<String, (i64, Instant)>>,
+}
+
+/// A cache of chat members, keyed by chat and user ID.
+pub(crate) struct MemberCache {
+    ttl: Duration,
+    members: Mutex<HashMap<(i64, i64), (Value, Instant)>>,
+}
+
+impl MemberCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            members: Default::default(),
+        }
+    }
+
+    fn get(&self, chat_id: i64, user_id: i64) -> Option<Value> {
+        let members = self.members.lock().unwrap();
+        let (member, fetched_at) = members.get(&(chat_id, user_id))?;
+        (fetched_at.elapsed() < self.ttl).then(|| member.clone())
+    }
+
+    fn insert(&self, chat_id: i64, user_id: i64, member: Value) {
+        self.members
+            .lock()
+            .unwrap()
+            .insert((chat_id, user_id), (member, Instant::now()));
+    }
+
+    /// Replaces cached members with the new status in `chat_member` and `my_chat_member` updates.
+    pub fn observe_update(&self, update: &Value) {
+        for kind in ["chat_member", "my_chat_member"] {
+            let changed = &update[kind];
+            if let (Some(chat_id), Some(user_id)) = (
+                changed["chat"]["id"].as_i64(),
+                changed["new_chat_member"]["user"]["id"].as_i64(),
+            ) {
+                self.insert(chat_id, user_id, changed["new_chat_member"].clone());
+            }
+        }
+    }
+}
+
+impl Inner {
+    async fn get_chat_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Value> {
+        let cache = self.member_cache.as_ref();
+        if let Some(member) = cache.and_then(|cache| cache.get(chat_id, user_id)) {
+            return Ok(member);
+        }
+        let member = self
+            .call_api(
+                "getChatMember",
+                json!({ "chat_id": chat_id, "user_id": user_id }),
+            )
+            .await?;
+        if let Some(cache) = cache {
+            cache.insert(chat_id, user_id, member.clone());
+        }
+        Ok(member)
+    }
+
+    async fn is_admin(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool> {
+        let member = self.get_chat_member(chat_id, user_id).await?;
+        Ok(matches!(
+            member["status"].as_str(),
+            Some("creator" | "administrator")
+        ))
+    }
+}
+
+impl BotHandle {
+    /// Gets a member of a chat. See [TelegramBot::get_chat_member].
+    pub async fn get_chat_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Value> {
+        self.inner.get_chat_member(chat_id, user_id).await
+    }
+
+    /// Whether a user is the owner or an administrator of a chat. See [TelegramBot::is_admin].
+    pub async fn is_admin(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool> {
+        self.inner.is_admin(chat_id, user_id).await
+    }
+}
+
+impl TelegramBot {
+    /// Gets a member of a chat by calling getChatMember, going through the chat member cache if it is enabled.
+    pub async fn get_chat_member(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Value> {
+        self.inner.get_chat_member(chat_id, user_id).await
+    }
+
+    /// Whether a user is the owner or an administrator of a chat, going through the chat member cache if it is enabled.
+    pub async fn is_admin(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool> {
+        self.inner.is_admin(chat_id, user_id).await
+    }
+
+    /// Resolves a `@username` to a numeric chat ID by calling getChat, caching the result. Numeric IDs are returned as-is.
+    ///
+    /// Telegram only resolves usernames of public groups and channels this way, not of users.
+    pub async fn resolve_chat(&self, username: &str) -> anyhow::Result<i64> {
+        if let Ok(chat_id) = username.parse() {
+            return Ok(chat_id);
+        }
+        let key = username.trim_start_matches('@').to_lowercase();
+        if let Some((chat_id, resolved_at)) =
+            self.inner.resolve_cache.resolved.lock().unwrap().get(&key)
+        {
+            if resolved_at.elapsed() < RESOLVE_TTL {
+                return Ok(*chat_id);
+            }
+        }
+        let chat = self
+            .call_api("getChat", json!({ "chat_id": format!("@{key}") }))
+            .await
+            .with_context(|| format!("cannot resolve @{key}"))?;
+        let chat_id = chat["id"]
+            .as_i64()
+            .context("getChat returned a chat without an id")?;
+        self.inner
+            .resolve_cache
+            .resolved
+            .lock()
+            .unwrap()
+            .insert(key, (chat_id, Instant::now()));
+        Ok(chat_id)
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::{format::ParseMode, BotHandle};
+
+/// A checklist to send or edit into a message. Bots can only send checklists on behalf of connected business accounts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputChecklist {
+    pub title: String,
+    /// Tasks as their ID, unique within the checklist, and text.
+    pub tasks: Vec<(i64, String)>,
+    /// How the title and tasks are formatted. Plain text if None.
+    pub parse_mode: Option<ParseMode>,
+    pub others_can_add_tasks: bool,
+    pub others_can_mark_tasks_as_done: bool,
+}
+
+impl InputChecklist {
+    /// Creates a checklist with no tasks.
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_owned(),
+            tasks: vec![],
+            parse_mode: None,
+            others_can_add_tasks: false,
+            others_can_mark_tasks_as_done: false,
+        }
+    }
+
+    /// Adds a task, numbered after the previous one.
+    pub fn task(mut self, text: &str) -> Self {
+        let id = self.tasks.last().map_or(1, |(id, _)| id + 1);
+        self.tasks.push((id, text.to_owned()));
+        self
+    }
+
+    /// Lets other users add tasks.
+    pub fn others_can_add(mut self) -> Self {
+        self.others_can_add_tasks = true;
+        self
+    }
+
+    /// Lets other users mark tasks as done.
+    pub fn others_can_mark_done(mut self) -> Self {
+        self.others_can_mark_tasks_as_done = true;
+        self
+    }
+
+    /// The checklist as a `checklist` argument.
+    pub fn to_json(&self) -> Value {
+        let parse_mode = self.parse_mode.map(|mode| mode.as_str());
+        let mut checklist = json!({
+            "title": self.title,
+            "tasks": self
+                .tasks
+                .iter()
+                .map(|(id, text)| {
+                    let mut task = json!({"id": id, "text": text});
+                    if let Some(parse_mode) = parse_mode {
+                        task["parse_mode"] = json!(parse_mode);
+                    }
+                    task
+                })
+                .collect::<Vec<_>>(),
+            "others_can_add_tasks": self.others_can_add_tasks,
+            "others_can_mark_tasks_as_done": self.others_can_mark_tasks_as_done,
+        });
+        if let Some(parse_mode) = parse_mode {
+            checklist["parse_mode"] = json!(parse_mode);
+        }
+        checklist
+    }
+}
+
+/// A task of a received checklist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChecklistTask {
+    pub id: i64,
+    pub text: String,
+    /// The user who marked the task as done, if it is done.
+    pub completed_by_user_id: Option<i64>,
+    /// When the task was marked as done, as a Unix timestamp.
+    pub completion_date: Option<i64>,
+}
+
+impl ChecklistTask {
+    /// Parses a task from its JSON representation.
+    pub fn from_json(task: &Value) -> Option<Self> {
+        Some(Self {
+            id: task["id"].as_i64()?,
+            text: task["text"].as_str()?.to_owned(),
+            completed_by_user_id: task["completed_by_user"]["id"].as_i64(),
+            completion_date: task["completion_date"].as_i64().filter(|date| *date != 0),
+        })
+    }
+}
+
+/// A received checklist, from the `checklist` field of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checklist {
+    pub title: String,
+    pub tasks: Vec<ChecklistTask>,
+    pub others_can_add_tasks: bool,
+    pub others_can_mark_tasks_as_done: bool,
+}
+
+impl Checklist {
+    /// Parses a checklist from its JSON representation.
+    pub fn from_json(checklist: &Value) -> Option<Self> {
+        Some(Self {
+            title: checklist["title"].as_str()?.to_owned(),
+            tasks: parse_tasks(&checklist["tasks"]),
+            others_can_add_tasks: checklist["others_can_add_tasks"].as_bool() == Some(true),
+            others_can_mark_tasks_as_done: checklist["others_can_mark_tasks_as_done"].as_bool()
+                == Some(true),
+        })
+    }
+}
+
+/// A change to a checklist, from a service message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChecklistEvent {
+    /// Tasks were marked as done or not done, from `checklist_tasks_done`.
+    TasksDone {
+        /// The checklist's message, if it still exists.
+        checklist_message_id: Option<i64>,
+        marked_as_done: Vec<i64>,
+        marked_as_not_done: Vec<i64>,
+    },
+    /// Tasks were added, from `checklist_tasks_added`.
+    TasksAdded {
+        checklist_message_id: Option<i64>,
+        tasks: Vec<ChecklistTask>,
+    },
+}
+
+impl ChecklistEvent {
+    /// Parses the checklist event in a message, if it is one.
+    pub fn from_message(message: &Value) -> Option<Self> {
+        let ids = |ids: &Value| -> Vec<i64> {
+            ids.as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_i64)
+                .collect()
+        };
+        let done = &message["checklist_tasks_done"];
+        if done.is_object() {
+            return Some(Self::TasksDone {
+                checklist_message_id: done["checklist_message"]["message_id"].as_i64(),
+                marked_as_done: ids(&done["marked_as_done_task_ids"]),
+                marked_as_not_done: ids(&done["marked_as_not_done_task_ids"]),
+            });
+        }
+        let added = &message["checklist_tasks_added"];
+        if added.is_object() {
+            return Some(Self::TasksAdded {
+                checklist_message_id: added["checklist_message"]["message_id"].as_i64(),
+                tasks: parse_tasks(&added["tasks"]),
+            });
+        }
+        None
+    }
+}
+
+fn parse_tasks(tasks: &Value) -> Vec<ChecklistTask> {
+    tasks
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(ChecklistTask::from_json)
+        .collect()
+}
+
+impl BotHandle {
+    /// Sends a checklist on behalf of a connected business account, returning the sent message.
+    pub async fn send_checklist(
+        &self,
+        business_connection_id: &str,
+        chat_id: i64,
+        checklist: &InputChecklist,
+    ) -> anyhow::Result<Value> {
+        self.call_api(
+            "sendChecklist",
+            json!({
+                "business_connection_id": business_connection_id,
+                "chat_id": chat_id,
+                "checklist": checklist.to_json(),
+            }),
+        )
+        .await
+        .context("cannot send checklist")
+    }
+
+    /// Replaces a checklist sent on behalf of a connected business account, returning the edited message.
+    pub async fn edit_checklist(
+        &self,
+        business_connection_id: &str,
+        chat_id: i64,
+        message_id: i64,
+        checklist: &InputChecklist,
+    ) -> anyhow::Result<Value> {
+        self.call_api(
+            "editMessageChecklist",
+            json!({
+                "business_connection_id": business_connection_id,
+                "chat_id": chat_id,
+                "message_id": message_id,
+                "checklist": checklist.to_json(),
+            }),
+        )
+        .await
+        .context("cannot edit checklist")
+    }
+}
@@ -0,0 +1,627 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+use smol::future::Boxed;
+
+use crate::{
+    store::{MemoryStore, Store},
+    Response,
+};
+
+/// A bot command parsed from a message, like `/ban@mybot 123 "spam links"`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    /// The command name, without the leading slash.
+    pub name: String,
+    /// The bot username the command was addressed to, if any.
+    pub mention: Option<String>,
+    /// Everything after the command, unparsed.
+    pub args: String,
+}
+
+impl Command {
+    /// Parses a command from message text, or returns None if the text is not a command.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.strip_prefix('/')?;
+        let (head, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let (name, mention) = match head.split_once('@') {
+            Some((name, mention)) => (name, Some(mention.to_owned())),
+            None => (head, None),
+        };
+        if name.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: name.to_owned(),
+            mention,
+            args: args.trim().to_owned(),
+        })
+    }
+
+    /// Parses the command in the message of an update, if there is one.
+    pub fn from_update(update: &Value) -> Option<Self> {
+        Self::parse(update["message"]["text"].as_str()?)
+    }
+
+    /// Splits the arguments on whitespace, keeping double-quoted strings together.
+    pub fn split_args(&self) -> Result<Vec<String>, ArgsError> {
+        split_args(&self.args)
+    }
+
+    /// Parses the arguments into typed values, like `cmd.parse_args::<(u64, String)>()`.
+    pub fn parse_args<T: FromArgs>(&self) -> Result<T, ArgsError> {
+        T::from_args(self.split_args()?)
+    }
+}
+
+/// An error parsing command arguments. Its message is meant to be shown to the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArgsError(pub String);
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ArgsError {}
+
+/// Splits command arguments on whitespace, keeping double-quoted strings together. Backslashes escape quotes inside quoted strings.
+pub fn split_args(args: &str) -> Result<Vec<String>, ArgsError> {
+    let mut out = vec![];
+    let mut chars = args.chars();
+    let mut current = String::new();
+    let mut in_arg = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => current.push(escaped),
+                            None => return Err(ArgsError("unterminated quoted argument".into())),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(ArgsError("unterminated quoted argument".into())),
+                    }
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_arg {
+                    out.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        out.push(current);
+    }
+    Ok(out)
+}
+
+/// Types that can be parsed from a list of command arguments.
+pub trait FromArgs: Sized {
+    fn from_args(args: Vec<String>) -> Result<Self, ArgsError>;
+}
+
+impl FromArgs for Vec<String> {
+    fn from_args(args: Vec<String>) -> Result<Self, ArgsError> {
+        Ok(args)
+    }
+}
+
+fn parse_arg<T: FromStr>(idx: usize, arg: String) -> Result<T, ArgsError> {
+    arg.parse().map_err(|_| {
+        let type_name = std::any::type_name::<T>();
+        let type_name = type_name.rsplit("::").next().unwrap_or(type_name);
+        ArgsError(format!(
+            "argument {} ({arg:?}) is not a valid {type_name}",
+            idx + 1
+        ))
+    })
+}
+
+macro_rules! impl_from_args {
+    ($count:expr; $($ty:ident),+) => {
+        impl<$($ty: FromStr),+> FromArgs for ($($ty,)+) {
+            fn from_args(args: Vec<String>) -> Result<Self, ArgsError> {
+                if args.len() != $count {
+                    return Err(ArgsError(format!(
+                        "expected {} argument{}, got {}",
+                        $count,
+                        if $count == 1 { "" } else { "s" },
+                        args.len()
+                    )));
+                }
+                let mut args = args.into_iter().enumerate();
+                Ok(($({
+                    let (idx, arg) = args.next().unwrap();
+                    parse_arg::<$ty>(idx, arg)?
+                },)+))
+            }
+        }
+    };
+}
+
+impl_from_args!(1; A);
+impl_from_args!(2; A, B);
+impl_from_args!(3; A, B, C);
+impl_from_args!(4; A, B, C, D);
+impl_from_args!(5; A, B, C, D, E);
+impl_from_args!(6; A, B, C, D, E, F);
+
+type CommandHandler =
+    Box<dyn Fn(Command, Value) -> Boxed<anyhow::Result<Vec<Response>>> + Send + Sync>;
+type FallbackHandler = Box<dyn Fn(Value) -> Boxed<anyhow::Result<Vec<Response>>> + Send + Sync>;
+
+/// Which chats a command is offered in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommandScope {
+    #[default]
+    All,
+    PrivateChats,
+    GroupChats,
+}
+
+impl CommandScope {
+    fn includes(self, chat_type: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::PrivateChats => chat_type == "private",
+            Self::GroupChats => chat_type == "group" || chat_type == "supergroup",
+        }
+    }
+
+    fn to_json(self) -> Value {
+        match self {
+            Self::All => json!({"type": "default"}),
+            Self::PrivateChats => json!({"type": "all_private_chats"}),
+            Self::GroupChats => json!({"type": "all_group_chats"}),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CommandHelp {
+    description: String,
+    category: Option<String>,
+    scope: CommandScope,
+}
+
+/// Routes updates to handlers by command name.
+///
+/// If a command handler fails with an [ArgsError], the error is sent back to the user as a reply instead of being treated as a handler failure.
+///
+/// If any command has a description and there is no `help` command, `/help` is answered with a list of the described commands available in the chat.
+#[derive(Default)]
+pub struct CommandRouter {
+    bot_username: Option<String>,
+    commands: HashMap<String, CommandHandler>,
+    fallback: Option<FallbackHandler>,
+    help: Vec<(String, CommandHelp)>,
+    cooldowns: HashMap<String, Duration>,
+    store: Option<Arc<dyn Store>>,
+    memory_store: MemoryStore,
+}
+
+impl CommandRouter {
+    /// Creates an empty router.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the bot's username, so that commands addressed to other bots (`/start@otherbot`) are ignored.
+    pub fn bot_username(mut self, username: &str) -> Self {
+        self.bot_username = Some(username.trim_start_matches('@').to_owned());
+        self
+    }
+
+    /// Registers a handler for the command with the given name, without the leading slash.
+    pub fn command<
+        Fun: Fn(Command, Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<Response>>> + Send + 'static,
+    >(
+        mut self,
+        name: &str,
+        handler: Fun,
+    ) -> Self {
+        self.commands.insert(
+            name.to_owned(),
+            Box::new(move |cmd, update| Box::pin(handler(cmd, update))),
+        );
+        self
+    }
+
+    /// Describes a command, listing it in `/help` and [CommandRouter::bot_commands].
+    pub fn describe(mut self, name: &str, description: &str) -> Self {
+        self.help_entry(name).description = description.to_owned();
+        self
+    }
+
+    /// Puts a described command under a heading in `/help`.
+    pub fn category(mut self, name: &str, category: &str) -> Self {
+        self.help_entry(name).category = Some(category.to_owned());
+        self
+    }
+
+    /// Limits which chats a described command is listed in. Defaults to all chats.
+    pub fn scope(mut self, name: &str, scope: CommandScope) -> Self {
+        self.help_entry(name).scope = scope;
+        self
+    }
+
+    /// Lets each user run a command at most once per the given time, answering early repeats with how long to wait.
+    pub fn cooldown(mut self, name: &str, cooldown: Duration) -> Self {
+        self.cooldowns.insert(name.to_owned(), cooldown);
+        self
+    }
+
+    /// Sets where to remember when users last ran commands with a cooldown, so cooldowns survive restarts. Defaults to the bot's store with [crate::TelegramBotBuilder::build_with_router], and otherwise to a [MemoryStore].
+    pub fn store(mut self, store: impl Store + 'static) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    pub(crate) fn store_if_unset(&mut self, store: Arc<dyn Store>) {
+        self.store.get_or_insert(store);
+    }
+
+    fn cooldown_store(&self) -> &dyn Store {
+        self.store.as_deref().unwrap_or(&self.memory_store)
+    }
+
+    // How many seconds, rounded up, the user must still wait before running the command again.
+    fn cooldown_left(&self, name: &str, user_id: i64) -> anyhow::Result<Option<u64>> {
+        let Some(cooldown) = self.cooldowns.get(name) else {
+            return Ok(None);
+        };
+        let key = format!("cooldown/{name}/{user_id}");
+        let now = unix_millis()?;
+        if let Some(last) = self
+            .cooldown_store()
+            .get(&key)?
+            .and_then(|last| last.as_u64())
+        {
+            let ready_at = last.saturating_add(cooldown.as_millis() as u64);
+            if now < ready_at {
+                return Ok(Some((ready_at - now).div_ceil(1000)));
+            }
+        }
+        Ok(None)
+    }
+
+    // Records that the user ran a command, starting its cooldown.
+    fn record_use(&self, name: &str, user_id: i64) -> anyhow::Result<()> {
+        if !self.cooldowns.contains_key(name) {
+            return Ok(());
+        }
+        self.cooldown_store()
+            .set(&format!("cooldown/{name}/{user_id}"), json!(unix_millis()?))
+    }
+
+    fn help_entry(&mut self, name: &str) -> &mut CommandHelp {
+        let idx = match self.help.iter().position(|(n, _)| n == name) {
+            Some(idx) => idx,
+            None => {
+                self.help.push((name.to_owned(), CommandHelp::default()));
+                self.help.len() - 1
+            }
+        };
+        &mut self.help[idx].1
+    }
+
+    /// The help text listing the described commands available in a chat of the given type (`private`, `group`, and so on), grouped by category.
+    pub fn help_text(&self, chat_type: &str) -> String {
+        let available: Vec<&(String, CommandHelp)> = self
+            .help
+            .iter()
+            .filter(|(_, help)| help.scope.includes(chat_type))
+            .collect();
+        let mut categories: Vec<Option<&str>> = vec![None];
+        for (_, help) in &available {
+            if !categories.contains(&help.category.as_deref()) {
+                categories.push(help.category.as_deref());
+            }
+        }
+        let mut sections = vec![];
+        for category in categories {
+            let mut lines: Vec<String> = available
+                .iter()
+                .filter(|(_, help)| help.category.as_deref() == category)
+                .map(|(name, help)| format!("/{name} - {}", help.description))
+                .collect();
+            if lines.is_empty() {
+                continue;
+            }
+            if let Some(category) = category {
+                lines.insert(0, format!("{category}:"));
+            }
+            sections.push(lines.join("\n"));
+        }
+        sections.join("\n\n")
+    }
+
+    /// Arguments for setMyCommands, one call per scope, so Telegram's command menu matches the described commands.
+    pub fn bot_commands(&self) -> Vec<Value> {
+        let mut scopes = vec![CommandScope::All];
+        for (_, help) in &self.help {
+            if !scopes.contains(&help.scope) {
+                scopes.push(help.scope);
+            }
+        }
+        scopes
+            .into_iter()
+            .map(|scope| {
+                // Telegram only shows the most specific scope, so narrower lists repeat the commands for all chats
+                let commands: Vec<Value> = self
+                    .help
+                    .iter()
+                    .filter(|(_, help)| help.scope == scope || help.scope == CommandScope::All)
+                    .map(|(name, help)| json!({"command": name, "description": help.description}))
+                    .collect();
+                json!({"commands": commands, "scope": scope.to_json()})
+            })
+            .collect()
+    }
+
+    /// Registers a handler for all updates that are not a registered command.
+    pub fn fallback<
+        Fun: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<Response>>> + Send + 'static,
+    >(
+        mut self,
+        handler: Fun,
+    ) -> Self {
+        self.fallback = Some(Box::new(move |update| Box::pin(handler(update))));
+        self
+    }
+
+    /// Handles an update, dispatching it to the matching handler.
+    pub async fn handle(&self, update: Value) -> anyhow::Result<Vec<Response>> {
+        let cmd =
+            Command::from_update(&update).filter(|cmd| match (&cmd.mention, &self.bot_username) {
+                (Some(mention), Some(username)) => mention.eq_ignore_ascii_case(username),
+                _ => true,
+            });
+        let chat_id = update["message"]["chat"]["id"].as_i64();
+        let message_id = update["message"]["message_id"].as_i64();
+        let reply = |text: String| {
+            chat_id
+                .map(|chat_id| Response {
+                    text,
+                    chat_id,
+                    reply_to_message_id: message_id,
+                })
+                .into_iter()
+                .collect()
+        };
+        if cmd.as_ref().is_some_and(|cmd| cmd.name == "help")
+            && !self.commands.contains_key("help")
+            && !self.help.is_empty()
+        {
+            let chat_type = update["message"]["chat"]["type"]
+                .as_str()
+                .unwrap_or("private");
+            return Ok(reply(self.help_text(chat_type)));
+        }
+        match cmd.and_then(|cmd| Some((self.commands.get(&cmd.name)?, cmd))) {
+            Some((handler, cmd)) => {
+                let user_id = update["message"]["from"]["id"].as_i64();
+                if let Some(user_id) = user_id {
+                    if let Some(wait) = self.cooldown_left(&cmd.name, user_id)? {
+                        return Ok(reply(format!(
+                            "Please wait {wait}s before using /{} again.",
+                            cmd.name
+                        )));
+                    }
+                }
+                let name = cmd.name.clone();
+                match handler(cmd, update).await {
+                    Err(err) if err.is::<ArgsError>() => Ok(reply(err.to_string())),
+                    Ok(responses) => {
+                        // only a successful run uses up the cooldown
+                        // the command already ran, so its responses are sent even if the cooldown cannot be
+                        if let Some(user_id) = user_id {
+                            if let Err(err) = self.record_use(&name, user_id) {
+                                tracing::warn!("cannot start cooldown of /{name}: {:?}", err);
+                            }
+                        }
+                        Ok(responses)
+                    }
+                    res => res,
+                }
+            }
+            None => match &self.fallback {
+                Some(fallback) => fallback(update).await,
+                None => Ok(vec![]),
+            },
+        }
+    }
+
+    /// Turns the router into a message handler for a TelegramBot.
+    pub fn into_handler(
+        self,
+    ) -> impl FnMut(Value) -> Boxed<anyhow::Result<Vec<Response>>> + Send + 'static {
+        let this = Arc::new(self);
+        move |update| {
+            let this = this.clone();
+            Box::pin(async move { this.handle(update).await })
+        }
+    }
+}
+
+fn unix_millis() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> Value {
+        json!({"message": {
+            "message_id": 1,
+            "chat": {"id": 7, "type": "private"},
+            "from": {"id": 7},
+            "text": text,
+        }})
+    }
+
+    fn ping_router() -> CommandRouter {
+        CommandRouter::new().command("ping", |_, _| async {
+            Ok(vec![Response {
+                text: "pong".into(),
+                chat_id: 7,
+                reply_to_message_id: None,
+            }])
+        })
+    }
+
+    async fn reply_to(router: &CommandRouter, text: &str) -> String {
+        let responses = router.handle(message(text)).await.unwrap();
+        responses[0].text.clone()
+    }
+
+    // A store that cannot be written to.
+    struct ReadOnlyStore;
+
+    impl Store for ReadOnlyStore {
+        fn get(&self, _key: &str) -> anyhow::Result<Option<Value>> {
+            Ok(None)
+        }
+
+        fn set(&self, _key: &str, _value: Value) -> anyhow::Result<()> {
+            anyhow::bail!("read-only")
+        }
+
+        fn remove(&self, _key: &str) -> anyhow::Result<()> {
+            anyhow::bail!("read-only")
+        }
+
+        fn keys(&self, _prefix: &str) -> anyhow::Result<Vec<String>> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn cooldowns_expire() {
+        smol::block_on(async {
+            let router = ping_router().cooldown("ping", Duration::from_millis(200));
+            assert_eq!(reply_to(&router, "/ping").await, "pong");
+            assert_eq!(
+                reply_to(&router, "/ping").await,
+                "Please wait 1s before using /ping again."
+            );
+            smol::Timer::after(Duration::from_millis(250)).await;
+            assert_eq!(reply_to(&router, "/ping").await, "pong");
+        });
+    }
+
+    #[test]
+    fn responses_are_kept_when_the_cooldown_cannot_be_stored() {
+        smol::block_on(async {
+            let router = ping_router()
+                .cooldown("ping", Duration::from_secs(60))
+                .store(ReadOnlyStore);
+            assert_eq!(reply_to(&router, "/ping").await, "pong");
+        });
+    }
+
+    #[test]
+    fn splits_quoted_and_escaped_args() {
+        assert_eq!(
+            split_args(r#"  ban  "spam links" x"y z"  "#).unwrap(),
+            ["ban", "spam links", "xy z"]
+        );
+        assert_eq!(
+            split_args(r#""say \"hi\"" "a\\b" """#).unwrap(),
+            [r#"say "hi""#, r"a\b", ""]
+        );
+        assert!(split_args("").unwrap().is_empty());
+        assert_eq!(
+            split_args(r#"ok "never closed"#),
+            Err(ArgsError("unterminated quoted argument".into()))
+        );
+        assert!(split_args(r#""ends with \"#).is_err());
+    }
+
+    #[test]
+    fn parses_typed_args() {
+        let cmd = Command::parse(r#"/ban@bot 123 "spam links""#).unwrap();
+        assert_eq!(cmd.mention.as_deref(), Some("bot"));
+        assert_eq!(
+            cmd.parse_args::<(u64, String)>().unwrap(),
+            (123, "spam links".to_owned())
+        );
+        assert_eq!(
+            cmd.parse_args::<Vec<String>>().unwrap(),
+            ["123", "spam links"]
+        );
+    }
+
+    #[test]
+    fn reports_wrong_arg_counts() {
+        let cmd = Command::parse("/ban 123").unwrap();
+        assert_eq!(
+            cmd.parse_args::<(u64, String)>().unwrap_err().to_string(),
+            "expected 2 arguments, got 1"
+        );
+        let cmd = Command::parse("/ban 1 2").unwrap();
+        assert_eq!(
+            cmd.parse_args::<(u64,)>().unwrap_err().to_string(),
+            "expected 1 argument, got 2"
+        );
+    }
+
+    #[test]
+    fn reports_unparseable_args_by_type() {
+        let cmd = Command::parse("/set x 1.5 maybe").unwrap();
+        assert_eq!(
+            cmd.parse_args::<(i64, f64, bool)>().unwrap_err(),
+            ArgsError(r#"argument 1 ("x") is not a valid i64"#.into())
+        );
+        let cmd = Command::parse("/set 1 y maybe").unwrap();
+        assert_eq!(
+            cmd.parse_args::<(i64, f64, bool)>().unwrap_err(),
+            ArgsError(r#"argument 2 ("y") is not a valid f64"#.into())
+        );
+        let cmd = Command::parse("/set 1 2 maybe").unwrap();
+        assert_eq!(
+            cmd.parse_args::<(i64, f64, bool)>().unwrap_err(),
+            ArgsError(r#"argument 3 ("maybe") is not a valid bool"#.into())
+        );
+    }
+
+    #[test]
+    fn answers_arg_errors_to_the_user() {
+        smol::block_on(async {
+            let router = CommandRouter::new().command("double", |cmd, _| async move {
+                let (n,) = cmd.parse_args::<(i64,)>()?;
+                Ok(vec![Response {
+                    text: (n * 2).to_string(),
+                    chat_id: 7,
+                    reply_to_message_id: None,
+                }])
+            });
+            assert_eq!(reply_to(&router, "/double 21").await, "42");
+            assert_eq!(
+                reply_to(&router, "/double many").await,
+                r#"argument 1 ("many") is not a valid i64"#
+            );
+        });
+    }
+}
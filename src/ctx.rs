@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::{
+    format::ParseMode,
+    reply::{ExternalReply, TextQuote},
+    Inner, OutgoingMessage, TelegramBot,
+};
+
+/// A cloneable handle for calling the API of a running bot, such as from inside its handler.
+#[derive(Clone)]
+pub struct BotHandle {
+    pub(crate) inner: Arc<Inner>,
+}
+
+impl BotHandle {
+    /// Calls an arbitrary Telegram bot API method, returning its result.
+    pub async fn call_api(&self, method: &str, args: Value) -> anyhow::Result<Value> {
+        self.inner.call_api(method, args).await
+    }
+
+    /// Sends a message of any kind, returning the sent message.
+    pub async fn send(&self, msg: impl Into<OutgoingMessage>) -> anyhow::Result<Value> {
+        self.inner.send(msg.into()).await
+    }
+}
+
+impl TelegramBot {
+    /// Returns a cloneable handle for calling the API of this bot.
+    pub fn handle(&self) -> BotHandle {
+        BotHandle {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// What a context handler is given for each message: the update, a handle to the bot, and helpers for replying.
+#[derive(Clone)]
+pub struct Ctx {
+    pub update: Value,
+    pub bot: BotHandle,
+}
+
+impl Ctx {
+    /// The message the update is about, whether sent to a chat or posted in a channel, and whether new or edited.
+    pub fn message(&self) -> &Value {
+        update_message(&self.update)
+    }
+
+    /// The text of the message, if any.
+    pub fn text(&self) -> Option<&str> {
+        self.message()["text"].as_str()
+    }
+
+    /// The ID of the chat the message was sent in.
+    pub fn chat_id(&self) -> Option<i64> {
+        self.message()["chat"]["id"].as_i64()
+    }
+
+    /// The ID of the message.
+    pub fn message_id(&self) -> Option<i64> {
+        self.message()["message_id"].as_i64()
+    }
+
+    /// The ID of the user who sent the message. For messages sent on behalf of a chat, this is a placeholder user; see [Ctx::sender_chat_id].
+    pub fn user_id(&self) -> Option<i64> {
+        self.message()["from"]["id"].as_i64()
+    }
+
+    /// The ID of the chat the message was sent on behalf of, such as a channel or an anonymous group admin.
+    pub fn sender_chat_id(&self) -> Option<i64> {
+        self.message()["sender_chat"]["id"].as_i64()
+    }
+
+    /// Whether the message is a channel post automatically forwarded into its linked discussion group, rather than something a user wrote.
+    pub fn is_automatic_forward(&self) -> bool {
+        self.message()["is_automatic_forward"]
+            .as_bool()
+            .unwrap_or(false)
+    }
+
+    /// The message replied to, when it is in another chat or forum topic.
+    pub fn external_reply(&self) -> Option<ExternalReply> {
+        ExternalReply::from_json(&self.message()["external_reply"])
+    }
+
+    /// The part of the replied-to message that the reply quotes, if any.
+    pub fn quote(&self) -> Option<TextQuote> {
+        TextQuote::from_json(&self.message()["quote"])
+    }
+
+    /// Whether the message's media is hidden behind a spoiler.
+    pub fn has_media_spoiler(&self) -> bool {
+        self.message()["has_media_spoiler"]
+            .as_bool()
+            .unwrap_or(false)
+    }
+
+    /// The ID of the animated effect the message was sent with, if any.
+    pub fn effect_id(&self) -> Option<&str> {
+        self.message()["effect_id"].as_str()
+    }
+
+    /// Sends a plain text reply to the message, returning the sent message.
+    pub async fn reply(&self, text: &str) -> anyhow::Result<Value> {
+        self.send_reply(text, None).await
+    }
+
+    /// Sends a reply to the message formatted as HTML, returning the sent message.
+    pub async fn reply_html(&self, html: &str) -> anyhow::Result<Value> {
+        self.send_reply(html, Some(ParseMode::Html)).await
+    }
+
+    /// Sends a message of any kind, returning the sent message.
+    pub async fn send(&self, msg: impl Into<OutgoingMessage>) -> anyhow::Result<Value> {
+        self.bot.send(msg).await
+    }
+
+    /// Reacts to the message with an emoji, replacing any previous reaction of the bot.
+    pub async fn react(&self, emoji: &str) -> anyhow::Result<()> {
+        self.bot
+            .call_api(
+                "setMessageReaction",
+                json!({
+                    "chat_id": self.chat_id().context("message without a chat id")?,
+                    "message_id": self.message_id().context("message without a message id")?,
+                    "reaction": [{"type": "emoji", "emoji": emoji}],
+                }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn send_reply(&self, text: &str, parse_mode: Option<ParseMode>) -> anyhow::Result<Value> {
+        let mut msg =
+            OutgoingMessage::text(self.chat_id().context("message without a chat id")?, text);
+        msg.options.reply_to_message_id = self.message_id();
+        msg.options.parse_mode = parse_mode;
+        self.bot.send(msg).await
+    }
+}
+
+// The message an update is about, or null if it is about something else.
+pub(crate) fn update_message(update: &Value) -> &Value {
+    [
+        "message",
+        "channel_post",
+        "edited_message",
+        "edited_channel_post",
+    ]
+    .into_iter()
+    .map(|kind| &update[kind])
+    .find(|message| message.is_object())
+    .unwrap_or(&Value::Null)
+}
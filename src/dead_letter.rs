@@ -0,0 +1,108 @@
+use serde_json::{json, Value};
+use smol::channel::{Receiver, Sender};
+
+use crate::{ephemeral::unix_now, sha256::sha256_hex, Inner, TelegramBot};
+
+/// An update the bot gave up on after its handler kept failing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeadLetter {
+    pub update: Value,
+    /// The last error the handler failed with.
+    pub error: String,
+    pub attempts: u32,
+    /// When the bot gave up, as a Unix timestamp.
+    pub failed_at: u64,
+}
+
+impl DeadLetter {
+    fn from_json(entry: &Value) -> Option<Self> {
+        Some(Self {
+            update: entry["update"].clone(),
+            error: entry["error"].as_str()?.to_owned(),
+            attempts: entry["attempts"].as_u64()? as u32,
+            failed_at: entry["failed_at"].as_u64()?,
+        })
+    }
+}
+
+// Updates taken out of the dead-letter store to be handled again, which the update loop takes alongside fetched ones.
+pub(crate) struct RedriveQueue {
+    pub send: Sender<Value>,
+    pub recv: Receiver<Value>,
+}
+
+impl Default for RedriveQueue {
+    fn default() -> Self {
+        let (send, recv) = smol::channel::unbounded();
+        Self { send, recv }
+    }
+}
+
+impl Inner {
+    // Stores an update that failed every attempt.
+    pub(crate) fn dead_letter(
+        &self,
+        update: &Value,
+        error: &str,
+        attempts: u32,
+    ) -> anyhow::Result<()> {
+        let key = dead_letter_key(update);
+        self.store.set(
+            &key,
+            json!({
+                "update": update,
+                "error": error,
+                "attempts": attempts,
+                "failed_at": unix_now(),
+            }),
+        )?;
+        tracing::warn!("moved update to the dead-letter store as {key}");
+        Ok(())
+    }
+}
+
+impl TelegramBot {
+    /// Lists the updates in the dead-letter store, oldest first. Updates only get there with [crate::TelegramBotBuilder::dead_letters].
+    pub fn dead_letters(&self) -> anyhow::Result<Vec<DeadLetter>> {
+        let mut letters = vec![];
+        for key in self.inner.store.keys("dead_letter/")? {
+            if let Some(letter) = self
+                .inner
+                .store
+                .get(&key)?
+                .as_ref()
+                .and_then(DeadLetter::from_json)
+            {
+                letters.push(letter);
+            }
+        }
+        letters.sort_by_key(|letter| letter.failed_at);
+        Ok(letters)
+    }
+
+    /// Takes every update out of the dead-letter store and queues it to be handled again, such as after fixing the bug that made it fail. Returns how many were queued. Updates that fail again go back into the store.
+    pub fn redrive_dead_letters(&self) -> anyhow::Result<usize> {
+        let letters = self.dead_letters()?;
+        for letter in &letters {
+            self.inner.store.remove(&dead_letter_key(&letter.update))?;
+            if let Some(update_id) = letter.update["update_id"].as_i64() {
+                self.inner.forget_processed(update_id)?;
+            }
+            let _ = self.inner.redrive.send.try_send(letter.update.clone());
+        }
+        Ok(letters.len())
+    }
+
+    /// Deletes an update from the dead-letter store without handling it.
+    pub fn discard_dead_letter(&self, letter: &DeadLetter) -> anyhow::Result<()> {
+        self.inner.store.remove(&dead_letter_key(&letter.update))
+    }
+}
+
+fn dead_letter_key(update: &Value) -> String {
+    match update["update_id"].as_i64() {
+        Some(update_id) => format!("dead_letter/{update_id}"),
+        // updates from custom sources may lack an ID
+        None => format!("dead_letter/{}", sha256_hex(update.to_string().as_bytes())),
+    }
+}
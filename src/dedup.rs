@@ -0,0 +1,52 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::{outgoing::ApiRequest, sha256::sha256_hex};
+
+/// Recently sent messages, so that identical ones to the same chat can be suppressed.
+pub(crate) struct DedupWindow {
+    window: Duration,
+    recent: Mutex<HashMap<String, (Value, Instant)>>,
+}
+
+impl DedupWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            recent: Default::default(),
+        }
+    }
+
+    // Identifies a request by everything sent to Telegram, including the chat and uploaded bytes.
+    pub fn fingerprint(req: &ApiRequest) -> String {
+        let mut data = format!("{}\n{}", req.method, req.args);
+        for upload in &req.uploads {
+            data.push_str(&format!(
+                "\n{}:{}:{}",
+                upload.field,
+                upload.file_name,
+                sha256_hex(&upload.data)
+            ));
+        }
+        sha256_hex(data.as_bytes())
+    }
+
+    /// The message sent for an identical request within the window, if any.
+    pub fn recent(&self, fingerprint: &str) -> Option<Value> {
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|_, (_, sent_at)| sent_at.elapsed() < self.window);
+        recent.get(fingerprint).map(|(sent, _)| sent.clone())
+    }
+
+    pub fn record(&self, fingerprint: String, sent: &Value) {
+        self.recent
+            .lock()
+            .unwrap()
+            .insert(fingerprint, (sent.clone(), Instant::now()));
+    }
+}
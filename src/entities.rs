@@ -0,0 +1,366 @@
+use std::ops::Range;
+
+use serde_json::{json, Map, Value};
+
+use crate::{unknown_fields, with_unknown_fields};
+
+/// The kind of a message entity, along with any kind-specific data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntityKind {
+    Mention,
+    Hashtag,
+    Cashtag,
+    BotCommand,
+    Url,
+    Email,
+    PhoneNumber,
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Spoiler,
+    Blockquote,
+    ExpandableBlockquote,
+    Code,
+    Pre {
+        language: Option<String>,
+    },
+    TextLink {
+        url: String,
+    },
+    TextMention {
+        user_id: i64,
+        /// The whole user object Telegram sent, or null for mentions built locally.
+        user: Value,
+    },
+    CustomEmoji {
+        custom_emoji_id: String,
+    },
+    /// An entity type this crate does not know about yet.
+    Other(String),
+}
+
+impl EntityKind {
+    /// The name Telegram uses for this kind of entity.
+    pub fn type_name(&self) -> &str {
+        match self {
+            EntityKind::Mention => "mention",
+            EntityKind::Hashtag => "hashtag",
+            EntityKind::Cashtag => "cashtag",
+            EntityKind::BotCommand => "bot_command",
+            EntityKind::Url => "url",
+            EntityKind::Email => "email",
+            EntityKind::PhoneNumber => "phone_number",
+            EntityKind::Bold => "bold",
+            EntityKind::Italic => "italic",
+            EntityKind::Underline => "underline",
+            EntityKind::Strikethrough => "strikethrough",
+            EntityKind::Spoiler => "spoiler",
+            EntityKind::Blockquote => "blockquote",
+            EntityKind::ExpandableBlockquote => "expandable_blockquote",
+            EntityKind::Code => "code",
+            EntityKind::Pre { .. } => "pre",
+            EntityKind::TextLink { .. } => "text_link",
+            EntityKind::TextMention { .. } => "text_mention",
+            EntityKind::CustomEmoji { .. } => "custom_emoji",
+            EntityKind::Other(name) => name,
+        }
+    }
+}
+
+/// A message entity. Offsets and lengths are in UTF-16 code units, as in the Telegram API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageEntity {
+    pub kind: EntityKind,
+    pub offset: usize,
+    pub length: usize,
+    /// Fields this crate does not know about, such as those of newer entity types, which [MessageEntity::to_json] sends back.
+    pub extra: Map<String, Value>,
+}
+
+impl MessageEntity {
+    /// Creates an entity covering the given byte range of the text, converting it to UTF-16 offsets.
+    pub fn spanning(kind: EntityKind, text: &str, range: Range<usize>) -> Self {
+        let offset = utf16_len(&text[..range.start]);
+        Self {
+            kind,
+            offset,
+            length: utf16_len(&text[range]),
+            extra: Map::new(),
+        }
+    }
+
+    /// Parses an entity from its JSON representation.
+    pub fn from_json(entity: &Value) -> Option<Self> {
+        let str_field = |name: &str| entity[name].as_str().map(|s| s.to_owned());
+        let kind = match entity["type"].as_str()? {
+            "mention" => EntityKind::Mention,
+            "hashtag" => EntityKind::Hashtag,
+            "cashtag" => EntityKind::Cashtag,
+            "bot_command" => EntityKind::BotCommand,
+            "url" => EntityKind::Url,
+            "email" => EntityKind::Email,
+            "phone_number" => EntityKind::PhoneNumber,
+            "bold" => EntityKind::Bold,
+            "italic" => EntityKind::Italic,
+            "underline" => EntityKind::Underline,
+            "strikethrough" => EntityKind::Strikethrough,
+            "spoiler" => EntityKind::Spoiler,
+            "blockquote" => EntityKind::Blockquote,
+            "expandable_blockquote" => EntityKind::ExpandableBlockquote,
+            "code" => EntityKind::Code,
+            "pre" => EntityKind::Pre {
+                language: str_field("language"),
+            },
+            "text_link" => EntityKind::TextLink {
+                url: str_field("url")?,
+            },
+            "text_mention" => EntityKind::TextMention {
+                user_id: entity["user"]["id"].as_i64()?,
+                user: entity["user"].clone(),
+            },
+            "custom_emoji" => EntityKind::CustomEmoji {
+                custom_emoji_id: str_field("custom_emoji_id")?,
+            },
+            other => EntityKind::Other(other.to_owned()),
+        };
+        Some(Self {
+            kind,
+            offset: entity["offset"].as_u64()? as usize,
+            length: entity["length"].as_u64()? as usize,
+            extra: unknown_fields(
+                entity,
+                &[
+                    "type",
+                    "offset",
+                    "length",
+                    "language",
+                    "url",
+                    "user",
+                    "custom_emoji_id",
+                ],
+            ),
+        })
+    }
+
+    /// Converts the entity to its JSON representation, for sending.
+    pub fn to_json(&self) -> Value {
+        let mut entity = json!({
+            "type": self.kind.type_name(),
+            "offset": self.offset,
+            "length": self.length,
+        });
+        match &self.kind {
+            EntityKind::Pre {
+                language: Some(language),
+            } => entity["language"] = json!(language),
+            EntityKind::TextLink { url } => entity["url"] = json!(url),
+            EntityKind::TextMention { user_id, user } => {
+                let mut user = if user.is_object() {
+                    user.clone()
+                } else {
+                    json!({})
+                };
+                user["id"] = json!(user_id);
+                entity["user"] = user;
+            }
+            EntityKind::CustomEmoji { custom_emoji_id } => {
+                entity["custom_emoji_id"] = json!(custom_emoji_id)
+            }
+            _ => {}
+        }
+        with_unknown_fields(entity, &self.extra)
+    }
+
+    /// The byte range this entity covers in the given text, or None if it is out of bounds or splits a character.
+    pub fn byte_range(&self, text: &str) -> Option<Range<usize>> {
+        let start = utf16_to_byte(text, self.offset)?;
+        let end = utf16_to_byte(text, self.offset + self.length)?;
+        Some(start..end)
+    }
+
+    /// The part of the given text this entity covers.
+    pub fn text<'a>(&self, text: &'a str) -> Option<&'a str> {
+        self.byte_range(text).map(|range| &text[range])
+    }
+}
+
+/// Parses the entities of a message, including caption entities for media messages.
+pub fn parse_entities(message: &Value) -> Vec<MessageEntity> {
+    let entities = if message["entities"].is_array() {
+        &message["entities"]
+    } else {
+        &message["caption_entities"]
+    };
+    entities
+        .as_array()
+        .map(|entities| {
+            entities
+                .iter()
+                .filter_map(MessageEntity::from_json)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Pairs each entity of a message with the part of the message text, or caption, that it covers.
+pub fn entity_texts(message: &Value) -> Vec<(MessageEntity, &str)> {
+    let text = message["text"]
+        .as_str()
+        .or_else(|| message["caption"].as_str())
+        .unwrap_or_default();
+    parse_entities(message)
+        .into_iter()
+        .filter_map(|entity| {
+            let covered = entity.text(text)?;
+            Some((entity, covered))
+        })
+        .collect()
+}
+
+/// Converts entities to a JSON array, for sending as `entities` or `caption_entities`.
+pub fn entities_to_json(entities: &[MessageEntity]) -> Value {
+    entities.iter().map(MessageEntity::to_json).collect()
+}
+
+/// The length of the string in UTF-16 code units.
+pub fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+/// Converts a UTF-16 offset into the text into a byte offset, or None if it is out of bounds or splits a character.
+pub fn utf16_to_byte(text: &str, utf16_offset: usize) -> Option<usize> {
+    let mut utf16_pos = 0;
+    for (byte_pos, c) in text.char_indices() {
+        if utf16_pos == utf16_offset {
+            return Some(byte_pos);
+        }
+        if utf16_pos > utf16_offset {
+            return None;
+        }
+        utf16_pos += c.len_utf16();
+    }
+    (utf16_pos == utf16_offset).then_some(text.len())
+}
+
+/// Builds message text together with its entities, keeping track of UTF-16 offsets.
+#[derive(Clone, Debug, Default)]
+pub struct EntityTextBuilder {
+    text: String,
+    entities: Vec<MessageEntity>,
+}
+
+impl EntityTextBuilder {
+    /// Creates an empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends plain text.
+    pub fn text(mut self, text: &str) -> Self {
+        self.text.push_str(text);
+        self
+    }
+
+    /// Appends text covered by an entity of the given kind.
+    pub fn entity(mut self, kind: EntityKind, text: &str) -> Self {
+        self.entities.push(MessageEntity {
+            kind,
+            offset: utf16_len(&self.text),
+            length: utf16_len(text),
+            extra: Map::new(),
+        });
+        self.text.push_str(text);
+        self
+    }
+
+    /// Appends a user's name as a `text_mention` entity, which mentions them even if they have no username.
+    pub fn mention(self, user_id: i64, name: &str) -> Self {
+        self.entity(
+            EntityKind::TextMention {
+                user_id,
+                user: Value::Null,
+            },
+            name,
+        )
+    }
+
+    /// Returns the text and its entities.
+    pub fn build(self) -> (String, Vec<MessageEntity>) {
+        (self.text, self.entities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entities_round_trip_through_json() {
+        let entities = json!([
+            {"type": "bold", "offset": 0, "length": 4},
+            {"type": "pre", "offset": 5, "length": 3, "language": "rust"},
+            {"type": "text_link", "offset": 9, "length": 2, "url": "https://example.com"},
+            {"type": "custom_emoji", "offset": 12, "length": 2, "custom_emoji_id": "123"},
+        ]);
+        for entity in entities.as_array().unwrap() {
+            assert_eq!(&MessageEntity::from_json(entity).unwrap().to_json(), entity);
+        }
+        assert!(
+            MessageEntity::from_json(&json!({"type": "text_link", "offset": 0, "length": 1}))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn offsets_count_utf16_code_units() {
+        let text = "hi 😀 there";
+        assert_eq!(utf16_len(text), 11);
+        let start = text.find("there").unwrap();
+        let entity = MessageEntity::spanning(EntityKind::Bold, text, start..text.len());
+        assert_eq!((entity.offset, entity.length), (6, 5));
+        assert_eq!(entity.text(text), Some("there"));
+        // an offset inside the surrogate pair of the emoji splits a character
+        assert_eq!(utf16_to_byte(text, 4), None);
+        assert_eq!(utf16_to_byte(text, 12), None);
+    }
+
+    #[test]
+    fn pairs_entities_with_the_text_or_caption() {
+        let message = json!({
+            "caption": "😀 #tag",
+            "caption_entities": [{"type": "hashtag", "offset": 3, "length": 4}],
+        });
+        let texts = entity_texts(&message);
+        assert_eq!(texts.len(), 1);
+        assert_eq!(texts[0].0.kind, EntityKind::Hashtag);
+        assert_eq!(texts[0].1, "#tag");
+    }
+
+    #[test]
+    fn unknown_fields_survive_round_trips() {
+        let entity =
+            json!({"type": "date_time", "offset": 0, "length": 5, "unix_time": 1700000000});
+        let parsed = MessageEntity::from_json(&entity).unwrap();
+        assert_eq!(parsed.kind, EntityKind::Other("date_time".to_owned()));
+        assert_eq!(parsed.to_json(), entity);
+    }
+
+    #[test]
+    fn text_mentions_keep_the_whole_user() {
+        let entity = json!({
+            "type": "text_mention",
+            "offset": 0,
+            "length": 3,
+            "user": {"id": 42, "is_bot": false, "first_name": "Ann"},
+        });
+        let parsed = MessageEntity::from_json(&entity).unwrap();
+        assert!(matches!(
+            parsed.kind,
+            EntityKind::TextMention { user_id: 42, .. }
+        ));
+        assert_eq!(parsed.to_json(), entity);
+        let (_, built) = EntityTextBuilder::new().mention(7, "Bob").build();
+        assert_eq!(built[0].to_json()["user"], json!({"id": 7}));
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use smol::{
+    channel::{Receiver, Sender},
+    future::FutureExt,
+};
+
+use crate::{ApiError, BotHandle, Inner, OutgoingMessage, TelegramBot};
+
+/// How long to wait before retrying a deletion that failed for reasons other than Telegram refusing it.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// Wakes the deletion task when a message is scheduled, in case it is due before the one the task is waiting for.
+pub(crate) struct DeletionQueue {
+    wake_send: Sender<()>,
+    wake_recv: Receiver<()>,
+}
+
+impl Default for DeletionQueue {
+    fn default() -> Self {
+        let (wake_send, wake_recv) = smol::channel::bounded(1);
+        Self {
+            wake_send,
+            wake_recv,
+        }
+    }
+}
+
+impl Inner {
+    async fn send_ephemeral(&self, msg: OutgoingMessage, ttl: Duration) -> anyhow::Result<Value> {
+        let delete_at = unix_now() + ttl.as_secs();
+        let mut first = None;
+        for req in msg.to_requests() {
+            let sent = self.send_request(req).await?;
+            // albums return an array of messages
+            let messages = match &sent {
+                Value::Array(messages) => messages.iter().collect(),
+                sent => vec![sent],
+            };
+            for sent in messages {
+                let (Some(chat_id), Some(message_id)) =
+                    (sent["chat"]["id"].as_i64(), sent["message_id"].as_i64())
+                else {
+                    continue;
+                };
+                self.store.set(
+                    &format!("ephemeral/{chat_id}/{message_id}"),
+                    json!({
+                        "chat_id": chat_id,
+                        "message_id": message_id,
+                        "delete_at": delete_at,
+                    }),
+                )?;
+            }
+            first.get_or_insert(sent);
+        }
+        let _ = self.deletions.wake_send.try_send(());
+        Ok(first.unwrap_or_default())
+    }
+}
+
+impl TelegramBot {
+    /// Sends a message and deletes it once the time to live has passed, such as for CAPTCHA prompts or one-time codes. Pending deletions are kept in the store, so they still happen after a restart.
+    pub async fn send_ephemeral(
+        &self,
+        msg: impl Into<OutgoingMessage>,
+        ttl: Duration,
+    ) -> anyhow::Result<Value> {
+        self.inner.send_ephemeral(msg.into(), ttl).await
+    }
+}
+
+impl BotHandle {
+    /// Sends a message and deletes it once the time to live has passed. See [TelegramBot::send_ephemeral].
+    pub async fn send_ephemeral(
+        &self,
+        msg: impl Into<OutgoingMessage>,
+        ttl: Duration,
+    ) -> anyhow::Result<Value> {
+        self.inner.send_ephemeral(msg.into(), ttl).await
+    }
+}
+
+// Deletes ephemeral messages as they expire, for as long as the bot runs.
+pub(crate) async fn delete_expired(inner: &Inner) {
+    loop {
+        let wait = match delete_due(inner).await {
+            Ok(wait) => wait,
+            Err(err) => {
+                tracing::error!("cannot delete expired messages: {:?}", err);
+                Some(RETRY_DELAY)
+            }
+        };
+        let woken = async {
+            let _ = inner.deletions.wake_recv.recv().await;
+        };
+        match wait {
+            Some(wait) => {
+                woken
+                    .or(async {
+                        smol::Timer::after(wait).await;
+                    })
+                    .await
+            }
+            None => woken.await,
+        }
+    }
+}
+
+// Deletes every message that is due, returning how long until the next one is.
+async fn delete_due(inner: &Inner) -> anyhow::Result<Option<Duration>> {
+    let now = unix_now();
+    let mut next = None::<u64>;
+    for key in inner.store.keys("ephemeral/")? {
+        let Some(entry) = inner.store.get(&key)? else {
+            continue;
+        };
+        let delete_at = entry["delete_at"].as_u64().unwrap_or_default();
+        if delete_at > now {
+            next = Some(next.map_or(delete_at, |next| next.min(delete_at)));
+            continue;
+        }
+        let res = inner
+            .call_api(
+                "deleteMessage",
+                json!({
+                    "chat_id": entry["chat_id"],
+                    "message_id": entry["message_id"],
+                }),
+            )
+            .await;
+        match res {
+            Ok(_) => {}
+            // already deleted, or too old for bots to delete; either way there is nothing left to do
+            Err(err) if err.downcast_ref::<ApiError>().is_some() => {
+                tracing::warn!("cannot delete ephemeral message {key}: {err}")
+            }
+            Err(err) => return Err(err),
+        }
+        inner.store.remove(&key)?;
+    }
+    Ok(next.map(|next| Duration::from_secs(next - now)))
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
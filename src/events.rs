@@ -0,0 +1,300 @@
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::checklist::ChecklistEvent;
+
+/// A giveaway a channel started, from the `giveaway` field of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Giveaway {
+    /// The chats users must subscribe to to take part.
+    pub chat_ids: Vec<i64>,
+    /// When winners are picked, as a Unix timestamp.
+    pub winners_selection_date: i64,
+    pub winner_count: i64,
+    pub only_new_members: bool,
+    pub has_public_winners: bool,
+    pub prize_description: Option<String>,
+    /// Two-letter codes of the countries users must be from, or empty if any.
+    pub country_codes: Vec<String>,
+    /// Stars split between the winners, for Star giveaways.
+    pub prize_star_count: Option<i64>,
+    /// Months of Telegram Premium each winner gets, for Premium giveaways.
+    pub premium_subscription_month_count: Option<i64>,
+}
+
+impl Giveaway {
+    /// Parses a giveaway from its JSON representation.
+    pub fn from_json(giveaway: &Value) -> Option<Self> {
+        Some(Self {
+            chat_ids: chat_ids(&giveaway["chats"]),
+            winners_selection_date: giveaway["winners_selection_date"].as_i64()?,
+            winner_count: giveaway["winner_count"].as_i64()?,
+            only_new_members: giveaway["only_new_members"].as_bool() == Some(true),
+            has_public_winners: giveaway["has_public_winners"].as_bool() == Some(true),
+            prize_description: giveaway["prize_description"].as_str().map(str::to_owned),
+            country_codes: giveaway["country_codes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|code| Some(code.as_str()?.to_owned()))
+                .collect(),
+            prize_star_count: giveaway["prize_star_count"].as_i64(),
+            premium_subscription_month_count: giveaway["premium_subscription_month_count"].as_i64(),
+        })
+    }
+}
+
+/// The public list of winners of a giveaway, from the `giveaway_winners` field of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GiveawayWinners {
+    /// The chat that ran the giveaway.
+    pub chat_id: i64,
+    pub giveaway_message_id: i64,
+    pub winners_selection_date: i64,
+    pub winner_count: i64,
+    /// Up to 100 of the winners' user IDs.
+    pub winner_ids: Vec<i64>,
+    pub unclaimed_prize_count: i64,
+    pub was_refunded: bool,
+    pub prize_star_count: Option<i64>,
+    pub premium_subscription_month_count: Option<i64>,
+}
+
+impl GiveawayWinners {
+    /// Parses giveaway winners from their JSON representation.
+    pub fn from_json(winners: &Value) -> Option<Self> {
+        Some(Self {
+            chat_id: winners["chat"]["id"].as_i64()?,
+            giveaway_message_id: winners["giveaway_message_id"].as_i64()?,
+            winners_selection_date: winners["winners_selection_date"].as_i64()?,
+            winner_count: winners["winner_count"].as_i64()?,
+            winner_ids: winners["winners"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|user| user["id"].as_i64())
+                .collect(),
+            unclaimed_prize_count: winners["unclaimed_prize_count"]
+                .as_i64()
+                .unwrap_or_default(),
+            was_refunded: winners["was_refunded"].as_bool() == Some(true),
+            prize_star_count: winners["prize_star_count"].as_i64(),
+            premium_subscription_month_count: winners["premium_subscription_month_count"].as_i64(),
+        })
+    }
+}
+
+/// A giveaway without public winners finished, from the `giveaway_completed` service message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GiveawayCompleted {
+    pub winner_count: i64,
+    pub unclaimed_prize_count: i64,
+    /// The giveaway's message, if it still exists.
+    pub giveaway_message_id: Option<i64>,
+    pub is_star_giveaway: bool,
+}
+
+impl GiveawayCompleted {
+    /// Parses a completed giveaway from its JSON representation.
+    pub fn from_json(completed: &Value) -> Option<Self> {
+        Some(Self {
+            winner_count: completed["winner_count"].as_i64()?,
+            unclaimed_prize_count: completed["unclaimed_prize_count"]
+                .as_i64()
+                .unwrap_or_default(),
+            giveaway_message_id: completed["giveaway_message"]["message_id"].as_i64(),
+            is_star_giveaway: completed["is_star_giveaway"].as_bool() == Some(true),
+        })
+    }
+}
+
+/// Something that happened to a giveaway, as found in a message or channel post.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GiveawayEvent {
+    /// A giveaway was scheduled, with this many Stars as the prize if it is a Star giveaway.
+    Created {
+        prize_star_count: Option<i64>,
+    },
+    Started(Giveaway),
+    Winners(GiveawayWinners),
+    Completed(GiveawayCompleted),
+}
+
+impl GiveawayEvent {
+    /// Parses the giveaway event in a message, if it has one.
+    pub fn from_message(message: &Value) -> Option<Self> {
+        if message["giveaway_created"].is_object() {
+            return Some(Self::Created {
+                prize_star_count: message["giveaway_created"]["prize_star_count"].as_i64(),
+            });
+        }
+        if message["giveaway"].is_object() {
+            return Giveaway::from_json(&message["giveaway"]).map(Self::Started);
+        }
+        if message["giveaway_winners"].is_object() {
+            return GiveawayWinners::from_json(&message["giveaway_winners"]).map(Self::Winners);
+        }
+        if message["giveaway_completed"].is_object() {
+            return GiveawayCompleted::from_json(&message["giveaway_completed"])
+                .map(Self::Completed);
+        }
+        None
+    }
+}
+
+/// The user let the bot message them, from a `write_access_allowed` or `connected_website`
+/// service message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccessEvent {
+    WriteAccessAllowed {
+        /// The bot asked for access with `requestWriteAccess` in a Mini App.
+        from_request: bool,
+        /// Access came from launching this Mini App from a link.
+        web_app_name: Option<String>,
+        /// Access came from adding the bot to the attachment or side menu.
+        from_attachment_menu: bool,
+    },
+    /// The user logged into this domain with the Login Widget.
+    ConnectedWebsite(String),
+}
+
+impl AccessEvent {
+    /// Parses the access event in a message, if it has one.
+    pub fn from_message(message: &Value) -> Option<Self> {
+        let allowed = &message["write_access_allowed"];
+        if allowed.is_object() {
+            return Some(Self::WriteAccessAllowed {
+                from_request: allowed["from_request"].as_bool() == Some(true),
+                web_app_name: allowed["web_app_name"].as_str().map(str::to_owned),
+                from_attachment_menu: allowed["from_attachment_menu"].as_bool() == Some(true),
+            });
+        }
+        message["connected_website"]
+            .as_str()
+            .map(|domain| Self::ConnectedWebsite(domain.to_owned()))
+    }
+}
+
+/// Something that happened to a chat's video chat, from a service message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VideoChatEvent {
+    /// A video chat was scheduled to start at this Unix timestamp.
+    Scheduled {
+        start_date: i64,
+    },
+    Started,
+    /// A video chat ended after running this long.
+    Ended {
+        duration: Duration,
+    },
+    ParticipantsInvited {
+        user_ids: Vec<i64>,
+    },
+}
+
+impl VideoChatEvent {
+    /// Parses the video chat event in a message, if it has one.
+    pub fn from_message(message: &Value) -> Option<Self> {
+        if message["video_chat_scheduled"].is_object() {
+            return Some(Self::Scheduled {
+                start_date: message["video_chat_scheduled"]["start_date"].as_i64()?,
+            });
+        }
+        if message["video_chat_started"].is_object() {
+            return Some(Self::Started);
+        }
+        if message["video_chat_ended"].is_object() {
+            return Some(Self::Ended {
+                duration: Duration::from_secs(message["video_chat_ended"]["duration"].as_u64()?),
+            });
+        }
+        if message["video_chat_participants_invited"].is_object() {
+            return Some(Self::ParticipantsInvited {
+                user_ids: message["video_chat_participants_invited"]["users"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|user| user["id"].as_i64())
+                    .collect(),
+            });
+        }
+        None
+    }
+}
+
+/// A message that records something happening in a chat rather than carrying text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServiceMessage {
+    /// A message was pinned.
+    MessagePinned {
+        message_id: i64,
+    },
+    /// The chat photo was changed, to the photo with this file ID.
+    ChatPhotoChanged {
+        file_id: String,
+    },
+    ChatPhotoDeleted,
+    TitleChanged(String),
+    /// A user sharing live location came within `distance` meters of another.
+    ProximityAlert {
+        traveler_id: i64,
+        watcher_id: i64,
+        distance: i64,
+    },
+    /// Messages are now deleted after this long, or no longer deleted if `None`.
+    AutoDeleteTimerChanged(Option<Duration>),
+    Giveaway(GiveawayEvent),
+    Access(AccessEvent),
+    VideoChat(VideoChatEvent),
+    Checklist(ChecklistEvent),
+}
+
+impl ServiceMessage {
+    /// Parses the service message a message carries, if it is one.
+    pub fn from_message(message: &Value) -> Option<Self> {
+        if let Some(message_id) = message["pinned_message"]["message_id"].as_i64() {
+            return Some(Self::MessagePinned { message_id });
+        }
+        if let Some(photo) = message["new_chat_photo"].as_array() {
+            // sizes come smallest first
+            let file_id = photo.last()?["file_id"].as_str()?.to_owned();
+            return Some(Self::ChatPhotoChanged { file_id });
+        }
+        if message["delete_chat_photo"].as_bool() == Some(true) {
+            return Some(Self::ChatPhotoDeleted);
+        }
+        if let Some(title) = message["new_chat_title"].as_str() {
+            return Some(Self::TitleChanged(title.to_owned()));
+        }
+        let alert = &message["proximity_alert_triggered"];
+        if alert.is_object() {
+            return Some(Self::ProximityAlert {
+                traveler_id: alert["traveler"]["id"].as_i64()?,
+                watcher_id: alert["watcher"]["id"].as_i64()?,
+                distance: alert["distance"].as_i64()?,
+            });
+        }
+        if let Some(secs) =
+            message["message_auto_delete_timer_changed"]["message_auto_delete_time"].as_u64()
+        {
+            return Some(Self::AutoDeleteTimerChanged(
+                (secs > 0).then(|| Duration::from_secs(secs)),
+            ));
+        }
+        GiveawayEvent::from_message(message)
+            .map(Self::Giveaway)
+            .or_else(|| AccessEvent::from_message(message).map(Self::Access))
+            .or_else(|| VideoChatEvent::from_message(message).map(Self::VideoChat))
+            .or_else(|| ChecklistEvent::from_message(message).map(Self::Checklist))
+    }
+}
+
+fn chat_ids(chats: &Value) -> Vec<i64> {
+    chats
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|chat| chat["id"].as_i64())
+        .collect()
+}
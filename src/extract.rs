@@ -0,0 +1,196 @@
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use serde_json::Value;
+use smol::future::Boxed;
+
+use crate::{commands::Command, store::Store, Ctx};
+
+/// Values that can be extracted from the context of a message, to be taken as handler parameters.
+pub trait FromCtx: Sized {
+    /// Extracts the value, or returns None if the message doesn't have it, in which case the handler is skipped.
+    fn from_ctx(ctx: &Ctx) -> anyhow::Result<Option<Self>>;
+}
+
+impl FromCtx for Ctx {
+    fn from_ctx(ctx: &Ctx) -> anyhow::Result<Option<Self>> {
+        Ok(Some(ctx.clone()))
+    }
+}
+
+impl FromCtx for Command {
+    fn from_ctx(ctx: &Ctx) -> anyhow::Result<Option<Self>> {
+        Ok(Command::from_update(&ctx.update))
+    }
+}
+
+impl<T: FromCtx> FromCtx for Option<T> {
+    fn from_ctx(ctx: &Ctx) -> anyhow::Result<Option<Self>> {
+        Ok(Some(T::from_ctx(ctx)?))
+    }
+}
+
+/// The raw message JSON.
+#[derive(Clone, Debug)]
+pub struct Message(pub Value);
+
+impl FromCtx for Message {
+    fn from_ctx(ctx: &Ctx) -> anyhow::Result<Option<Self>> {
+        let message = ctx.message();
+        Ok((!message.is_null()).then(|| Message(message.clone())))
+    }
+}
+
+/// The text of the message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Text(pub String);
+
+impl FromCtx for Text {
+    fn from_ctx(ctx: &Ctx) -> anyhow::Result<Option<Self>> {
+        Ok(ctx.text().map(|text| Text(text.to_owned())))
+    }
+}
+
+/// State that can be kept in a [Session].
+pub trait SessionState: Default {
+    /// Parses the state from how it was stored, or returns None if it is invalid, in which case the default is used.
+    fn from_json(value: &Value) -> Option<Self>;
+
+    fn to_json(&self) -> Value;
+}
+
+impl SessionState for Value {
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+
+    fn to_json(&self) -> Value {
+        self.clone()
+    }
+}
+
+/// Per-chat state kept in the bot's store. Changes are kept only once [Session::save] is called.
+pub struct Session<T> {
+    key: String,
+    store: Arc<dyn Store>,
+    state: T,
+}
+
+impl<T: SessionState> Session<T> {
+    /// Writes the state back to the store.
+    pub fn save(&self) -> anyhow::Result<()> {
+        self.store.set(&self.key, self.state.to_json())
+    }
+
+    /// Removes the state from the store, so the next message starts over with the default.
+    pub fn clear(self) -> anyhow::Result<()> {
+        self.store.remove(&self.key)
+    }
+}
+
+impl<T> Deref for Session<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.state
+    }
+}
+
+impl<T> DerefMut for Session<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.state
+    }
+}
+
+impl<T: SessionState> FromCtx for Session<T> {
+    fn from_ctx(ctx: &Ctx) -> anyhow::Result<Option<Self>> {
+        let Some(chat_id) = ctx.chat_id() else {
+            return Ok(None);
+        };
+        let key = format!("session/{chat_id}");
+        let store = ctx.bot.inner.store.clone();
+        let state = store
+            .get(&key)?
+            .and_then(|value| T::from_json(&value))
+            .unwrap_or_default();
+        Ok(Some(Session { key, store, state }))
+    }
+}
+
+/// Functions whose parameters can all be extracted from a [Ctx]. Implemented for async functions of up to six [FromCtx] parameters.
+pub trait Handler<Args>: Send + Sync + 'static {
+    /// Extracts the parameters and calls the function, or returns None if any parameter is missing.
+    fn call(&self, ctx: &Ctx) -> anyhow::Result<Option<Boxed<anyhow::Result<()>>>>;
+}
+
+macro_rules! impl_handler {
+    ($($ty:ident),*) => {
+        #[allow(non_snake_case, unused_variables)]
+        impl<Fun, Fut, $($ty: FromCtx,)*> Handler<($($ty,)*)> for Fun
+        where
+            Fun: Fn($($ty),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+        {
+            fn call(&self, ctx: &Ctx) -> anyhow::Result<Option<Boxed<anyhow::Result<()>>>> {
+                $(
+                    let Some($ty) = $ty::from_ctx(ctx)? else {
+                        return Ok(None);
+                    };
+                )*
+                Ok(Some(Box::pin(self($($ty),*))))
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(A);
+impl_handler!(A, B);
+impl_handler!(A, B, C);
+impl_handler!(A, B, C, D);
+impl_handler!(A, B, C, D, E);
+impl_handler!(A, B, C, D, E, F);
+
+type BoxedHandler =
+    Box<dyn Fn(&Ctx) -> anyhow::Result<Option<Boxed<anyhow::Result<()>>>> + Send + Sync>;
+
+/// Dispatches each message to the first registered handler whose parameters can all be extracted from it.
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: Vec<BoxedHandler>,
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher with no handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler, like `async fn greet(Text(text): Text, ctx: Ctx) -> anyhow::Result<()>`. Handlers are tried in the order they are registered.
+    pub fn handler<Args>(mut self, handler: impl Handler<Args>) -> Self {
+        self.handlers.push(Box::new(move |ctx| handler.call(ctx)));
+        self
+    }
+
+    /// Handles a message, calling the first matching handler. Messages no handler matches are ignored.
+    pub async fn handle(&self, ctx: Ctx) -> anyhow::Result<()> {
+        for handler in &self.handlers {
+            if let Some(handled) = handler(&ctx)? {
+                return handled.await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns the dispatcher into a handler for [crate::TelegramBotBuilder::build_with_ctx].
+    pub fn into_handler(self) -> impl FnMut(Ctx) -> Boxed<anyhow::Result<()>> + Send + 'static {
+        let this = Arc::new(self);
+        move |ctx| {
+            let this = this.clone();
+            Box::pin(async move { this.handle(ctx).await })
+        }
+    }
+}
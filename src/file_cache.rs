@@ -0,0 +1,43 @@
+use serde_json::{json, Value};
+
+use crate::{outgoing::ApiRequest, sha256::sha256_hex, store::Store};
+
+// Looks up uploads in the cache, sending the file_id of those already uploaded instead of their content. Returns the fields and cache keys of the uploads left.
+pub(crate) fn apply(store: &dyn Store, req: &mut ApiRequest) -> Vec<(String, String)> {
+    let mut pending = vec![];
+    let args = &mut req.args;
+    req.uploads.retain(|upload| {
+        let key = format!("file_id/{}/{}", upload.field, sha256_hex(&upload.data));
+        match store.get(&key) {
+            Ok(Some(file_id)) => {
+                args[&upload.field] = file_id;
+                false
+            }
+            Ok(None) => {
+                pending.push((upload.field.clone(), key));
+                true
+            }
+            Err(err) => {
+                tracing::warn!("cannot look up cached file_id: {:?}", err);
+                true
+            }
+        }
+    });
+    pending
+}
+
+// Caches the file_ids Telegram assigned to the uploads, found in the sent message.
+pub(crate) fn record(store: &dyn Store, pending: &[(String, String)], sent: &Value) {
+    for (field, key) in pending {
+        // photos come in several sizes, the largest last
+        let file = match &sent[field] {
+            Value::Array(sizes) => sizes.last().unwrap_or(&Value::Null),
+            file => file,
+        };
+        if let Some(file_id) = file["file_id"].as_str() {
+            if let Err(err) = store.set(key, json!(file_id)) {
+                tracing::warn!("cannot cache file_id: {:?}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::Context;
+use serde_json::json;
+use smol::io::AsyncReadExt;
+use smol_timeout::TimeoutExt;
+
+use crate::{redact_error, BotHandle, Inner, TelegramBot};
+
+type ProgressCallback = Box<dyn FnMut(u64, Option<u64>) + Send>;
+
+/// Options for downloading a file.
+#[derive(Default)]
+pub struct DownloadOptions {
+    max_size: Option<u64>,
+    progress: Option<ProgressCallback>,
+}
+
+impl DownloadOptions {
+    /// Creates options with no size limit and no progress callback.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuses files larger than the given number of bytes, before downloading them if their size is known.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Calls the callback with the bytes downloaded so far and the total size, if known, as the download progresses.
+    pub fn progress(mut self, progress: impl FnMut(u64, Option<u64>) + Send + 'static) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+}
+
+impl Inner {
+    async fn download_file(&self, file_id: &str, opts: DownloadOptions) -> anyhow::Result<Vec<u8>> {
+        let timeout = self.timeouts.transfer;
+        self.download_file_untimed(file_id, opts)
+            .timeout(timeout)
+            .await
+            .with_context(|| format!("downloading file {file_id} timed out after {timeout:?}"))?
+    }
+
+    async fn download_file_untimed(
+        &self,
+        file_id: &str,
+        mut opts: DownloadOptions,
+    ) -> anyhow::Result<Vec<u8>> {
+        let file = self
+            .call_api("getFile", json!({ "file_id": file_id }))
+            .await?;
+        let file_path = file["file_path"]
+            .as_str()
+            .context("getFile returned no file_path")?;
+        let too_big = |size: u64| {
+            opts.max_size.filter(|max| size > *max).map(|max| {
+                anyhow::anyhow!("file {file_id} is {size} bytes, more than the limit of {max}")
+            })
+        };
+        if let Some(err) = file["file_size"].as_u64().and_then(too_big) {
+            return Err(err);
+        }
+        let token = self.token();
+        let mut download = self
+            .transport
+            .download(&token, file_path)
+            .await
+            .map_err(|err| redact_error(err, &token))?;
+        let total = download.size.or(file["file_size"].as_u64());
+        if let Some(err) = download.size.and_then(too_big) {
+            return Err(err);
+        }
+        let mut data = vec![];
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = download
+                .body
+                .read(&mut buf)
+                .await
+                .with_context(|| format!("cannot download file {file_id}"))?;
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+            if let Some(err) = too_big(data.len() as u64) {
+                return Err(err);
+            }
+            if let Some(progress) = &mut opts.progress {
+                progress(data.len() as u64, total);
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl TelegramBot {
+    /// Downloads a file by its file_id. Telegram only serves files up to 20 MB to bots.
+    pub async fn download_file(&self, file_id: &str) -> anyhow::Result<Vec<u8>> {
+        self.inner
+            .download_file(file_id, DownloadOptions::default())
+            .await
+    }
+
+    /// Downloads a file by its file_id, with a size limit or progress callback.
+    pub async fn download_file_with(
+        &self,
+        file_id: &str,
+        opts: DownloadOptions,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.inner.download_file(file_id, opts).await
+    }
+}
+
+impl BotHandle {
+    /// Downloads a file by its file_id. Telegram only serves files up to 20 MB to bots.
+    pub async fn download_file(&self, file_id: &str) -> anyhow::Result<Vec<u8>> {
+        self.inner
+            .download_file(file_id, DownloadOptions::default())
+            .await
+    }
+
+    /// Downloads a file by its file_id, with a size limit or progress callback.
+    pub async fn download_file_with(
+        &self,
+        file_id: &str,
+        opts: DownloadOptions,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.inner.download_file(file_id, opts).await
+    }
+}
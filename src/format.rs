@@ -0,0 +1,237 @@
+use std::ops::Range;
+
+use serde_json::Value;
+
+use crate::entities::{EntityKind, MessageEntity};
+
+/// A Telegram parse mode for formatted text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseMode {
+    Html,
+    MarkdownV2,
+}
+
+impl ParseMode {
+    /// The value of the `parse_mode` parameter for this mode.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseMode::Html => "HTML",
+            ParseMode::MarkdownV2 => "MarkdownV2",
+        }
+    }
+
+    /// Escapes plain text so that it shows up literally in this mode.
+    pub fn escape(&self, text: &str) -> String {
+        match self {
+            ParseMode::Html => escape_html(text),
+            ParseMode::MarkdownV2 => escape_markdown_v2(text),
+        }
+    }
+
+    /// Markup linking the name to a user's profile, which mentions them even if they have no username.
+    pub fn mention(&self, user_id: i64, name: &str) -> String {
+        wrap(
+            *self,
+            &EntityKind::TextMention {
+                user_id,
+                user: Value::Null,
+            },
+            &self.escape(name),
+        )
+    }
+
+    /// Markup showing the text as inline monospace code.
+    pub fn code(&self, text: &str) -> String {
+        wrap(*self, &EntityKind::Code, &self.escape_code(text))
+    }
+
+    /// Markup showing the text as a preformatted block, highlighted as the given language if any.
+    pub fn pre(&self, text: &str, language: Option<&str>) -> String {
+        let kind = EntityKind::Pre {
+            language: language.map(str::to_owned),
+        };
+        wrap(*self, &kind, &self.escape_code(text))
+    }
+
+    /// Markup quoting the text as a block. An expandable blockquote starts collapsed.
+    pub fn blockquote(&self, text: &str, expandable: bool) -> String {
+        let kind = if expandable {
+            EntityKind::ExpandableBlockquote
+        } else {
+            EntityKind::Blockquote
+        };
+        wrap(*self, &kind, &self.escape(text))
+    }
+
+    fn escape_code(&self, text: &str) -> String {
+        match self {
+            ParseMode::Html => escape_html(text),
+            ParseMode::MarkdownV2 => escape_markdown_v2_code(text),
+        }
+    }
+
+    /// Reconstructs markup in this mode from text and its entities.
+    pub fn render(&self, text: &str, entities: &[MessageEntity]) -> String {
+        let mut spans: Vec<(Range<usize>, &EntityKind)> = entities
+            .iter()
+            .filter_map(|entity| Some((entity.byte_range(text)?, &entity.kind)))
+            .collect();
+        // outer entities come before the entities nested inside them
+        spans.sort_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)));
+        render_range(*self, text, 0..text.len(), &spans, false)
+    }
+}
+
+/// Escapes text for the HTML parse mode.
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes text for the MarkdownV2 parse mode.
+pub fn escape_markdown_v2(text: &str) -> String {
+    escape_chars(text, "\\_*[]()~`>#+-=|{}.!")
+}
+
+// Inside code and pre entities, MarkdownV2 only needs backticks and backslashes escaped.
+fn escape_markdown_v2_code(text: &str) -> String {
+    escape_chars(text, "\\`")
+}
+
+// Inside the url part of a link, MarkdownV2 only needs parentheses and backslashes escaped.
+fn escape_markdown_v2_url(text: &str) -> String {
+    escape_chars(text, "\\)")
+}
+
+fn escape_chars(text: &str, special: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if special.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Renders text and its entities as HTML.
+pub fn to_html(text: &str, entities: &[MessageEntity]) -> String {
+    ParseMode::Html.render(text, entities)
+}
+
+/// Renders text and its entities as MarkdownV2.
+pub fn to_markdown_v2(text: &str, entities: &[MessageEntity]) -> String {
+    ParseMode::MarkdownV2.render(text, entities)
+}
+
+// Renders the given range of the text, where spans are sorted and all start within the range.
+fn render_range(
+    mode: ParseMode,
+    text: &str,
+    range: Range<usize>,
+    spans: &[(Range<usize>, &EntityKind)],
+    in_code: bool,
+) -> String {
+    let escape = |s: &str| match mode {
+        ParseMode::MarkdownV2 if in_code => escape_markdown_v2_code(s),
+        _ => mode.escape(s),
+    };
+    let mut out = String::new();
+    let mut pos = range.start;
+    let mut i = 0;
+    while i < spans.len() {
+        let (span, kind) = &spans[i];
+        let start = span.start.max(pos);
+        // entities overlapping the end of their parent are cut off there
+        let end = span.end.min(range.end);
+        let mut j = i + 1;
+        while j < spans.len() && spans[j].0.start < end {
+            j += 1;
+        }
+        if start < end {
+            out.push_str(&escape(&text[pos..start]));
+            let nested_in_code =
+                in_code || matches!(kind, EntityKind::Code | EntityKind::Pre { .. });
+            let inner = render_range(mode, text, start..end, &spans[i + 1..j], nested_in_code);
+            out.push_str(&wrap(mode, kind, &inner));
+            pos = end;
+        }
+        i = j;
+    }
+    out.push_str(&escape(&text[pos..range.end]));
+    out
+}
+
+// Wraps already-rendered inner markup in the markup for the given entity kind.
+fn wrap(mode: ParseMode, kind: &EntityKind, inner: &str) -> String {
+    match mode {
+        ParseMode::Html => match kind {
+            EntityKind::Bold => format!("<b>{inner}</b>"),
+            EntityKind::Italic => format!("<i>{inner}</i>"),
+            EntityKind::Underline => format!("<u>{inner}</u>"),
+            EntityKind::Strikethrough => format!("<s>{inner}</s>"),
+            EntityKind::Spoiler => format!("<tg-spoiler>{inner}</tg-spoiler>"),
+            EntityKind::Code => format!("<code>{inner}</code>"),
+            EntityKind::Pre {
+                language: Some(language),
+            } => format!(
+                "<pre><code class=\"language-{}\">{inner}</code></pre>",
+                escape_html(language)
+            ),
+            EntityKind::Pre { language: None } => format!("<pre>{inner}</pre>"),
+            EntityKind::Blockquote => format!("<blockquote>{inner}</blockquote>"),
+            EntityKind::ExpandableBlockquote => {
+                format!("<blockquote expandable>{inner}</blockquote>")
+            }
+            EntityKind::TextLink { url } => format!("<a href=\"{}\">{inner}</a>", escape_html(url)),
+            EntityKind::TextMention { user_id, .. } => {
+                format!("<a href=\"tg://user?id={user_id}\">{inner}</a>")
+            }
+            EntityKind::CustomEmoji { custom_emoji_id } => format!(
+                "<tg-emoji emoji-id=\"{}\">{inner}</tg-emoji>",
+                escape_html(custom_emoji_id)
+            ),
+            _ => inner.to_owned(),
+        },
+        ParseMode::MarkdownV2 => match kind {
+            EntityKind::Bold => format!("*{inner}*"),
+            // the ignored \r keeps a closing italic from merging into an enclosing underline
+            EntityKind::Italic => format!("_{inner}_\r"),
+            EntityKind::Underline => format!("__{inner}__"),
+            EntityKind::Strikethrough => format!("~{inner}~"),
+            EntityKind::Spoiler => format!("||{inner}||"),
+            EntityKind::Code => format!("`{inner}`"),
+            EntityKind::Pre { language } => format!(
+                "```{}\n{inner}\n```",
+                language.as_deref().unwrap_or_default()
+            ),
+            EntityKind::Blockquote => quote_lines(inner, ">"),
+            EntityKind::ExpandableBlockquote => format!("**{}||", quote_lines(inner, ">")),
+            EntityKind::TextLink { url } => {
+                format!("[{inner}]({})", escape_markdown_v2_url(url))
+            }
+            EntityKind::TextMention { user_id, .. } => format!("[{inner}](tg://user?id={user_id})"),
+            EntityKind::CustomEmoji { custom_emoji_id } => {
+                format!("![{inner}](tg://emoji?id={custom_emoji_id})")
+            }
+            _ => inner.to_owned(),
+        },
+    }
+}
+
+fn quote_lines(inner: &str, prefix: &str) -> String {
+    inner
+        .split('\n')
+        .map(|line| format!("{prefix}{line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
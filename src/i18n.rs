@@ -0,0 +1,203 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::Ctx;
+
+/// Translations of messages into several languages, loaded from simple catalogs.
+///
+/// A catalog is a TOML file of `key = "text"` entries, where keys in a `[section]` are looked up as `section.key`. `{name}` in a text is replaced by the argument of that name.
+#[derive(Clone, Debug)]
+pub struct Localizer {
+    default_language: String,
+    catalogs: HashMap<String, HashMap<String, String>>,
+}
+
+impl Localizer {
+    /// Creates a localizer with no catalogs, falling back to the given language when the user's is not available.
+    pub fn new(default_language: &str) -> Self {
+        Self {
+            default_language: default_language.to_lowercase(),
+            catalogs: HashMap::new(),
+        }
+    }
+
+    /// Adds the catalog of a language, parsed from text.
+    pub fn catalog(mut self, language: &str, catalog: &str) -> anyhow::Result<Self> {
+        let entries = parse_catalog(catalog)
+            .with_context(|| format!("cannot parse catalog for {language}"))?;
+        self.catalogs
+            .entry(language.to_lowercase())
+            .or_default()
+            .extend(entries);
+        Ok(self)
+    }
+
+    /// Adds every `<language>.toml` catalog in a directory.
+    pub fn load_dir(mut self, dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        for entry in std::fs::read_dir(dir)
+            .with_context(|| format!("cannot read catalogs in {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                continue;
+            }
+            let Some(language) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let catalog = std::fs::read_to_string(&path)
+                .with_context(|| format!("cannot read catalog at {}", path.display()))?;
+            self = self.catalog(language, &catalog)?;
+        }
+        Ok(self)
+    }
+
+    /// Translates a message into a language, trying the language itself, its base language (`pt` for `pt-br`), then the default. Returns the key if no catalog has it.
+    pub fn translate(&self, language: &str, key: &str, args: &[(&str, &str)]) -> String {
+        let language = language.to_lowercase();
+        let base = language.split(['-', '_']).next().unwrap_or_default();
+        let text = [language.as_str(), base, &self.default_language]
+            .into_iter()
+            .find_map(|language| self.catalogs.get(language)?.get(key))
+            .map(String::as_str)
+            .unwrap_or(key);
+        let mut out = text.to_owned();
+        for (name, value) in args {
+            out = out.replace(&format!("{{{name}}}"), value);
+        }
+        out
+    }
+}
+
+// Parses a TOML catalog, joining the keys of nested tables with dots.
+fn parse_catalog(catalog: &str) -> anyhow::Result<HashMap<String, String>> {
+    let table: toml::Table = catalog.parse()?;
+    let mut entries = HashMap::new();
+    flatten_catalog("", table, &mut entries)?;
+    Ok(entries)
+}
+
+fn flatten_catalog(
+    prefix: &str,
+    table: toml::Table,
+    entries: &mut HashMap<String, String>,
+) -> anyhow::Result<()> {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            toml::Value::String(text) => {
+                entries.insert(key, text);
+            }
+            toml::Value::Table(table) => flatten_catalog(&key, table, entries)?,
+            other => anyhow::bail!("{key} is a {}, not text", other.type_str()),
+        }
+    }
+    Ok(())
+}
+
+fn language_key(user_id: i64) -> String {
+    format!("language/{user_id}")
+}
+
+impl Ctx {
+    /// The language to talk to the sender in: their override if set with [Ctx::set_language], otherwise the language of their Telegram client.
+    pub fn language(&self) -> String {
+        self.user_id()
+            .and_then(|user_id| {
+                self.bot
+                    .inner
+                    .store
+                    .get(&language_key(user_id))
+                    .inspect_err(|err| tracing::warn!("cannot load language override: {:?}", err))
+                    .ok()?
+            })
+            .and_then(|language| language.as_str().map(str::to_owned))
+            .or_else(|| {
+                self.message()["from"]["language_code"]
+                    .as_str()
+                    .map(str::to_owned)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Overrides the language used for the sender, or clears the override if None.
+    pub fn set_language(&self, language: Option<&str>) -> anyhow::Result<()> {
+        let user_id = self.user_id().context("message without a sender")?;
+        let store = &self.bot.inner.store;
+        match language {
+            Some(language) => store.set(&language_key(user_id), json!(language)),
+            None => store.remove(&language_key(user_id)),
+        }
+    }
+
+    /// Translates a message into the sender's language with the bot's [Localizer]. Returns the key if the bot has none.
+    pub fn t(&self, key: &str, args: &[(&str, &str)]) -> String {
+        match &self.bot.inner.localizer {
+            Some(localizer) => localizer.translate(&self.language(), key, args),
+            None => key.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn localizer() -> Localizer {
+        Localizer::new("EN")
+            .catalog(
+                "en",
+                "# greetings\nhello = \"Hello, {name}!\"\nbye = \"Bye\"\nlines = \"a\\nb\"",
+            )
+            .unwrap()
+            .catalog("pt", "hello = \"Olá, {name}!\"")
+            .unwrap()
+            .catalog("pt-BR", "bye = \"Tchau\"")
+            .unwrap()
+    }
+
+    #[test]
+    fn falls_back_to_the_base_then_the_default_language() {
+        let localizer = localizer();
+        assert_eq!(localizer.translate("pt-br", "bye", &[]), "Tchau");
+        assert_eq!(
+            localizer.translate("pt-BR", "hello", &[("name", "Ana")]),
+            "Olá, Ana!"
+        );
+        assert_eq!(
+            localizer.translate("de", "hello", &[("name", "Ann")]),
+            "Hello, Ann!"
+        );
+        assert_eq!(localizer.translate("", "bye", &[]), "Bye");
+        assert_eq!(localizer.translate("pt", "unknown", &[]), "unknown");
+    }
+
+    #[test]
+    fn parses_catalogs() {
+        assert_eq!(localizer().translate("en", "lines", &[]), "a\nb");
+        assert!(Localizer::new("en").catalog("en", "hello").is_err());
+        assert!(Localizer::new("en").catalog("en", "hello = Hi").is_err());
+        assert!(Localizer::new("en").catalog("en", "count = 3").is_err());
+    }
+
+    #[test]
+    fn looks_up_sections_with_dotted_keys() {
+        let localizer = Localizer::new("en")
+            .catalog(
+                "en",
+                "[menu]\nback = \"Back\"\n\n[menu.settings]\ntitle = \"Settings\"",
+            )
+            .unwrap();
+        assert_eq!(localizer.translate("en", "menu.back", &[]), "Back");
+        assert_eq!(
+            localizer.translate("en", "menu.settings.title", &[]),
+            "Settings"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::BotHandle;
+
+/// The most results Telegram accepts in one answer to an inline query.
+const MAX_RESULTS: usize = 50;
+
+/// A button shown above inline results.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InlineQueryButton {
+    /// Opens a Web App at the URL.
+    WebApp { text: String, url: String },
+    /// Opens a private chat with the bot, sending `/start` with the parameter, such as to log in before using the bot inline.
+    Start {
+        text: String,
+        start_parameter: String,
+    },
+}
+
+impl InlineQueryButton {
+    fn to_json(&self) -> Value {
+        match self {
+            Self::WebApp { text, url } => json!({"text": text, "web_app": {"url": url}}),
+            Self::Start {
+                text,
+                start_parameter,
+            } => json!({"text": text, "start_parameter": start_parameter}),
+        }
+    }
+}
+
+/// How Telegram caches and presents the answer to an inline query.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InlineAnswerOptions {
+    /// How long Telegram may serve the same results for the same query. Telegram's default is 5 minutes.
+    pub cache_time: Option<Duration>,
+    /// Caches results per user instead of for everyone, for personalized results.
+    pub is_personal: bool,
+    pub button: Option<InlineQueryButton>,
+}
+
+impl InlineAnswerOptions {
+    fn apply(&self, args: &mut Value) {
+        if let Some(cache_time) = self.cache_time {
+            args["cache_time"] = json!(cache_time.as_secs());
+        }
+        if self.is_personal {
+            args["is_personal"] = json!(true);
+        }
+        if let Some(button) = &self.button {
+            args["button"] = button.to_json();
+        }
+    }
+}
+
+/// An inline query, given to the handler set with [crate::TelegramBotBuilder::on_inline_query].
+#[derive(Clone)]
+pub struct InlineQuery {
+    /// The `inline_query` object of the update.
+    pub query: Value,
+    pub bot: BotHandle,
+    pub(crate) options: InlineAnswerOptions,
+}
+
+impl InlineQuery {
+    /// Sets how answers to this query are cached and presented.
+    pub fn with_options(mut self, options: InlineAnswerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The ID to answer the query with.
+    pub fn id(&self) -> &str {
+        self.query["id"].as_str().unwrap_or_default()
+    }
+
+    /// The text the user typed after the bot's username.
+    pub fn text(&self) -> &str {
+        self.query["query"].as_str().unwrap_or_default()
+    }
+
+    /// The offset of the page of results the user scrolled to, empty for the first page.
+    pub fn offset(&self) -> &str {
+        self.query["offset"].as_str().unwrap_or_default()
+    }
+
+    /// The ID of the user who sent the query.
+    pub fn user_id(&self) -> Option<i64> {
+        self.query["from"]["id"].as_i64()
+    }
+
+    /// Answers the query with results, as raw JSON `InlineQueryResult` objects, and no more pages.
+    pub async fn answer(&self, results: Vec<Value>) -> anyhow::Result<()> {
+        self.answer_page(results, "").await
+    }
+
+    /// Answers the query with one page of results, fetched with the position of the first result and the page size. Telegram asks for the next page, as another query, when the user scrolls to the end; a page shorter than the page size is the last.
+    pub async fn answer_paged<
+        Fun: FnOnce(usize, usize) -> Fut,
+        Fut: std::future::Future<Output = anyhow::Result<Vec<Value>>>,
+    >(
+        &self,
+        page_size: usize,
+        fetch: Fun,
+    ) -> anyhow::Result<()> {
+        let page_size = page_size.clamp(1, MAX_RESULTS);
+        let start: usize = match self.offset() {
+            "" => 0,
+            offset => offset
+                .parse()
+                .with_context(|| format!("invalid inline query offset {offset:?}"))?,
+        };
+        let mut results = fetch(start, page_size).await?;
+        results.truncate(page_size);
+        let next_offset = if results.len() == page_size {
+            (start + page_size).to_string()
+        } else {
+            String::new()
+        };
+        self.answer_page(results, &next_offset).await
+    }
+
+    async fn answer_page(&self, results: Vec<Value>, next_offset: &str) -> anyhow::Result<()> {
+        let mut args = json!({
+            "inline_query_id": self.id(),
+            "results": results,
+            "next_offset": next_offset,
+        });
+        self.options.apply(&mut args);
+        self.bot
+            .call_api("answerInlineQuery", args)
+            .await
+            .context("cannot answer inline query")?;
+        Ok(())
+    }
+}
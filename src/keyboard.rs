@@ -0,0 +1,56 @@
+use serde_json::{json, Value};
+
+/// A builder for an inline keyboard, the buttons attached below a message.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InlineKeyboard {
+    rows: Vec<Vec<Value>>,
+}
+
+impl InlineKeyboard {
+    /// Creates a keyboard with no buttons.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new row. Buttons are added to the last row.
+    pub fn row(mut self) -> Self {
+        self.rows.push(vec![]);
+        self
+    }
+
+    /// Adds a button that sends a callback query with the given data when pressed.
+    pub fn callback(self, text: &str, data: &str) -> Self {
+        self.button(json!({"text": text, "callback_data": data}))
+    }
+
+    /// Adds a button that opens a URL.
+    pub fn url(self, text: &str, url: &str) -> Self {
+        self.button(json!({"text": text, "url": url}))
+    }
+
+    /// Adds a button that copies the given text, such as a promo code, to the clipboard when pressed.
+    pub fn copy_text(self, text: &str, to_copy: &str) -> Self {
+        self.button(json!({"text": text, "copy_text": {"text": to_copy}}))
+    }
+
+    /// Adds an arbitrary button, as raw JSON.
+    pub fn button(mut self, button: Value) -> Self {
+        match self.rows.last_mut() {
+            Some(row) => row.push(button),
+            None => self.rows.push(vec![button]),
+        }
+        self
+    }
+
+    /// The keyboard as a `reply_markup` value.
+    pub fn to_json(&self) -> Value {
+        let rows: Vec<&Vec<Value>> = self.rows.iter().filter(|row| !row.is_empty()).collect();
+        json!({ "inline_keyboard": rows })
+    }
+}
+
+impl From<InlineKeyboard> for Value {
+    fn from(keyboard: InlineKeyboard) -> Self {
+        keyboard.to_json()
+    }
+}
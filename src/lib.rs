@@ -1,146 +1,1667 @@
-use std::{future::Future, time::Duration};
+use std::{
+    any::Any,
+    future::Future,
+    net::IpAddr,
+    panic::AssertUnwindSafe,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
-use isahc::{AsyncReadResponseExt, HttpClient, Request};
-use serde_json::{json, Value};
-use smol::Task;
+use serde_json::{json, Map, Value};
+use smol::{
+    future::{Boxed, FutureExt},
+    lock::Semaphore,
+    Task,
+};
 use smol_timeout::TimeoutExt;
+use source::UpdateSource;
+use status::QueuedResponses;
+use store::{MemoryStore, Store};
+use tracing::Instrument;
+use transport::{HttpOptions, Transport};
+pub use transport::{HttpVersion, IpFamily};
+pub use unreachable::{Unreachable, UnreachableReason};
+
+use crate::{commands::Command, outgoing::ApiRequest, transport::Upload};
+
+mod api_version;
+mod batch;
+mod business;
+pub mod callback;
+pub mod chat_info;
+mod chats;
+pub mod checklist;
+pub mod commands;
+mod ctx;
+mod dead_letter;
+mod dedup;
+pub mod entities;
+mod ephemeral;
+pub mod events;
+pub mod extract;
+mod file_cache;
+mod files;
+pub mod format;
+#[cfg(feature = "i18n")]
+pub mod i18n;
+pub mod inline;
+pub mod keyboard;
+mod limits;
+pub mod links;
+pub mod live_test;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod migration;
+pub mod mini_app;
+pub mod mock;
+mod moderation;
+mod outbox;
+pub mod outgoing;
+pub mod passport;
+mod pause;
+mod pool;
+mod processed;
+pub mod reply;
+mod sha256;
+pub mod source;
+pub mod stars;
+mod stats;
+mod status;
+pub mod store;
+pub mod stories;
+pub mod streaming;
+#[cfg(feature = "templates")]
+pub mod templates;
+pub mod transport;
+mod unreachable;
+mod upsert;
+mod verification;
+pub mod webhook;
+
+pub use api_version::ApiVersion;
+pub use ctx::{BotHandle, Ctx};
+pub use dead_letter::DeadLetter;
+pub use files::DownloadOptions;
+pub use moderation::parse_duration;
+pub use outgoing::OutgoingMessage;
+pub use pool::BotPool;
+pub use stats::ChatStats;
+pub use status::{BotStatus, UpdateCounts};
+
+/// How many chats the responses to a single update are sent to at once.
+const MAX_CONCURRENT_SENDS: usize = 8;
+
+type ErrorHandler = Arc<dyn Fn(Value, anyhow::Error) -> Boxed<()> + Send + Sync>;
+type InlineHandler = Box<dyn Fn(inline::InlineQuery) -> Boxed<anyhow::Result<()>> + Send + Sync>;
+type CallbackHandler =
+    Box<dyn Fn(callback::CallbackQuery) -> Boxed<anyhow::Result<()>> + Send + Sync>;
+type ServiceHandler =
+    Box<dyn Fn(events::ServiceMessage, Ctx) -> Boxed<anyhow::Result<()>> + Send + Sync>;
+type RawHandler = Box<dyn Fn(Value, BotHandle) -> Boxed<anyhow::Result<()>> + Send + Sync>;
+type RequestHook = Box<dyn Fn(&str, &mut Value) -> Option<anyhow::Result<Value>> + Send + Sync>;
+type ResponseHook = Box<dyn Fn(&str, &Value, Duration, &anyhow::Result<Value>) + Send + Sync>;
+type UnreachableHook = Box<dyn Fn(i64, UnreachableReason) + Send + Sync>;
+type MigrationHook = Box<dyn Fn(i64, i64) + Send + Sync>;
+type LagHook = Box<dyn Fn(Duration) + Send + Sync>;
+
+/// Update types Telegram sends when `allowed_updates` is left empty. Only used when the bot's current set is unknown.
+const DEFAULT_UPDATE_TYPES: &[&str] = &[
+    "message",
+    "edited_message",
+    "channel_post",
+    "edited_channel_post",
+    "business_connection",
+    "business_message",
+    "edited_business_message",
+    "deleted_business_messages",
+    "inline_query",
+    "chosen_inline_result",
+    "callback_query",
+    "shipping_query",
+    "pre_checkout_query",
+    "purchased_paid_media",
+    "poll",
+    "poll_answer",
+    "my_chat_member",
+    "chat_join_request",
+    "chat_boost",
+    "removed_chat_boost",
+];
 
 /// A client of the Telegram bot API.
 pub struct TelegramBot {
-    client: HttpClient,
-    bot_token: String,
+    inner: Arc<Inner>,
     _task: Task<()>,
 }
+
+// State shared between a TelegramBot and its update-handling task.
+struct Inner {
+    transport: Arc<dyn Transport>,
+    bot_token: RwLock<Arc<str>>,
+    resolve_cache: chats::ResolveCache,
+    member_cache: Option<chats::MemberCache>,
+    dedup: Option<dedup::DedupWindow>,
+    store: Arc<dyn Store>,
+    debug_logging: bool,
+    file_id_cache: bool,
+    remember_processed: bool,
+    report_unmodified: bool,
+    api_version: Option<ApiVersion>,
+    upload_limit: usize,
+    timeouts: Timeouts,
+    on_request: Option<RequestHook>,
+    on_response: Option<ResponseHook>,
+    on_unreachable: Option<UnreachableHook>,
+    on_chat_migrated: Option<MigrationHook>,
+    status: status::StatusTracker,
+    update_counts: status::UpdateCounter,
+    pause: pause::PauseSwitch,
+    deletions: ephemeral::DeletionQueue,
+    redrive: dead_letter::RedriveQueue,
+    outbox_pruned: outbox::PruneClock,
+    #[cfg(feature = "metrics")]
+    metrics: metrics::Metrics,
+    #[cfg(feature = "i18n")]
+    localizer: Option<i18n::Localizer>,
+}
+
+// How long API calls may take, by kind of call.
+#[derive(Clone, Copy, Debug)]
+struct Timeouts {
+    poll: Duration,
+    call: Duration,
+    transfer: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            poll: Duration::from_secs(source::LONG_POLL_SECS + 30),
+            call: Duration::from_secs(30),
+            transfer: Duration::from_secs(600),
+        }
+    }
+}
+
+impl Timeouts {
+    fn for_call(&self, method: &str, uploads: &[Upload]) -> Duration {
+        if method == "getUpdates" {
+            self.poll
+        } else if uploads.is_empty() {
+            self.call
+        } else {
+            self.transfer
+        }
+    }
+}
+
+/// An error returned by the Telegram API.
+#[derive(Clone, Debug)]
+pub struct ApiError {
+    pub error_code: i64,
+    pub description: String,
+    /// The `parameters` field of the error, like `retry_after` or `migrate_to_chat_id`, if any.
+    pub parameters: Value,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "telegram failed with error code {}: {}",
+            self.error_code, self.description
+        )
+    }
+}
+
+impl std::error::Error for ApiError {}
+
 pub struct Response {
     pub text: String,
     pub chat_id: i64,
     pub reply_to_message_id: Option<i64>,
 }
 
+/// A builder for a TelegramBot, for options beyond the bot token and message handler.
+pub struct TelegramBotBuilder {
+    bot_token: String,
+    on_error: Option<ErrorHandler>,
+    on_inline_query: Option<InlineHandler>,
+    inline_options: inline::InlineAnswerOptions,
+    on_callback_query: Option<CallbackHandler>,
+    on_service_message: Option<ServiceHandler>,
+    on_unhandled_update: Option<RawHandler>,
+    handler_timeout: Duration,
+    update_queue_len: usize,
+    at_least_once: bool,
+    max_attempts: u32,
+    dead_letters: bool,
+    member_cache_ttl: Option<Duration>,
+    dedup_window: Option<Duration>,
+    lag_threshold: Option<Duration>,
+    on_lag: Option<LagHook>,
+    store: Arc<dyn Store>,
+    chat_stats: bool,
+    stats_command: bool,
+    debug_logging: bool,
+    file_id_cache: bool,
+    remember_processed: bool,
+    report_unmodified: bool,
+    api_version: Option<ApiVersion>,
+    upload_limit: usize,
+    timeouts: Timeouts,
+    on_request: Option<RequestHook>,
+    on_response: Option<ResponseHook>,
+    on_unreachable: Option<UnreachableHook>,
+    on_chat_migrated: Option<MigrationHook>,
+    transport: Option<Arc<dyn Transport>>,
+    source: Option<Box<dyn UpdateSource>>,
+    record_path: Option<PathBuf>,
+    http: HttpOptions,
+    #[cfg(feature = "i18n")]
+    localizer: Option<i18n::Localizer>,
+}
+
+impl TelegramBotBuilder {
+    /// Sets a callback that is called with the offending update whenever the message handler, or sending its responses, fails.
+    pub fn on_error<
+        Fun: Fn(Value, anyhow::Error) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    >(
+        mut self,
+        on_error: Fun,
+    ) -> Self {
+        self.on_error = Some(Arc::new(move |update, err| Box::pin(on_error(update, err))));
+        self
+    }
+
+    /// Sets a handler for inline queries, which otherwise go unanswered. It may take as long as the message handler, and its failures go to the error callback too.
+    pub fn on_inline_query<
+        Fun: Fn(inline::InlineQuery) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    >(
+        mut self,
+        handler: Fun,
+    ) -> Self {
+        self.on_inline_query = Some(Box::new(move |query| Box::pin(handler(query))));
+        self
+    }
+
+    /// Sets how answers to inline queries are cached and presented, unless a handler overrides it with [inline::InlineQuery::with_options].
+    pub fn inline_answer_options(mut self, options: inline::InlineAnswerOptions) -> Self {
+        self.inline_options = options;
+        self
+    }
+
+    /// Sets a handler for presses of inline keyboard buttons. It may take as long as the message handler, and its failures go to the error callback too.
+    pub fn on_callback_query<
+        Fun: Fn(callback::CallbackQuery) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    >(
+        mut self,
+        handler: Fun,
+    ) -> Self {
+        self.on_callback_query = Some(Box::new(move |query| Box::pin(handler(query))));
+        self
+    }
+
+    /// Sets a handler for service messages, such as pins or title changes, which carry no text and so never reach the message handler.
+    pub fn on_service_message<
+        Fun: Fn(events::ServiceMessage, Ctx) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    >(
+        mut self,
+        handler: Fun,
+    ) -> Self {
+        self.on_service_message = Some(Box::new(move |service, ctx| {
+            Box::pin(handler(service, ctx))
+        }));
+        self
+    }
+
+    /// Sets a handler for updates nothing else handles, such as update types this crate does not know yet, given as raw JSON. Its failures go to the error callback too.
+    pub fn on_unhandled_update<
+        Fun: Fn(Value, BotHandle) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    >(
+        mut self,
+        handler: Fun,
+    ) -> Self {
+        self.on_unhandled_update =
+            Some(Box::new(move |update, bot| Box::pin(handler(update, bot))));
+        self
+    }
+
+    /// Sets how long the message handler may take to handle a single update, including sending its responses. Defaults to 300 seconds.
+    pub fn handler_timeout(mut self, timeout: Duration) -> Self {
+        self.handler_timeout = timeout;
+        self
+    }
+
+    /// Sets how many received updates may wait for a handler. Once that many are waiting, the bot stops fetching more until handlers catch up. Defaults to 100, the most a single getUpdates returns.
+    ///
+    /// Fetching ahead tells Telegram that queued updates were processed before they are handled, so a crash loses every update still in the queue, up to this many plus a batch. Use [TelegramBotBuilder::at_least_once] to have them delivered again instead.
+    pub fn update_queue(mut self, len: usize) -> Self {
+        self.update_queue_len = len.max(1);
+        self
+    }
+
+    /// Processes every update at least once: a failing update is handled again, up to the given number of attempts in all, and Telegram is only told an update was processed once it was handled, so updates in flight when the bot crashes are delivered again on restart. Fetching then waits for each batch to be handled. Handlers may see an update twice, so they should be idempotent, for example with [outgoing::SendOptions::idempotency_key].
+    ///
+    /// An update that fails every attempt is only acknowledged once it is kept with [TelegramBotBuilder::dead_letters]. Without dead letters, or if storing it fails, the bot stops fetching updates, and Telegram delivers the update again once the bot restarts.
+    pub fn at_least_once(mut self, max_attempts: u32) -> Self {
+        self.at_least_once = true;
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Keeps updates whose handler failed, after all attempts, in the store along with the error, so that they can be inspected with [TelegramBot::dead_letters] and handled again with [TelegramBot::redrive_dead_letters]. Use a persistent store, like [store::JsonFileStore], for them to survive restarts.
+    pub fn dead_letters(mut self) -> Self {
+        self.dead_letters = true;
+        self
+    }
+
+    /// Remembers which updates were processed since Telegram was last told they were handled, and skips them when Telegram delivers them again after a restart, so that commands with side effects don't run twice. Use a persistent store, like [store::JsonFileStore], since the default store is lost on restart anyway.
+    pub fn remember_processed_updates(mut self) -> Self {
+        self.remember_processed = true;
+        self
+    }
+
+    /// Suppresses sending a message identical to one sent to the same chat within the window, returning the earlier message instead. This keeps a handler stuck in a retry loop from spamming users.
+    pub fn dedup_window(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Enables caching getChatMember results for the given time. The cache is also updated from `chat_member` updates, which Telegram only sends for chats the bot administers.
+    ///
+    /// Telegram only sends `chat_member` updates when asked for them by name, so the bot polls for the update types it already receives plus `chat_member`. If it receives the default types, those are taken from a list in this crate, and update types added to the API after it was written are not received.
+    pub fn chat_member_cache(mut self, ttl: Duration) -> Self {
+        self.member_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the store for state that should outlive a single update. Defaults to a [MemoryStore].
+    pub fn store(mut self, store: impl Store + 'static) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Enables recording per-chat statistics in the store, available through [TelegramBot::chat_stats].
+    pub fn chat_stats(mut self) -> Self {
+        self.chat_stats = true;
+        self
+    }
+
+    /// Enables recording per-chat statistics and replying to `/stats` with them, without calling the message handler.
+    pub fn stats_command(mut self) -> Self {
+        self.chat_stats = true;
+        self.stats_command = true;
+        self
+    }
+
+    /// Enables logging every API request payload and raw response at debug level. The bot token is never logged.
+    pub fn debug_logging(mut self) -> Self {
+        self.debug_logging = true;
+        self
+    }
+
+    /// Enables remembering the file_id of every uploaded file in the store, keyed by a hash of its content, and sending that instead when the same content is uploaded again.
+    pub fn file_id_cache(mut self) -> Self {
+        self.file_id_cache = true;
+        self
+    }
+
+    /// Makes [TelegramBot::edit] and [TelegramBot::upsert] fail with Telegram's "message is not modified" error when a message is edited to what it already says, instead of treating that as success.
+    pub fn report_unmodified_edits(mut self) -> Self {
+        self.report_unmodified = true;
+        self
+    }
+
+    /// Sets a hook called before every API call with the method name and arguments. It may modify the arguments, or return a result to use instead of calling Telegram.
+    pub fn on_request(
+        mut self,
+        hook: impl Fn(&str, &mut Value) -> Option<anyhow::Result<Value>> + Send + Sync + 'static,
+    ) -> Self {
+        self.on_request = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets a hook called after every API call with the method name, arguments, latency, and result.
+    pub fn on_response(
+        mut self,
+        hook: impl Fn(&str, &Value, Duration, &anyhow::Result<Value>) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_response = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets a callback called with the chat ID whenever sending to a chat fails because it blocked or removed the bot, so that it can be dropped from subscriber lists. Such sends fail with an [Unreachable] error.
+    pub fn on_unreachable(
+        mut self,
+        hook: impl Fn(i64, UnreachableReason) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_unreachable = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets a callback called with the old and new chat IDs when a group becomes a supergroup, so that stored chat IDs can be updated. Sessions move to the new ID automatically, and sends to the old ID are redirected.
+    pub fn on_chat_migrated(mut self, hook: impl Fn(i64, i64) + Send + Sync + 'static) -> Self {
+        self.on_chat_migrated = Some(Box::new(hook));
+        self
+    }
+
+    /// Logs a warning whenever the bot gets to an update more than the threshold after it was sent, which means it has fallen behind. The current lag is always in [TelegramBot::status].
+    pub fn lag_threshold(mut self, threshold: Duration) -> Self {
+        self.lag_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets a callback called with the lag whenever it exceeds the [TelegramBotBuilder::lag_threshold], for alerting.
+    pub fn on_lag(mut self, hook: impl Fn(Duration) + Send + Sync + 'static) -> Self {
+        self.on_lag = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets how API calls reach Telegram, such as a [mock::MockTelegram] in tests. Defaults to HTTPS.
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Gets updates from the given source, such as a [source::ReplaySource], instead of polling Telegram.
+    pub fn update_source(mut self, source: impl UpdateSource + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Appends every received update, as raw JSON, to the given file, one per line. The file can be replayed with [source::ReplaySource].
+    pub fn record_updates(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record_path = Some(path.into());
+        self
+    }
+
+    /// Sets how many connections to Telegram may be open at once. Defaults to 4.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.http.max_connections = max;
+        self
+    }
+
+    /// Sets how long connecting to Telegram may take.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.http.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Enables TCP keepalive probes at the given interval on connections to Telegram.
+    pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.http.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Sets how long a getUpdates long poll may take before it is abandoned and retried. Defaults to 150 seconds; values too short to outlast Telegram's 120-second long poll are raised to 125 seconds.
+    pub fn poll_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.poll = timeout.max(Duration::from_secs(source::LONG_POLL_SECS + 5));
+        self
+    }
+
+    /// Sets how long an ordinary API call may take. Defaults to 30 seconds.
+    pub fn call_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.call = timeout;
+        self
+    }
+
+    /// Sets how long an API call uploading files, or a file download, may take. Defaults to 10 minutes.
+    pub fn transfer_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.transfer = timeout;
+        self
+    }
+
+    /// Declares the Bot API version of the server, such as an outdated self-hosted one. Calls to methods added after it then fail without reaching the server, and parameters added after it are dropped with a warning. By default, everything is sent as is.
+    pub fn api_version(mut self, version: ApiVersion) -> Self {
+        self.api_version = Some(version);
+        self
+    }
+
+    /// Sets the largest file that may be uploaded, in bytes, above which calls fail without reaching the server. Defaults to Telegram's limit of 50 MB; a local Bot API server allows up to 2000 MB.
+    pub fn upload_limit(mut self, bytes: usize) -> Self {
+        self.upload_limit = bytes;
+        self
+    }
+
+    /// Sets which HTTP versions may be used to talk to Telegram.
+    pub fn http_version(mut self, version: HttpVersion) -> Self {
+        self.http.http_version = version;
+        self
+    }
+
+    /// Sets which IP address families may be used to reach Telegram.
+    pub fn ip_family(mut self, family: IpFamily) -> Self {
+        self.http.ip_family = family;
+        self
+    }
+
+    /// Makes the default transport a [transport::RustlsTransport] even when the curl one is compiled in. Builds without the `curl` feature always use it. It fails to build with options that only curl supports, like [TelegramBotBuilder::dns_over_https].
+    #[cfg(feature = "rustls")]
+    pub fn rustls(mut self) -> Self {
+        self.http.rustls = true;
+        self
+    }
+
+    /// Sets the translations used by [Ctx::t].
+    #[cfg(feature = "i18n")]
+    pub fn localizer(mut self, localizer: i18n::Localizer) -> Self {
+        self.localizer = Some(localizer);
+        self
+    }
+
+    /// Connects to Telegram at the given address instead of resolving its domain, for networks where DNS is poisoned. TLS still verifies api.telegram.org, or the front domain if set.
+    pub fn resolve_to(mut self, addr: IpAddr) -> Self {
+        self.http.resolve_to = Some(addr);
+        self
+    }
+
+    /// Looks up Telegram's address with DNS-over-HTTPS from the given provider, like [transport::DohResolver::CLOUDFLARE], instead of the system's DNS.
+    ///
+    /// Only IPv4 addresses are looked up unless [TelegramBotBuilder::ip_family] is [IpFamily::V6Only].
+    pub fn dns_over_https(mut self, provider_url: &str) -> Self {
+        self.http.doh_url = Some(provider_url.to_owned());
+        self
+    }
+
+    /// Reaches Telegram through a domain fronting it, connecting to and sending the front domain as the TLS server name while asking for api.telegram.org in the Host header.
+    pub fn front_domain(mut self, domain: &str) -> Self {
+        self.http.front_domain = Some(domain.to_owned());
+        self
+    }
+
+    /// Trusts only the CA certificates in the given PEM bundle when connecting to Telegram, pinning them instead of the system's.
+    pub fn ca_certificate(mut self, path: impl Into<PathBuf>) -> Self {
+        self.http.ca_certificate = Some(path.into());
+        self
+    }
+
+    /// Makes the default transport use Telegram's test environment, for developing payments and Web Apps. Test bots have their own tokens, issued by @BotFather in the test environment.
+    pub fn test_environment(mut self) -> Self {
+        self.http.test_environment = true;
+        self
+    }
+
+    /// Builds the TelegramBot, starting to handle updates with the given message handler.
+    pub fn build<
+        Fun: FnMut(Value) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<R>>> + Send + 'static,
+        R: Into<OutgoingMessage> + Send + 'static,
+    >(
+        mut self,
+        msg_handler: Fun,
+    ) -> TelegramBot {
+        let inner = self.start();
+        TelegramBot {
+            inner: inner.clone(),
+            _task: smol::spawn(handle_telegram(inner, self, msg_handler)),
+        }
+    }
+
+    /// Like [TelegramBotBuilder::build], but first checks that the token is well-formed and that Telegram accepts it, by calling getMe.
+    pub async fn build_verified<
+        Fun: FnMut(Value) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<R>>> + Send + 'static,
+        R: Into<OutgoingMessage> + Send + 'static,
+    >(
+        mut self,
+        msg_handler: Fun,
+    ) -> anyhow::Result<TelegramBot> {
+        validate_token(&self.bot_token)?;
+        let inner = self.start();
+        inner.call_api("getMe", json!({})).await.map_err(|err| {
+            match err.downcast_ref::<ApiError>() {
+                Some(api_err) if api_err.error_code == 401 || api_err.error_code == 404 => {
+                    anyhow::anyhow!(
+                        "bot token was rejected by telegram: {}",
+                        api_err.description
+                    )
+                }
+                _ => err.context("cannot check bot token with getMe"),
+            }
+        })?;
+        Ok(TelegramBot {
+            inner: inner.clone(),
+            _task: smol::spawn(handle_telegram(inner, self, msg_handler)),
+        })
+    }
+
+    /// Builds the TelegramBot, starting to handle updates with a [commands::CommandRouter]. Unless the router was given its own store, it remembers cooldowns in the bot's store.
+    pub fn build_with_router(self, mut router: commands::CommandRouter) -> TelegramBot {
+        router.store_if_unset(self.store.clone());
+        self.build(router.into_handler())
+    }
+
+    /// Builds the TelegramBot, starting to handle updates with a handler that is given a [Ctx] and replies through it.
+    pub fn build_with_ctx<
+        Fun: FnMut(Ctx) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    >(
+        mut self,
+        mut handler: Fun,
+    ) -> TelegramBot {
+        let inner = self.start();
+        let bot = BotHandle {
+            inner: inner.clone(),
+        };
+        let msg_handler = move |update| {
+            let handled = handler(Ctx {
+                update,
+                bot: bot.clone(),
+            });
+            async move {
+                handled.await?;
+                Ok(Vec::<OutgoingMessage>::new())
+            }
+        };
+        TelegramBot {
+            inner: inner.clone(),
+            _task: smol::spawn(handle_telegram(inner, self, msg_handler)),
+        }
+    }
+
+    // Creates the state shared with the update-handling task, taking the options it needs.
+    fn start(&mut self) -> Arc<Inner> {
+        let transport = self
+            .transport
+            .take()
+            .unwrap_or_else(|| self.http.build_transport().unwrap());
+        Arc::new(Inner {
+            transport,
+            bot_token: RwLock::new(self.bot_token.as_str().into()),
+            resolve_cache: Default::default(),
+            member_cache: self.member_cache_ttl.map(chats::MemberCache::new),
+            dedup: self.dedup_window.map(dedup::DedupWindow::new),
+            store: self.store.clone(),
+            debug_logging: self.debug_logging,
+            file_id_cache: self.file_id_cache,
+            remember_processed: self.remember_processed,
+            report_unmodified: self.report_unmodified,
+            api_version: self.api_version,
+            upload_limit: self.upload_limit,
+            timeouts: self.timeouts,
+            on_request: self.on_request.take(),
+            on_response: self.on_response.take(),
+            on_unreachable: self.on_unreachable.take(),
+            on_chat_migrated: self.on_chat_migrated.take(),
+            status: Default::default(),
+            update_counts: Default::default(),
+            pause: Default::default(),
+            deletions: Default::default(),
+            redrive: Default::default(),
+            outbox_pruned: Default::default(),
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
+            #[cfg(feature = "i18n")]
+            localizer: self.localizer.take(),
+        })
+    }
+}
+
 impl TelegramBot {
     /// Creates a new TelegramBot.
     pub fn new<
         Fun: FnMut(Value) -> Fut + Send + 'static,
-        Fut: Future<Output = anyhow::Result<Vec<Response>>> + Send + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<R>>> + Send + 'static,
+        R: Into<OutgoingMessage> + Send + 'static,
     >(
         bot_token: &str,
         msg_handler: Fun,
     ) -> Self {
-        let client = isahc::HttpClientBuilder::new()
-            .max_connections(4)
-            .build()
-            .unwrap();
-        Self {
-            client: client.clone(),
+        Self::builder(bot_token).build(msg_handler)
+    }
+
+    /// Creates a builder for a TelegramBot with more options.
+    pub fn builder(bot_token: &str) -> TelegramBotBuilder {
+        TelegramBotBuilder {
             bot_token: bot_token.into(),
-            _task: smol::spawn(handle_telegram(client, bot_token.to_owned(), msg_handler)),
+            on_error: None,
+            on_inline_query: None,
+            inline_options: Default::default(),
+            on_callback_query: None,
+            on_service_message: None,
+            on_unhandled_update: None,
+            handler_timeout: Duration::from_secs(300),
+            update_queue_len: 100,
+            at_least_once: false,
+            max_attempts: 1,
+            dead_letters: false,
+            member_cache_ttl: None,
+            dedup_window: None,
+            lag_threshold: None,
+            on_lag: None,
+            store: Arc::new(MemoryStore::new()),
+            chat_stats: false,
+            stats_command: false,
+            debug_logging: false,
+            file_id_cache: false,
+            remember_processed: false,
+            report_unmodified: false,
+            api_version: None,
+            upload_limit: limits::MAX_UPLOAD_SIZE,
+            timeouts: Default::default(),
+            on_request: None,
+            on_response: None,
+            on_unreachable: None,
+            on_chat_migrated: None,
+            transport: None,
+            source: None,
+            record_path: None,
+            http: Default::default(),
+            #[cfg(feature = "i18n")]
+            localizer: None,
         }
     }
 
+    /// Calls an arbitrary Telegram bot API method, returning its result.
+    pub async fn call_api(&self, method: &str, args: Value) -> anyhow::Result<Value> {
+        self.inner.call_api(method, args).await
+    }
+
+    /// Replaces the bot token, such as after revoking the old one. Calls already in flight finish with the old token; every later call, including the next getUpdates, uses the new one. Fails if the new token is malformed.
+    pub fn set_token(&self, bot_token: &str) -> anyhow::Result<()> {
+        validate_token(bot_token)?;
+        *self.inner.bot_token.write().unwrap() = bot_token.into();
+        Ok(())
+    }
+
+    /// Metrics about this bot, in a form that can be served to Prometheus. The queue depth gauges are taken from [TelegramBot::status] when this is called.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &metrics::Metrics {
+        self.inner.metrics.record_status(&self.inner.status.get());
+        &self.inner.metrics
+    }
+
     pub async fn send_msg(&self, to_send: Response) -> anyhow::Result<()> {
-        call_api(
-            &self.client,
-            &self.bot_token,
-            "sendMessage",
-            resp_json(&to_send),
-        )
-        .await
-        .context("cannot send reply back to telegram")?;
+        self.send(to_send).await?;
         Ok(())
     }
+
+    /// Sends a message of any kind, returning the sent message. If the message has an [outgoing::SendOptions::idempotency_key] it was already sent under in the last day, the message recorded then is returned instead.
+    pub async fn send(&self, msg: impl Into<OutgoingMessage>) -> anyhow::Result<Value> {
+        self.inner.send(msg.into()).await
+    }
 }
 
 async fn handle_telegram<
     Fun: FnMut(Value) -> Fut + Send,
-    Fut: Future<Output = anyhow::Result<Vec<Response>>>,
+    Fut: Future<Output = anyhow::Result<Vec<R>>>,
+    R: Into<OutgoingMessage>,
 >(
-    client: HttpClient,
-    bot_token: String,
+    inner: Arc<Inner>,
+    mut opts: TelegramBotBuilder,
     mut msg_handler: Fun,
 ) {
-    let mut counter = 0;
+    let _deleter = smol::spawn({
+        let inner = inner.clone();
+        async move { ephemeral::delete_expired(&inner).await }
+    });
+    let allowed_updates = if inner.member_cache.is_some() && opts.source.is_none() {
+        member_update_types(&inner).await
+    } else {
+        vec![]
+    };
+    let recording = opts.record_path.as_ref().and_then(|path| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .inspect_err(|err| tracing::error!("cannot open update recording: {:?}", err))
+            .ok()
+    });
+    // fetching runs ahead of handling, so the next getUpdates is already waiting when a handler finishes
+    let (queue_send, queue_recv) = smol::channel::bounded(opts.update_queue_len);
+    // with at-least-once processing, the fetcher only acknowledges updates to Telegram once they are handled
+    let (handled_send, handled_recv) = smol::channel::unbounded();
+    let source = opts.source.take();
+    // other sources have no acknowledgements to hold back
+    let handled_recv = (opts.at_least_once && source.is_none()).then_some(handled_recv);
+    let _fetcher = smol::spawn(source::fetch_updates(
+        inner.clone(),
+        source,
+        recording,
+        allowed_updates,
+        queue_send,
+        handled_recv,
+    ));
+    // set once an update is dropped without being dead-lettered, which must not be acknowledged
+    let mut halted = false;
+    while let Ok(update) = queue_recv.recv().or(inner.redrive.recv.recv()).await {
+        inner
+            .status
+            .update(|status| status.queued_updates = queue_recv.len());
+        let update_id = update["update_id"].as_i64();
+        if update_id.is_some_and(|update_id| inner.already_processed(update_id)) {
+            tracing::info!("skipping update {update_id:?}, which was already processed");
+        } else {
+            let span = update_span(&update);
+            let settled = handle_update(&inner, &opts, &mut msg_handler, update)
+                .instrument(span)
+                .await;
+            let dropped = !settled && opts.at_least_once;
+            if dropped && !halted {
+                // leaving the update unacknowledged makes Telegram deliver it again on restart
+                tracing::error!(
+                    "update {update_id:?} failed every attempt and was not dead-lettered, so no more updates are fetched until the bot restarts"
+                );
+                halted = true;
+            }
+            if let (Some(update_id), false) = (update_id, dropped) {
+                inner.record_processed(update_id);
+            }
+        }
+        if opts.at_least_once && !halted {
+            let _ = handled_send.try_send(update_id.unwrap_or_default());
+        }
+    }
+}
+
+// The update types to poll for so that chat_member updates arrive: the ones the bot already receives, plus chat_member.
+async fn member_update_types(inner: &Inner) -> Vec<String> {
+    // Telegram remembers the last allowed_updates, and reports them in the webhook info
+    let current = inner
+        .call_api("getWebhookInfo", json!({}))
+        .await
+        .inspect_err(|err| tracing::warn!("cannot get the current update types: {:?}", err))
+        .ok()
+        .and_then(|info| webhook::WebhookInfo::from_json(&info).ok())
+        .map(|info| info.allowed_updates)
+        .unwrap_or_default();
+    let mut types = if current.is_empty() {
+        DEFAULT_UPDATE_TYPES.iter().map(|t| t.to_string()).collect()
+    } else {
+        current
+    };
+    if !types.iter().any(|t| t == "chat_member") {
+        types.push("chat_member".to_owned());
+    }
+    types
+}
+
+// Handles a single update from Telegram, retrying it if the bot processes updates at least once. Returns false if it failed every attempt and could not be dead-lettered.
+async fn handle_update<
+    Fun: FnMut(Value) -> Fut + Send,
+    Fut: Future<Output = anyhow::Result<Vec<R>>>,
+    R: Into<OutgoingMessage>,
+>(
+    inner: &Arc<Inner>,
+    opts: &TelegramBotBuilder,
+    msg_handler: &mut Fun,
+    update: Value,
+) -> bool {
+    observe_update(inner, opts, &update);
+    let mut attempts = 0;
     loop {
-        log::info!("getting updates at {counter}");
-        let fallible = async {
-            let updates = call_api(
-                &client,
-                &bot_token,
-                "getUpdates",
-                json!({"timeout": 120, "offset": counter + 1, "allowed_updates": []}),
-            )
+        attempts += 1;
+        let Err(err) = dispatch_update(inner, opts, msg_handler, &update).await else {
+            return true;
+        };
+        tracing::error!("error handling update: {:?}", err);
+        let error = format!("{err:#}");
+        if let Some(on_error) = &opts.on_error {
+            on_error(update.clone(), err).await;
+        }
+        if attempts >= opts.max_attempts {
+            if opts.max_attempts > 1 {
+                tracing::error!("giving up on update after {attempts} attempts");
+            }
+            if !opts.dead_letters {
+                return false;
+            }
+            return inner
+                .dead_letter(&update, &error, attempts)
+                .inspect_err(|err| tracing::error!("cannot dead-letter update: {:?}", err))
+                .is_ok();
+        }
+        smol::Timer::after(retry_backoff(attempts)).await;
+    }
+}
+
+// How long to wait before handling an update again after the given number of failed attempts.
+fn retry_backoff(attempts: u32) -> Duration {
+    // 1s, 2s, 4s, ... capped at a minute
+    Duration::from_secs(1 << attempts.saturating_sub(1).min(6)).min(Duration::from_secs(60))
+}
+
+// Updates the bot's own bookkeeping for an update, once however many times it is handled.
+fn observe_update(inner: &Inner, opts: &TelegramBotBuilder, update: &Value) {
+    #[cfg(feature = "metrics")]
+    inner.metrics.record_update(update_type(update));
+    inner.update_counts.received(update_type(update));
+    if let Some(lag) = status::update_lag(update) {
+        inner.status.update(|status| status.update_lag = Some(lag));
+        if opts.lag_threshold.is_some_and(|threshold| lag > threshold) {
+            tracing::warn!("handling update {:?} after it was sent", lag);
+            if let Some(on_lag) = &opts.on_lag {
+                on_lag(lag);
+            }
+        }
+    }
+    if let Some(member_cache) = &inner.member_cache {
+        member_cache.observe_update(update);
+    }
+    if opts.chat_stats {
+        if let Err(err) = stats::record_update(&*inner.store, update) {
+            tracing::warn!("cannot record chat stats: {:?}", err);
+        }
+    }
+    if let (Some(old_id), Some(new_id)) = (
+        update["message"]["chat"]["id"].as_i64(),
+        update["message"]["migrate_to_chat_id"].as_i64(),
+    ) {
+        if let Err(err) = inner.migrate_chat(old_id, new_id) {
+            tracing::warn!("cannot record chat migration: {:?}", err);
+        }
+    }
+}
+
+// Runs the handler an update is for, returning whether it and the sending of its responses succeeded.
+async fn dispatch_update<
+    Fun: FnMut(Value) -> Fut + Send,
+    Fut: Future<Output = anyhow::Result<Vec<R>>>,
+    R: Into<OutgoingMessage>,
+>(
+    inner: &Arc<Inner>,
+    opts: &TelegramBotBuilder,
+    msg_handler: &mut Fun,
+    update: &Value,
+) -> anyhow::Result<()> {
+    let bot = || BotHandle {
+        inner: inner.clone(),
+    };
+    let event_handled = match (
+        &opts.on_inline_query,
+        &opts.on_callback_query,
+        &opts.on_service_message,
+    ) {
+        (Some(on_inline_query), _, _) if update["inline_query"].is_object() => {
+            Some(on_inline_query(inline::InlineQuery {
+                query: update["inline_query"].clone(),
+                bot: bot(),
+                options: opts.inline_options.clone(),
+            }))
+        }
+        (_, Some(on_callback_query), _) if update["callback_query"].is_object() => {
+            Some(on_callback_query(callback::CallbackQuery {
+                query: update["callback_query"].clone(),
+                bot: bot(),
+            }))
+        }
+        (_, _, Some(on_service_message)) => {
+            events::ServiceMessage::from_message(ctx::update_message(update)).map(|service| {
+                on_service_message(
+                    service,
+                    Ctx {
+                        update: update.clone(),
+                        bot: bot(),
+                    },
+                )
+            })
+        }
+        _ => None,
+    }
+    .or_else(|| match &opts.on_unhandled_update {
+        // only text messages reach the message handler
+        Some(on_unhandled_update) if update["message"]["text"].is_null() => {
+            Some(on_unhandled_update(update.clone(), bot()))
+        }
+        _ => None,
+    });
+    if let Some(handled) = event_handled {
+        inner.status.update(|status| status.handlers_in_flight += 1);
+        // event handlers must not take down the polling task either
+        let handled = async {
+            AssertUnwindSafe(handled)
+                .catch_unwind()
+                .await
+                .map_err(|panic| {
+                    anyhow::anyhow!("event handler panicked: {}", panic_message(&*panic))
+                })?
+        }
+        .timeout(opts.handler_timeout)
+        .await
+        .unwrap_or_else(|| {
+            Err(anyhow::anyhow!(
+                "event handler timed out after {:?}",
+                opts.handler_timeout
+            ))
+        });
+        inner.status.update(|status| status.handlers_in_flight -= 1);
+        return handled;
+    }
+    // we only support text msgs atm
+    if update["message"]["text"].is_null() {
+        #[cfg(feature = "metrics")]
+        inner.metrics.record_unhandled(update_type(update));
+        inner.update_counts.unhandled(update_type(update));
+        return Ok(());
+    }
+    #[cfg(feature = "metrics")]
+    let handle_start = Instant::now();
+    inner.status.update(|status| status.handlers_in_flight += 1);
+    let handled = async {
+        if opts.stats_command && Command::from_update(update).is_some_and(|cmd| cmd.name == "stats")
+        {
+            let chat_id = update["message"]["chat"]["id"]
+                .as_i64()
+                .context("message without a chat id")?;
+            let stats = stats::load_stats(&*inner.store, chat_id)?;
+            inner
+                .call_api(
+                    "sendMessage",
+                    json!({"chat_id": chat_id, "text": stats.render()}),
+                )
+                .await
+                .context("cannot send stats to telegram")?;
+            return anyhow::Ok(());
+        }
+        // a panicking handler must not take down the polling task
+        let responses = AssertUnwindSafe(async { msg_handler(update.clone()).await })
+            .catch_unwind()
             .await
-            .context("cannot call telegram for updates")?;
-            let updates: Vec<Value> = serde_json::from_value(updates)?;
-            for update in updates {
-                // we only support text msgs atm
-                counter = counter.max(update["update_id"].as_i64().unwrap_or_default());
-                if !update["message"]["text"].is_null() {
-                    let responses = msg_handler(update).await?;
-                    // send response to telegram
-                    let json_resps: Vec<Value> =
-                        responses.iter().map(|resp| resp_json(resp)).collect();
-
-                    for r in json_resps {
-                        call_api(&client, &bot_token, "sendMessage", r)
-                            .await
-                            .context("cannot send reply back to telegram")?;
-                    }
+            .map_err(|panic| {
+                anyhow::anyhow!("message handler panicked: {}", panic_message(&*panic))
+            })??;
+        // send response to telegram
+        let requests = responses
+            .into_iter()
+            .flat_map(|resp| resp.into().to_requests())
+            .collect();
+        send_responses(inner, requests).await
+    };
+    let handled = handled
+        .timeout(opts.handler_timeout)
+        .await
+        .unwrap_or_else(|| {
+            Err(anyhow::anyhow!(
+                "message handler timed out after {:?}",
+                opts.handler_timeout
+            ))
+        });
+    #[cfg(feature = "metrics")]
+    inner.metrics.record_handler(handle_start.elapsed());
+    inner.status.update(|status| status.handlers_in_flight -= 1);
+    handled
+}
+
+// Sends responses concurrently across chats, but one at a time and in order within each chat.
+async fn send_responses(inner: &Inner, requests: Vec<ApiRequest>) -> anyhow::Result<()> {
+    let queued = Mutex::new(QueuedResponses::new(&inner.status, requests.len()));
+    let mut by_chat: Vec<(Value, Vec<ApiRequest>)> = vec![];
+    for r in requests {
+        match by_chat
+            .iter_mut()
+            .find(|(chat_id, _)| *chat_id == r.args["chat_id"])
+        {
+            Some((_, chat_resps)) => chat_resps.push(r),
+            None => by_chat.push((r.args["chat_id"].clone(), vec![r])),
+        }
+    }
+    let semaphore = Semaphore::new(MAX_CONCURRENT_SENDS);
+    let executor = smol::Executor::new();
+    let tasks: Vec<_> = by_chat
+        .into_iter()
+        .map(|(_, chat_resps)| {
+            let (semaphore, queued) = (&semaphore, &queued);
+            executor.spawn(async move {
+                let _permit = semaphore.acquire().await;
+                for r in chat_resps {
+                    inner.send_request(r).await?;
+                    queued.lock().unwrap().sent_one();
                 }
+                anyhow::Ok(())
+            })
+        })
+        .collect();
+    executor
+        .run(async {
+            for task in tasks {
+                task.await?;
             }
-            anyhow::Ok(())
+            Ok(())
+        })
+        .await
+}
+
+/// Checks that a bot token has the form Telegram issues, like `123456789:AAE...`, without asking Telegram.
+pub fn validate_token(token: &str) -> anyhow::Result<()> {
+    let valid = token.split_once(':').is_some_and(|(id, secret)| {
+        !id.is_empty()
+            && id.bytes().all(|b| b.is_ascii_digit())
+            && secret.len() == 35
+            && secret
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+    });
+    if !valid {
+        anyhow::bail!("bot token is malformed: expected a numeric bot ID, a colon, and 35 letters, digits, underscores, or dashes")
+    }
+    Ok(())
+}
+
+// Creates the span that everything done for an update is logged under.
+fn update_span(update: &Value) -> tracing::Span {
+    let update_type = update_type(update);
+    let body = &update[update_type];
+    // callback queries and the like carry their chat in an attached message
+    let chat_id = body["chat"]["id"]
+        .as_i64()
+        .or_else(|| body["message"]["chat"]["id"].as_i64());
+    let user_id = body["from"]["id"].as_i64();
+    tracing::info_span!(
+        "update",
+        update_id = update["update_id"].as_i64(),
+        update_type,
+        chat_id,
+        user_id
+    )
+}
+
+// Extracts a human-readable message from a caught panic.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
+// The fields of a JSON object that a typed model does not know, so they survive parsing and sending it back.
+pub(crate) fn unknown_fields(value: &Value, known: &[&str]) -> Map<String, Value> {
+    value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| !known.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+// Adds the unknown fields kept by a typed model back to its JSON, without overriding the fields it knows.
+pub(crate) fn with_unknown_fields(mut value: Value, extra: &Map<String, Value>) -> Value {
+    if let Some(fields) = value.as_object_mut() {
+        for (key, extra) in extra {
+            fields.entry(key.clone()).or_insert_with(|| extra.clone());
+        }
+    }
+    value
+}
+
+// The type of an update, i.e. the name of its field other than update_id.
+fn update_type(update: &Value) -> &str {
+    update
+        .as_object()
+        .and_then(|fields| fields.keys().find(|key| *key != "update_id"))
+        .map(|key| key.as_str())
+        .unwrap_or("unknown")
+}
+
+impl Inner {
+    // Calls a Telegram API.
+    async fn call_api(&self, method: &str, args: Value) -> anyhow::Result<Value> {
+        self.call_api_with_uploads(method, args, &[]).await
+    }
+
+    // Sends a message, returning the sent message, or the first one if it took several.
+    async fn send(&self, msg: OutgoingMessage) -> anyhow::Result<Value> {
+        let mut first = None;
+        for req in msg.to_requests() {
+            let sent = self.send_request(req).await?;
+            first.get_or_insert(sent);
+        }
+        Ok(first.unwrap_or_default())
+    }
+
+    // Makes a call that sends a message, reusing cached file_ids for its uploads if enabled.
+    async fn send_request(&self, mut req: ApiRequest) -> anyhow::Result<Value> {
+        if let Some(key) = &req.idempotency_key {
+            if let Some(sent) = self.already_sent(key)? {
+                tracing::debug!("{key} was already sent, not sending it again");
+                return Ok(sent);
+            }
+        }
+        let fingerprint = self
+            .dedup
+            .as_ref()
+            .map(|_| dedup::DedupWindow::fingerprint(&req));
+        if let (Some(dedup), Some(fingerprint)) = (&self.dedup, &fingerprint) {
+            if let Some(sent) = dedup.recent(fingerprint) {
+                tracing::warn!(
+                    "not sending {} identical to one sent moments ago",
+                    req.method
+                );
+                return Ok(sent);
+            }
+        }
+        let pending = if self.file_id_cache {
+            file_cache::apply(&*self.store, &mut req)
+        } else {
+            vec![]
         };
-        match fallible.timeout(Duration::from_secs(300)).await {
-            Some(x) => {
-                if let Err(err) = x {
-                    log::error!("error getting updates: {:?}", err)
+        self.apply_migration(&mut req.args)?;
+        let chat_id = req.args["chat_id"].as_i64();
+        let res = match self
+            .call_api_rate_limited(req.method, req.args.clone(), &req.uploads)
+            .await
+        {
+            Err(err) => match (chat_id, migration::migrated_to(&err)) {
+                (Some(old_id), Some(new_id)) => {
+                    self.migrate_chat(old_id, new_id)?;
+                    req.args["chat_id"] = json!(new_id);
+                    self.call_api_rate_limited(req.method, req.args, &req.uploads)
+                        .await
                 }
+                _ => Err(err),
+            },
+            res => res,
+        };
+        let sent = res
+            .map_err(|err| self.check_unreachable(chat_id, err))
+            .context("cannot send reply back to telegram")?;
+        file_cache::record(&*self.store, &pending, &sent);
+        if let Some(key) = &req.idempotency_key {
+            self.record_sent(key, &sent);
+        }
+        if let (Some(dedup), Some(fingerprint)) = (&self.dedup, fingerprint) {
+            dedup.record(fingerprint, &sent);
+        }
+        Ok(sent)
+    }
+
+    // Calls a Telegram API with files attached.
+    async fn call_api_with_uploads(
+        &self,
+        method: &str,
+        mut args: Value,
+        uploads: &[Upload],
+    ) -> anyhow::Result<Value> {
+        if let Some(res) = self
+            .on_request
+            .as_ref()
+            .and_then(|on_request| on_request(method, &mut args))
+        {
+            return res;
+        }
+        if let Some(version) = self.api_version {
+            api_version::gate(version, method, &mut args)?;
+        }
+        limits::check(method, &args, uploads, self.upload_limit)?;
+        let start = Instant::now();
+        // in-flight calls finish with the token they started with, even if it is rotated meanwhile
+        let token = self.token();
+        let res = self
+            .call_api_inner(&token, method, &args, uploads)
+            .instrument(tracing::debug_span!("api_call", method))
+            .await
+            .map_err(|err| redact_error(err, &token));
+        if let Some(on_response) = &self.on_response {
+            on_response(method, &args, start.elapsed(), &res);
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics.record_api_call(
+            method,
+            start.elapsed(),
+            res.as_ref()
+                .err()
+                .map(|err| match err.downcast_ref::<ApiError>() {
+                    Some(err) => err.error_code.to_string(),
+                    None => "network".to_owned(),
+                })
+                .as_deref(),
+        );
+        res
+    }
+
+    async fn call_api_inner(
+        &self,
+        token: &str,
+        method: &str,
+        args: &Value,
+        uploads: &[Upload],
+    ) -> anyhow::Result<Value> {
+        if self.debug_logging {
+            tracing::debug!(method, args = %args, uploads = uploads.len(), "sending request");
+        }
+        let timeout = self.timeouts.for_call(method, uploads);
+        let raw_res = async {
+            if uploads.is_empty() {
+                self.transport.call(token, method, args).await
+            } else {
+                self.transport.upload(token, method, args, uploads).await
+            }
+        }
+        .timeout(timeout)
+        .await;
+        let mut raw_res = match raw_res {
+            Some(res) => res?,
+            None => anyhow::bail!("{method} timed out after {timeout:?}"),
+        };
+        if self.debug_logging {
+            tracing::debug!(method, response = %raw_res, "received response");
+        }
+        if raw_res["ok"].as_bool().unwrap_or(false) {
+            Ok(raw_res["result"].take())
+        } else {
+            Err(ApiError {
+                error_code: raw_res["error_code"]
+                    .as_i64()
+                    .context("could not parse error code as integer")?,
+                description: raw_res["description"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_owned(),
+                parameters: raw_res["parameters"].take(),
             }
-            None => log::error!("timed out getting telegram updates!"),
+            .into())
         }
     }
+
+    // Calls a Telegram API, waiting and retrying a few times if Telegram says we are sending too fast.
+    async fn call_api_rate_limited(
+        &self,
+        method: &str,
+        args: Value,
+        uploads: &[Upload],
+    ) -> anyhow::Result<Value> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self
+                .call_api_with_uploads(method, args.clone(), uploads)
+                .await
+            {
+                Err(err) if attempts < 3 => {
+                    let Some(retry_after) = err
+                        .downcast_ref::<ApiError>()
+                        .filter(|err| err.error_code == 429)
+                        .and_then(|err| err.parameters["retry_after"].as_u64())
+                    else {
+                        return Err(err);
+                    };
+                    tracing::warn!("rate limited by telegram, retrying in {retry_after}s");
+                    #[cfg(feature = "metrics")]
+                    self.metrics
+                        .record_rate_limit_wait(Duration::from_secs(retry_after));
+                    smol::Timer::after(Duration::from_secs(retry_after)).await;
+                    #[cfg(feature = "metrics")]
+                    self.metrics.record_rate_limit_wait(Duration::ZERO);
+                }
+                res => return res,
+            }
+        }
+    }
+
+    // The current bot token.
+    fn token(&self) -> Arc<str> {
+        self.bot_token.read().unwrap().clone()
+    }
 }
 
-// Calls a Telegram API.
-async fn call_api(
-    client: &HttpClient,
-    token: &str,
-    method: &str,
-    args: Value,
-) -> anyhow::Result<Value> {
-    let raw_res: Value = client
-        .send_async(
-            Request::post(format!("https://api.telegram.org/bot{}/{method}", token))
-                .header("Content-Type", "application/json")
-                .body(serde_json::to_vec(&args)?)?,
-        )
-        .await?
-        .json()
-        .await?;
-    if raw_res["ok"].as_bool().unwrap_or(false) {
-        Ok(raw_res["result"].clone())
+// Strips the bot token out of transport errors, which may mention the request URL, since errors end up in logs.
+fn redact_error(err: anyhow::Error, token: &str) -> anyhow::Error {
+    if err.is::<ApiError>() {
+        return err;
+    }
+    let message = format!("{:#}", err);
+    if message.contains(token) {
+        anyhow::anyhow!(message.replace(token, "<redacted>"))
     } else {
-        anyhow::bail!(
-            "telegram failed with error code {}",
-            raw_res["error_code"]
-                .as_i64()
-                .context("could not parse error code as integer")?
-        )
+        err
     }
 }
 
-// puts message into correct json format for telegram bot api
-fn resp_json(resp: &Response) -> Value {
-    if let Some(reply_to_msg_id) = resp.reply_to_message_id {
-        json!({
-            "chat_id": resp.chat_id,
-            "text": resp.text,
-            "reply_to_message_id": reply_to_msg_id,
-        })
-    } else {
-        json!({
-            "chat_id": resp.chat_id,
-            "text": resp.text,
-        })
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::mock::MockTelegram;
+
+    // A bot whose handler always fails, trying each update once.
+    fn failing_bot(mock: &MockTelegram, builder: TelegramBotBuilder) -> TelegramBot {
+        builder
+            .transport(mock.clone())
+            .at_least_once(1)
+            .build(|_| async { anyhow::Result::<Vec<Response>>::Err(anyhow::anyhow!("broken")) })
+    }
+
+    #[test]
+    fn at_least_once_keeps_updates_it_cannot_dead_letter() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            mock.push_message(1, "first");
+            mock.push_message(1, "second");
+            let _bot = failing_bot(&mock, TelegramBot::builder("1:test"));
+            smol::Timer::after(Duration::from_millis(300)).await;
+            mock.push_message(1, "third");
+            smol::Timer::after(Duration::from_millis(300)).await;
+            let pending = mock.pending_updates();
+            let texts: Vec<&str> = pending
+                .iter()
+                .map(|update| update["message"]["text"].as_str().unwrap())
+                .collect();
+            assert_eq!(texts, ["first", "second", "third"]);
+        });
+    }
+
+    #[test]
+    fn at_least_once_acknowledges_dead_lettered_updates() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            mock.push_message(1, "first");
+            let bot = failing_bot(&mock, TelegramBot::builder("1:test").dead_letters());
+            smol::Timer::after(Duration::from_millis(300)).await;
+            mock.push_message(1, "second");
+            smol::Timer::after(Duration::from_millis(300)).await;
+            assert!(mock.pending_updates().is_empty());
+            assert_eq!(bot.dead_letters().unwrap().len(), 2);
+        });
+    }
+
+    #[test]
+    fn member_cache_adds_chat_member_to_the_current_update_types() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            mock.respond_with(
+                "getWebhookInfo",
+                json!({"url": "", "allowed_updates": ["message", "message_reaction"]}),
+            );
+            let _bot = TelegramBot::builder("1:test")
+                .transport(mock.clone())
+                .chat_member_cache(Duration::from_secs(60))
+                .build(|_| async { anyhow::Ok(Vec::<Response>::new()) });
+            smol::Timer::after(Duration::from_millis(100)).await;
+            assert_eq!(
+                mock.allowed_updates(),
+                ["message", "message_reaction", "chat_member"]
+            );
+        });
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_report_the_rate_limit_wait() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            let bot = TelegramBot::builder("1:test")
+                .transport(mock.clone())
+                .build(|_| async { anyhow::Ok(Vec::<Response>::new()) });
+            mock.respond_with_error("sendMessage", 429, "Too Many Requests: retry after 1");
+            let send = smol::spawn({
+                let handle = bot.handle();
+                async move {
+                    handle
+                        .send(Response {
+                            text: "hi".into(),
+                            chat_id: 1,
+                            reply_to_message_id: None,
+                        })
+                        .await
+                }
+            });
+            smol::Timer::after(Duration::from_millis(300)).await;
+            let rendered = bot.metrics().render();
+            assert!(rendered.contains("\ntelegram_bot_rate_limit_wait_seconds 1\n"));
+            assert!(rendered.contains("\ntelegram_bot_update_queue_depth 0\n"));
+            mock.respond_with("sendMessage", json!({"message_id": 1, "chat": {"id": 1}}));
+            send.await.unwrap();
+            assert!(bot
+                .metrics()
+                .render()
+                .contains("\ntelegram_bot_rate_limit_wait_seconds 0\n"));
+        });
+    }
+
+    #[test]
+    fn at_least_once_retries_failed_updates_with_backoff() {
+        assert_eq!(retry_backoff(1), Duration::from_secs(1));
+        assert_eq!(retry_backoff(3), Duration::from_secs(4));
+        assert_eq!(retry_backoff(20), Duration::from_secs(60));
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            mock.push_message(1, "flaky");
+            let attempts = Arc::new(Mutex::new(vec![]));
+            let _bot = TelegramBot::builder("1:test")
+                .transport(mock.clone())
+                .at_least_once(3)
+                .build({
+                    let attempts = attempts.clone();
+                    move |_| {
+                        let mut attempts = attempts.lock().unwrap();
+                        attempts.push(Instant::now());
+                        let failed = attempts.len() == 1;
+                        async move {
+                            anyhow::ensure!(!failed, "flaky");
+                            Ok(Vec::<Response>::new())
+                        }
+                    }
+                });
+            smol::Timer::after(Duration::from_millis(1500)).await;
+            let attempts = attempts.lock().unwrap().clone();
+            assert_eq!(attempts.len(), 2);
+            assert!(attempts[1] - attempts[0] >= retry_backoff(1));
+            assert!(mock.pending_updates().is_empty());
+        });
+    }
+
+    #[test]
+    fn dead_letters_can_be_redriven_and_discarded() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            let broken = Arc::new(AtomicBool::new(true));
+            let handled = Arc::new(Mutex::new(vec![]));
+            let bot = TelegramBot::builder("1:test")
+                .transport(mock.clone())
+                .dead_letters()
+                .build({
+                    let (broken, handled) = (broken.clone(), handled.clone());
+                    move |update: Value| {
+                        let text = update["message"]["text"].as_str().unwrap_or_default();
+                        let failed = broken.load(Ordering::SeqCst) || text == "poison";
+                        if !failed {
+                            handled.lock().unwrap().push(text.to_owned());
+                        }
+                        async move {
+                            anyhow::ensure!(!failed, "broken");
+                            Ok(Vec::<Response>::new())
+                        }
+                    }
+                });
+            mock.push_message(1, "hello");
+            mock.push_message(1, "poison");
+            smol::Timer::after(Duration::from_millis(300)).await;
+            let letters = bot.dead_letters().unwrap();
+            assert_eq!(letters.len(), 2);
+            assert_eq!(letters[0].error, "broken");
+            assert_eq!(letters[0].attempts, 1);
+
+            broken.store(false, Ordering::SeqCst);
+            assert_eq!(bot.redrive_dead_letters().unwrap(), 2);
+            smol::Timer::after(Duration::from_millis(300)).await;
+            assert_eq!(*handled.lock().unwrap(), ["hello"]);
+            let letters = bot.dead_letters().unwrap();
+            assert_eq!(letters.len(), 1);
+            assert_eq!(letters[0].update["message"]["text"], "poison");
+            bot.discard_dead_letter(&letters[0]).unwrap();
+            assert!(bot.dead_letters().unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn chat_migrations_redirect_later_sends() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            let migrations = Arc::new(Mutex::new(vec![]));
+            let bot = TelegramBot::builder("1:test")
+                .transport(mock.clone())
+                .on_chat_migrated({
+                    let migrations = migrations.clone();
+                    move |old_id, new_id| migrations.lock().unwrap().push((old_id, new_id))
+                })
+                .build(|_| async { anyhow::Ok(Vec::<Response>::new()) });
+            let migration = json!({"message": {
+                "message_id": 1,
+                "date": 0,
+                "chat": {"id": -100, "type": "group"},
+                "migrate_to_chat_id": -1001,
+            }});
+            mock.push_update(migration.clone());
+            mock.push_update(migration);
+            smol::Timer::after(Duration::from_millis(200)).await;
+            assert_eq!(*migrations.lock().unwrap(), [(-100, -1001)]);
+            bot.send(Response {
+                text: "hi".into(),
+                chat_id: -100,
+                reply_to_message_id: None,
+            })
+            .await
+            .unwrap();
+            assert_eq!(mock.calls_to("sendMessage")[0]["chat_id"], -1001);
+        });
+    }
+
+    #[test]
+    fn responses_to_each_chat_are_sent_in_order() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            let _bot = TelegramBot::builder("1:test")
+                .transport(mock.clone())
+                .build(|_| async {
+                    anyhow::Ok(
+                        (0..12)
+                            .map(|i| Response {
+                                text: i.to_string(),
+                                chat_id: i % 3,
+                                reply_to_message_id: None,
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                });
+            mock.push_message(1, "go");
+            mock.wait_for_calls(12).await;
+            let sent = mock.calls_to("sendMessage");
+            assert_eq!(sent.len(), 12);
+            for chat_id in 0..3 {
+                let texts: Vec<&str> = sent
+                    .iter()
+                    .filter(|args| args["chat_id"] == chat_id)
+                    .map(|args| args["text"].as_str().unwrap())
+                    .collect();
+                let expected: Vec<String> = (0..12)
+                    .filter(|i| i % 3 == chat_id)
+                    .map(|i| i.to_string())
+                    .collect();
+                assert_eq!(texts, expected);
+            }
+        });
     }
 }
@@ -0,0 +1,72 @@
+use serde_json::Value;
+
+use crate::{
+    entities::utf16_len,
+    outgoing::{MAX_CAPTION_LEN, MAX_TEXT_LEN},
+    transport::Upload,
+};
+
+/// The largest file bots may upload to Telegram's own servers.
+pub(crate) const MAX_UPLOAD_SIZE: usize = 50 * 1024 * 1024;
+
+/// The most items in an album.
+const MAX_MEDIA_GROUP_LEN: usize = 10;
+
+/// The most buttons in an inline keyboard.
+const MAX_KEYBOARD_BUTTONS: usize = 100;
+
+// Checks a call against Telegram's documented limits, so that it fails locally with a clear error instead of with a bare "Bad Request" from Telegram.
+pub(crate) fn check(
+    method: &str,
+    args: &Value,
+    uploads: &[Upload],
+    upload_limit: usize,
+) -> anyhow::Result<()> {
+    // with a parse mode, markup does not count towards the limits, so only Telegram can tell
+    let formatted = !args["parse_mode"].is_null();
+    if let (Some(text), false) = (args["text"].as_str(), formatted) {
+        let len = utf16_len(text);
+        if len > MAX_TEXT_LEN && method != "answerCallbackQuery" {
+            anyhow::bail!(
+                "{method}: text is {len} characters, more than Telegram's limit of {MAX_TEXT_LEN}"
+            )
+        }
+    }
+    if let (Some(caption), false) = (args["caption"].as_str(), formatted) {
+        let len = utf16_len(caption);
+        if len > MAX_CAPTION_LEN {
+            anyhow::bail!(
+                "{method}: caption is {len} characters, more than Telegram's limit of {MAX_CAPTION_LEN}; see CaptionOverflow to split or truncate it"
+            )
+        }
+    }
+    for upload in uploads {
+        if upload.data.len() > upload_limit {
+            anyhow::bail!(
+                "{method}: file {:?} is {} bytes, more than the upload limit of {upload_limit} bytes",
+                upload.file_name,
+                upload.data.len()
+            )
+        }
+    }
+    if method == "sendMediaGroup" {
+        let len = args["media"].as_array().map_or(0, Vec::len);
+        if !(2..=MAX_MEDIA_GROUP_LEN).contains(&len) {
+            anyhow::bail!(
+                "{method}: albums must have 2 to {MAX_MEDIA_GROUP_LEN} items, but this one has {len}"
+            )
+        }
+    }
+    let buttons: usize = args["reply_markup"]["inline_keyboard"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|row| row.as_array().map_or(0, Vec::len))
+        .sum();
+    if buttons > MAX_KEYBOARD_BUTTONS {
+        anyhow::bail!(
+            "{method}: inline keyboard has {buttons} buttons, more than Telegram's limit of {MAX_KEYBOARD_BUTTONS}"
+        )
+    }
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use serde_json::Value;
+
+/// The most characters Telegram allows in a deep link payload.
+const MAX_PAYLOAD_LEN: usize = 64;
+
+/// Supergroup and channel chat IDs are their internal IDs subtracted from this, hence the `-100` prefix.
+const CHANNEL_ID_OFFSET: i64 = -1_000_000_000_000;
+
+const BASE64URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Builds a `https://t.me/<bot>?start=<payload>` deep link. The payload may only contain `A-Z`, `a-z`, `0-9`, `_` and `-`, up to 64 characters.
+pub fn start_link(bot_username: &str, payload: &str) -> anyhow::Result<String> {
+    check_payload(payload)?;
+    Ok(format!(
+        "https://t.me/{}?start={payload}",
+        bot_username.trim_start_matches('@')
+    ))
+}
+
+/// Builds a `https://t.me/<bot>?startgroup=<payload>` deep link, which prompts the user to add the bot to a group.
+pub fn start_group_link(bot_username: &str, payload: &str) -> anyhow::Result<String> {
+    check_payload(payload)?;
+    Ok(format!(
+        "https://t.me/{}?startgroup={payload}",
+        bot_username.trim_start_matches('@')
+    ))
+}
+
+/// Builds a deep link carrying arbitrary bytes, base64url-encoded. At most 48 bytes fit in a payload.
+pub fn start_link_bytes(bot_username: &str, data: &[u8]) -> anyhow::Result<String> {
+    start_link(bot_username, &base64url_encode(data))
+}
+
+/// Extracts the payload from a `/start <payload>` message in an update, if there is one. Links from [start_group_link] arrive in groups as `/start@<bot> <payload>`.
+pub fn start_payload(update: &Value) -> Option<&str> {
+    let text = update["message"]["text"].as_str()?;
+    let (command, payload) = text.split_once(char::is_whitespace)?;
+    let name = command.split_once('@').map_or(command, |(name, _)| name);
+    let payload = payload.trim();
+    (name == "/start" && !payload.is_empty()).then_some(payload)
+}
+
+/// Decodes a payload created by [start_link_bytes] back into bytes.
+pub fn decode_start_payload(payload: &str) -> anyhow::Result<Vec<u8>> {
+    base64url_decode(payload)
+}
+
+/// Builds a `https://t.me/<username>` link to a user, bot, group, or channel with a public username.
+pub fn username_link(username: &str) -> String {
+    format!("https://t.me/{}", username.trim_start_matches('@'))
+}
+
+/// Builds a `tg://user?id=<id>` link to a user, which works even if they have no public username.
+pub fn user_id_link(user_id: i64) -> String {
+    format!("tg://user?id={user_id}")
+}
+
+/// Converts a supergroup or channel chat ID (like `-1001234567890`) to the internal ID used in `t.me/c/` links (like `1234567890`). Returns None for private chats and basic groups, which have no such links.
+pub fn internal_chat_id(chat_id: i64) -> Option<i64> {
+    let internal = CHANNEL_ID_OFFSET - chat_id;
+    (internal > 0).then_some(internal)
+}
+
+/// Converts an internal ID from a `t.me/c/` link back to the supergroup or channel chat ID.
+pub fn chat_id_from_internal(internal_id: i64) -> i64 {
+    CHANNEL_ID_OFFSET - internal_id
+}
+
+/// Builds a `https://t.me/c/<id>/<message>` link to a message in a supergroup or channel, visible to its members. Errors for private chats and basic groups.
+pub fn message_link(chat_id: i64, message_id: i64) -> anyhow::Result<String> {
+    let internal = internal_chat_id(chat_id).ok_or_else(|| {
+        anyhow::anyhow!(
+            "chat {chat_id} is not a supergroup or channel, so its messages have no links"
+        )
+    })?;
+    Ok(format!("https://t.me/c/{internal}/{message_id}"))
+}
+
+/// Builds a `https://t.me/<username>/<message>` link to a message in a public supergroup or channel.
+pub fn public_message_link(username: &str, message_id: i64) -> String {
+    format!("{}/{message_id}", username_link(username))
+}
+
+/// Builds a `https://t.me/share/url` link that lets the user share a URL, with optional text, to a chat of their choice.
+pub fn share_link(url: &str, text: Option<&str>) -> String {
+    let mut link = format!("https://t.me/share/url?url={}", percent_encode(url));
+    if let Some(text) = text {
+        link.push_str("&text=");
+        link.push_str(&percent_encode(text));
+    }
+    link
+}
+
+// Percent-encodes everything except unreserved URL characters.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+fn check_payload(payload: &str) -> anyhow::Result<()> {
+    if payload.len() > MAX_PAYLOAD_LEN {
+        anyhow::bail!(
+            "deep link payload is {} characters, more than the maximum of {MAX_PAYLOAD_LEN}",
+            payload.len()
+        )
+    }
+    if let Some(c) = payload
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '_' && *c != '-')
+    {
+        anyhow::bail!("deep link payload contains invalid character {c:?}")
+    }
+    Ok(())
+}
+
+// Encodes bytes as unpadded base64url.
+fn base64url_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, b)| acc | (*b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(BASE64URL_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+// Decodes unpadded base64url.
+fn base64url_decode(encoded: &str) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(encoded.len() * 3 / 4);
+    for chunk in encoded.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            anyhow::bail!("invalid base64url length {}", encoded.len())
+        }
+        let mut bits = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = BASE64URL_ALPHABET
+                .iter()
+                .position(|a| a == c)
+                .ok_or_else(|| anyhow::anyhow!("invalid base64url character {:?}", *c as char))?;
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn message(text: &str) -> Value {
+        json!({"message": {"text": text}})
+    }
+
+    #[test]
+    fn base64url_round_trips() {
+        for len in 0..=48 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 250) as u8).collect();
+            let encoded = base64url_encode(&data);
+            assert!(check_payload(&encoded).is_ok(), "{encoded}");
+            assert_eq!(base64url_decode(&encoded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn base64url_matches_known_encodings() {
+        assert_eq!(base64url_encode(b"hello"), "aGVsbG8");
+        assert_eq!(base64url_encode(&[0xfb, 0xff]), "-_8");
+        assert!(base64url_decode("a").is_err());
+        assert!(base64url_decode("a+b=").is_err());
+    }
+
+    #[test]
+    fn start_payloads_round_trip() {
+        let link = start_link_bytes("@mybot", b"\x00ref:42").unwrap();
+        let payload = link.strip_prefix("https://t.me/mybot?start=").unwrap();
+        let update = message(&format!("/start {payload}"));
+        assert_eq!(
+            decode_start_payload(start_payload(&update).unwrap()).unwrap(),
+            b"\x00ref:42"
+        );
+        assert!(start_link_bytes("mybot", &[0; 49]).is_err());
+        assert!(start_link("mybot", "no spaces").is_err());
+    }
+
+    #[test]
+    fn extracts_start_payloads_in_groups() {
+        assert_eq!(start_payload(&message("/start@mybot ref42")), Some("ref42"));
+        assert_eq!(start_payload(&message("/start ref42 ")), Some("ref42"));
+        assert_eq!(start_payload(&message("/start")), None);
+        assert_eq!(start_payload(&message("/start@mybot")), None);
+        assert_eq!(start_payload(&message("/started ref42")), None);
+    }
+
+    #[test]
+    fn internal_chat_ids_round_trip() {
+        assert_eq!(internal_chat_id(-1001234567890), Some(1234567890));
+        assert_eq!(chat_id_from_internal(1234567890), -1001234567890);
+        assert_eq!(internal_chat_id(-4567), None);
+        assert_eq!(internal_chat_id(4567), None);
+    }
+
+    #[test]
+    fn builds_links() {
+        assert_eq!(
+            message_link(-1001234567890, 7).unwrap(),
+            "https://t.me/c/1234567890/7"
+        );
+        assert!(message_link(-4567, 7).is_err());
+        assert_eq!(public_message_link("@news", 7), "https://t.me/news/7");
+        assert_eq!(
+            share_link("https://a.b/?x=1", Some("hi there")),
+            "https://t.me/share/url?url=https%3A%2F%2Fa.b%2F%3Fx%3D1&text=hi%20there"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::{outgoing::InputFile, source::NoUpdates, OutgoingMessage, Response, TelegramBot};
+
+/// The environment variable holding the token of the bot to test with.
+pub const TOKEN_VAR: &str = "TELEGRAM_TEST_TOKEN";
+/// The environment variable holding the ID of the chat to test in, which the bot must be able to post in.
+pub const CHAT_VAR: &str = "TELEGRAM_TEST_CHAT_ID";
+
+/// Exercises the crate against the real Bot API in a test chat, deleting every message it sends. It never polls for updates, so it is safe to point at a token a bot is running with.
+///
+/// ```ignore
+/// #[test]
+/// fn live_round_trips() {
+///     let Some(harness) = LiveHarness::from_env().unwrap() else {
+///         return;
+///     };
+///     smol::block_on(harness.run_all()).unwrap();
+/// }
+/// ```
+pub struct LiveHarness {
+    bot: TelegramBot,
+    chat_id: i64,
+    sent: Mutex<Vec<i64>>,
+}
+
+impl LiveHarness {
+    /// Creates a harness for the given bot token and test chat.
+    pub fn new(bot_token: &str, chat_id: i64) -> Self {
+        Self {
+            bot: TelegramBot::builder(bot_token)
+                .update_source(NoUpdates)
+                .build(|_| async { anyhow::Ok(Vec::<Response>::new()) }),
+            chat_id,
+            sent: Mutex::new(vec![]),
+        }
+    }
+
+    /// Creates a harness from [TOKEN_VAR] and [CHAT_VAR], or returns None if either is unset, so that tests can skip themselves.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let (Ok(token), Ok(chat_id)) = (std::env::var(TOKEN_VAR), std::env::var(CHAT_VAR)) else {
+            return Ok(None);
+        };
+        let chat_id = chat_id
+            .trim()
+            .parse()
+            .with_context(|| format!("{CHAT_VAR} is not a chat ID"))?;
+        Ok(Some(Self::new(&token, chat_id)))
+    }
+
+    /// The bot under test, for checks of your own. Messages it sends are not cleaned up unless passed to [LiveHarness::track].
+    pub fn bot(&self) -> &TelegramBot {
+        &self.bot
+    }
+
+    /// The test chat.
+    pub fn chat_id(&self) -> i64 {
+        self.chat_id
+    }
+
+    /// Marks a message in the test chat for deletion by [LiveHarness::cleanup].
+    pub fn track(&self, message_id: i64) {
+        self.sent.lock().unwrap().push(message_id);
+    }
+
+    /// Runs every round trip, then cleans up, even if a round trip failed.
+    pub async fn run_all(&self) -> anyhow::Result<()> {
+        let result = async {
+            self.text_round_trip().await.context("text round trip")?;
+            self.upload_round_trip()
+                .await
+                .context("upload round trip")?;
+            self.delete_round_trip().await.context("delete round trip")
+        }
+        .await;
+        let cleaned = self.cleanup().await;
+        result.and(cleaned)
+    }
+
+    /// Sends a text message and edits it, checking that Telegram reports both texts back.
+    pub async fn text_round_trip(&self) -> anyhow::Result<()> {
+        let text = format!("live test {}", nonce());
+        let sent = self
+            .send(OutgoingMessage::text(self.chat_id, &text))
+            .await?;
+        anyhow::ensure!(
+            sent["text"] == json!(text),
+            "sent text came back as {}",
+            sent["text"]
+        );
+        let edited_text = format!("{text} (edited)");
+        let edited = self
+            .bot
+            .edit(
+                message_id(&sent)?,
+                OutgoingMessage::text(self.chat_id, &edited_text),
+            )
+            .await?;
+        anyhow::ensure!(
+            edited["text"] == json!(edited_text),
+            "edited text came back as {}",
+            edited["text"]
+        );
+        Ok(())
+    }
+
+    /// Uploads a document and downloads it again, checking that the bytes survive.
+    pub async fn upload_round_trip(&self) -> anyhow::Result<()> {
+        let data = format!("live test {}\n", nonce()).into_bytes();
+        let sent = self
+            .send(OutgoingMessage::document(
+                self.chat_id,
+                InputFile::bytes("live-test.txt", data.clone()),
+            ))
+            .await?;
+        let file_id = sent["document"]["file_id"]
+            .as_str()
+            .context("sent document has no file ID")?;
+        let downloaded = self.bot.download_file(file_id).await?;
+        anyhow::ensure!(
+            downloaded == data,
+            "downloaded document differs from upload"
+        );
+        Ok(())
+    }
+
+    /// Sends a message and deletes it, checking that deleting it again fails.
+    pub async fn delete_round_trip(&self) -> anyhow::Result<()> {
+        let sent = self
+            .bot
+            .send(OutgoingMessage::text(self.chat_id, "live test, deleting"))
+            .await?;
+        let message_id = message_id(&sent)?;
+        if let Err(err) = self.delete(message_id).await {
+            self.track(message_id);
+            return Err(err);
+        }
+        anyhow::ensure!(
+            self.delete(message_id).await.is_err(),
+            "deleting a deleted message succeeded"
+        );
+        Ok(())
+    }
+
+    /// Deletes every message the harness sent or was told to track.
+    pub async fn cleanup(&self) -> anyhow::Result<()> {
+        let sent = std::mem::take(&mut *self.sent.lock().unwrap());
+        let mut first_err = None;
+        for message_id in sent {
+            if let Err(err) = self.delete(message_id).await {
+                tracing::warn!("cannot delete live test message {message_id}: {:?}", err);
+                first_err.get_or_insert(err);
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    async fn send(&self, msg: OutgoingMessage) -> anyhow::Result<Value> {
+        let sent = self.bot.send(msg).await?;
+        self.track(message_id(&sent)?);
+        Ok(sent)
+    }
+
+    async fn delete(&self, message_id: i64) -> anyhow::Result<()> {
+        self.bot
+            .call_api(
+                "deleteMessage",
+                json!({"chat_id": self.chat_id, "message_id": message_id}),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+fn message_id(sent: &Value) -> anyhow::Result<i64> {
+    sent["message_id"]
+        .as_i64()
+        .context("sent message has no message ID")
+}
+
+// Distinguishes runs, so a stale message from an earlier run cannot pass a check.
+fn nonce() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
@@ -0,0 +1,196 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+use crate::BotStatus;
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 150.0, 300.0,
+];
+
+/// Metrics about a bot, rendered in the Prometheus text exposition format.
+#[derive(Default)]
+pub struct Metrics {
+    updates: Mutex<BTreeMap<String, u64>>,
+    unhandled_updates: Mutex<BTreeMap<String, u64>>,
+    handler_latency: Mutex<Histogram>,
+    api_latency: Mutex<BTreeMap<String, Histogram>>,
+    api_errors: Mutex<BTreeMap<(String, String), u64>>,
+    gauges: Mutex<Gauges>,
+}
+
+#[derive(Default)]
+struct Gauges {
+    update_queue_depth: usize,
+    outgoing_queue_depth: usize,
+    rate_limit_wait: Duration,
+}
+
+#[derive(Clone, Default)]
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        for (count, bound) in self.counts.iter_mut().zip(BUCKETS) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (count, bound) in self.counts.iter().zip(BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+impl Metrics {
+    pub(crate) fn record_update(&self, update_type: &str) {
+        *self
+            .updates
+            .lock()
+            .unwrap()
+            .entry(update_type.to_owned())
+            .or_default() += 1;
+    }
+
+    pub(crate) fn record_unhandled(&self, update_type: &str) {
+        *self
+            .unhandled_updates
+            .lock()
+            .unwrap()
+            .entry(update_type.to_owned())
+            .or_default() += 1;
+    }
+
+    pub(crate) fn record_handler(&self, latency: Duration) {
+        self.handler_latency.lock().unwrap().observe(latency);
+    }
+
+    pub(crate) fn record_api_call(&self, method: &str, latency: Duration, error: Option<&str>) {
+        self.api_latency
+            .lock()
+            .unwrap()
+            .entry(method.to_owned())
+            .or_default()
+            .observe(latency);
+        if let Some(status) = error {
+            *self
+                .api_errors
+                .lock()
+                .unwrap()
+                .entry((method.to_owned(), status.to_owned()))
+                .or_default() += 1;
+        }
+    }
+
+    pub(crate) fn record_status(&self, status: &BotStatus) {
+        let mut gauges = self.gauges.lock().unwrap();
+        gauges.update_queue_depth = status.queued_updates;
+        gauges.outgoing_queue_depth = status.queued_responses;
+    }
+
+    // Sets how long a call is waiting out a 429 from Telegram, or zero once it stops waiting.
+    pub(crate) fn record_rate_limit_wait(&self, wait: Duration) {
+        self.gauges.lock().unwrap().rate_limit_wait = wait;
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format, for serving at a `/metrics` endpoint.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP telegram_bot_updates_total Updates received, by update type.\n");
+        out.push_str("# TYPE telegram_bot_updates_total counter\n");
+        for (update_type, count) in self.updates.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "telegram_bot_updates_total{{type=\"{update_type}\"}} {count}"
+            );
+        }
+
+        out.push_str(
+            "# HELP telegram_bot_updates_unhandled_total Updates nothing handled, by update type.\n",
+        );
+        out.push_str("# TYPE telegram_bot_updates_unhandled_total counter\n");
+        for (update_type, count) in self.unhandled_updates.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "telegram_bot_updates_unhandled_total{{type=\"{update_type}\"}} {count}"
+            );
+        }
+
+        out.push_str("# HELP telegram_bot_handler_seconds Time taken to handle an update, including sending responses.\n");
+        out.push_str("# TYPE telegram_bot_handler_seconds histogram\n");
+        self.handler_latency
+            .lock()
+            .unwrap()
+            .render(&mut out, "telegram_bot_handler_seconds", "");
+
+        out.push_str(
+            "# HELP telegram_bot_api_call_seconds Time taken by Telegram API calls, by method.\n",
+        );
+        out.push_str("# TYPE telegram_bot_api_call_seconds histogram\n");
+        for (method, histogram) in self.api_latency.lock().unwrap().iter() {
+            histogram.render(
+                &mut out,
+                "telegram_bot_api_call_seconds",
+                &format!("method=\"{method}\""),
+            );
+        }
+
+        out.push_str("# HELP telegram_bot_api_errors_total Failed Telegram API calls, by method and error code.\n");
+        out.push_str("# TYPE telegram_bot_api_errors_total counter\n");
+        for ((method, status), count) in self.api_errors.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "telegram_bot_api_errors_total{{method=\"{method}\",status=\"{status}\"}} {count}"
+            );
+        }
+
+        let gauges = self.gauges.lock().unwrap();
+        out.push_str(
+            "# HELP telegram_bot_update_queue_depth Updates received but not yet handled.\n",
+        );
+        out.push_str("# TYPE telegram_bot_update_queue_depth gauge\n");
+        let _ = writeln!(
+            out,
+            "telegram_bot_update_queue_depth {}",
+            gauges.update_queue_depth
+        );
+
+        out.push_str(
+            "# HELP telegram_bot_outgoing_queue_depth Responses returned by handlers that are not sent yet.\n",
+        );
+        out.push_str("# TYPE telegram_bot_outgoing_queue_depth gauge\n");
+        let _ = writeln!(
+            out,
+            "telegram_bot_outgoing_queue_depth {}",
+            gauges.outgoing_queue_depth
+        );
+
+        out.push_str("# HELP telegram_bot_rate_limit_wait_seconds How long the bot was last told to wait by a 429 from Telegram, or 0 once the wait is over.\n");
+        out.push_str("# TYPE telegram_bot_rate_limit_wait_seconds gauge\n");
+        let _ = writeln!(
+            out,
+            "telegram_bot_rate_limit_wait_seconds {}",
+            gauges.rate_limit_wait.as_secs_f64()
+        );
+
+        out
+    }
+}
@@ -0,0 +1,39 @@
+use serde_json::{json, Value};
+
+use crate::{ApiError, Inner};
+
+impl Inner {
+    // Records that a group became a supergroup with a new ID, moving its session and calling the hook the first time.
+    pub(crate) fn migrate_chat(&self, old_id: i64, new_id: i64) -> anyhow::Result<()> {
+        let key = format!("migrated/{old_id}");
+        if self.store.get(&key)?.is_some() {
+            return Ok(());
+        }
+        tracing::info!("chat {old_id} migrated to {new_id}");
+        let session_key = format!("session/{old_id}");
+        if let Some(session) = self.store.get(&session_key)? {
+            self.store.set(&format!("session/{new_id}"), session)?;
+            self.store.remove(&session_key)?;
+        }
+        self.store.set(&key, json!(new_id))?;
+        if let Some(on_chat_migrated) = &self.on_chat_migrated {
+            on_chat_migrated(old_id, new_id);
+        }
+        Ok(())
+    }
+
+    // Points a call at the new ID of a chat that is known to have migrated.
+    pub(crate) fn apply_migration(&self, args: &mut Value) -> anyhow::Result<()> {
+        if let Some(old_id) = args["chat_id"].as_i64() {
+            if let Some(new_id) = self.store.get(&format!("migrated/{old_id}"))? {
+                args["chat_id"] = new_id;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The chat a failed call should be retried in, if it failed because the group became a supergroup.
+pub(crate) fn migrated_to(err: &anyhow::Error) -> Option<i64> {
+    err.downcast_ref::<ApiError>()?.parameters["migrate_to_chat_id"].as_i64()
+}
@@ -0,0 +1,110 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::BotHandle;
+
+/// The outcome of a Mini App asking the user, with `requestEmojiStatusAccess`, to let the bot set their emoji status. Mini Apps get it in the `emojiStatusAccessRequested` event and pass it on to their backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmojiStatusAccess {
+    Allowed,
+    Cancelled,
+}
+
+impl EmojiStatusAccess {
+    /// Parses the `status` field of the `emojiStatusAccessRequested` event.
+    pub fn from_status(status: &str) -> Option<Self> {
+        match status {
+            "allowed" => Some(Self::Allowed),
+            "cancelled" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// Which kinds of chats a prepared inline message may be shared to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShareTargets {
+    pub users: bool,
+    pub bots: bool,
+    pub groups: bool,
+    pub channels: bool,
+}
+
+impl Default for ShareTargets {
+    fn default() -> Self {
+        Self {
+            users: true,
+            bots: true,
+            groups: true,
+            channels: true,
+        }
+    }
+}
+
+/// A message prepared for a Mini App user to share, passed to `shareMessage` in the Mini App by its ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreparedInlineMessage {
+    pub id: String,
+    /// When the prepared message can no longer be shared, as a Unix timestamp.
+    pub expiration_date: i64,
+}
+
+impl PreparedInlineMessage {
+    /// Parses a prepared message from its JSON representation.
+    pub fn from_json(prepared: &Value) -> Option<Self> {
+        Some(Self {
+            id: prepared["id"].as_str()?.to_owned(),
+            expiration_date: prepared["expiration_date"].as_i64()?,
+        })
+    }
+}
+
+impl BotHandle {
+    /// Stores a message, given as a raw JSON `InlineQueryResult`, for a user of a Mini App to share.
+    pub async fn save_prepared_inline_message(
+        &self,
+        user_id: i64,
+        result: Value,
+        targets: ShareTargets,
+    ) -> anyhow::Result<PreparedInlineMessage> {
+        let prepared = self
+            .call_api(
+                "savePreparedInlineMessage",
+                json!({
+                    "user_id": user_id,
+                    "result": result,
+                    "allow_user_chats": targets.users,
+                    "allow_bot_chats": targets.bots,
+                    "allow_group_chats": targets.groups,
+                    "allow_channel_chats": targets.channels,
+                }),
+            )
+            .await
+            .context("cannot save prepared inline message")?;
+        PreparedInlineMessage::from_json(&prepared)
+            .context("savePreparedInlineMessage returned an invalid message")
+    }
+
+    /// Sets a user's emoji status to a custom emoji, optionally until a time has passed, or clears it if `custom_emoji_id` is None. The user must have allowed it through a Mini App first; see [EmojiStatusAccess].
+    pub async fn set_user_emoji_status(
+        &self,
+        user_id: i64,
+        custom_emoji_id: Option<&str>,
+        duration: Option<Duration>,
+    ) -> anyhow::Result<()> {
+        let mut args = json!({
+            "user_id": user_id,
+            "emoji_status_custom_emoji_id": custom_emoji_id.unwrap_or_default(),
+        });
+        if let Some(duration) = duration {
+            let expires = SystemTime::now().duration_since(UNIX_EPOCH)? + duration;
+            args["emoji_status_expiration_date"] = json!(expires.as_secs());
+        }
+        self.call_api("setUserEmojiStatus", args)
+            .await
+            .context("cannot set emoji status")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use smol::{
+    channel::{Receiver, Sender},
+    future::FutureExt,
+};
+
+use crate::transport::{Download, DownloadFuture, Transport, TransportFuture, Upload};
+
+/// An in-process stand-in for Telegram, for unit-testing bots without network access or a real token.
+///
+/// Updates pushed into it are returned by getUpdates, and every other call is recorded and answered with a canned result.
+#[derive(Clone)]
+pub struct MockTelegram {
+    state: Arc<Mutex<MockState>>,
+    pushed_send: Sender<()>,
+    pushed_recv: Receiver<()>,
+}
+
+#[derive(Default)]
+struct MockState {
+    updates: VecDeque<Value>,
+    next_update_id: i64,
+    next_message_id: i64,
+    calls: Vec<(String, Value)>,
+    results: HashMap<String, Value>,
+    files: HashMap<String, Vec<u8>>,
+    allowed_updates: Vec<String>,
+}
+
+impl Default for MockTelegram {
+    fn default() -> Self {
+        let (pushed_send, pushed_recv) = smol::channel::unbounded();
+        Self {
+            state: Default::default(),
+            pushed_send,
+            pushed_recv,
+        }
+    }
+}
+
+impl MockTelegram {
+    /// Creates a mock with no pending updates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues an update for getUpdates. An `update_id` is assigned if it has none.
+    pub fn push_update(&self, mut update: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.next_update_id += 1;
+        if update["update_id"].is_null() {
+            update["update_id"] = json!(state.next_update_id);
+        }
+        state.updates.push_back(update);
+        let _ = self.pushed_send.try_send(());
+    }
+
+    /// Queues a text message from a user in their private chat with the bot.
+    pub fn push_message(&self, user_id: i64, text: &str) {
+        let message_id = self.next_message_id();
+        self.push_update(json!({
+            "message": {
+                "message_id": message_id,
+                "date": 0,
+                "chat": {"id": user_id, "type": "private"},
+                "from": {"id": user_id, "is_bot": false, "first_name": "Test"},
+                "text": text,
+            }
+        }));
+    }
+
+    /// Sets the result returned for calls to a method, instead of the default.
+    pub fn respond_with(&self, method: &str, result: Value) {
+        self.state
+            .lock()
+            .unwrap()
+            .results
+            .insert(method.to_owned(), json!({"ok": true, "result": result}));
+    }
+
+    /// Makes calls to a method fail with the given Telegram error. Like Telegram, a 429 whose description ends in `retry after N` tells the bot to wait N seconds.
+    pub fn respond_with_error(&self, method: &str, error_code: i64, description: &str) {
+        let mut response =
+            json!({"ok": false, "error_code": error_code, "description": description});
+        if let Some(retry_after) = description
+            .rsplit_once("retry after ")
+            .and_then(|(_, secs)| secs.parse::<u64>().ok())
+        {
+            response["parameters"] = json!({ "retry_after": retry_after });
+        }
+        self.state
+            .lock()
+            .unwrap()
+            .results
+            .insert(method.to_owned(), response);
+    }
+
+    /// Makes a file available through getFile and downloads, under the given file_id.
+    pub fn add_file(&self, file_id: &str, data: &[u8]) {
+        self.state
+            .lock()
+            .unwrap()
+            .files
+            .insert(file_id.to_owned(), data.to_vec());
+    }
+
+    /// All calls made so far, other than getUpdates, as method names and arguments. Uploaded files appear in the arguments as `{"file_name": ..., "size": ...}`.
+    pub fn calls(&self) -> Vec<(String, Value)> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// The arguments of all calls made so far to a method.
+    pub fn calls_to(&self, method: &str) -> Vec<Value> {
+        self.calls()
+            .into_iter()
+            .filter(|(called, _)| called == method)
+            .map(|(_, args)| args)
+            .collect()
+    }
+
+    /// The updates getUpdates has not yet been told were processed, which Telegram would deliver again after a restart.
+    pub fn pending_updates(&self) -> Vec<Value> {
+        Vec::from(self.state.lock().unwrap().updates.clone())
+    }
+
+    /// The update types the bot last polled for with getUpdates, empty for Telegram's default set.
+    pub fn allowed_updates(&self) -> Vec<String> {
+        self.state.lock().unwrap().allowed_updates.clone()
+    }
+
+    /// Waits until at least the given number of calls were made, or a few seconds pass, then returns all calls.
+    pub async fn wait_for_calls(&self, count: usize) -> Vec<(String, Value)> {
+        for _ in 0..500 {
+            if self.state.lock().unwrap().calls.len() >= count {
+                break;
+            }
+            smol::Timer::after(Duration::from_millis(10)).await;
+        }
+        self.calls()
+    }
+
+    fn next_message_id(&self) -> i64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_message_id += 1;
+        state.next_message_id
+    }
+
+    // Records a call and returns its raw response.
+    fn answer(&self, method: &str, args: Value) -> Value {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push((method.to_owned(), args.clone()));
+        if let Some(result) = state.results.get(method) {
+            return result.clone();
+        }
+        if method == "getFile" {
+            let file_id = args["file_id"].as_str().unwrap_or_default();
+            return match state.files.get(file_id) {
+                Some(data) => json!({"ok": true, "result": {
+                    "file_id": file_id,
+                    "file_unique_id": file_id,
+                    "file_size": data.len(),
+                    "file_path": file_id,
+                }}),
+                None => {
+                    json!({"ok": false, "error_code": 400, "description": "Bad Request: invalid file_id"})
+                }
+            };
+        }
+        drop(state);
+        let result = if method.starts_with("send") {
+            json!({
+                "message_id": self.next_message_id(),
+                "date": 0,
+                "chat": {"id": args["chat_id"]},
+                "text": args["text"],
+            })
+        } else {
+            json!(true)
+        };
+        json!({"ok": true, "result": result})
+    }
+
+    // Returns pending updates at or after the offset, waiting up to the long-poll timeout for one to be pushed.
+    async fn get_updates(&self, args: &Value) -> Value {
+        let offset = args["offset"].as_i64().unwrap_or_default();
+        let timeout = Duration::from_secs(args["timeout"].as_u64().unwrap_or_default());
+        self.state.lock().unwrap().allowed_updates = args["allowed_updates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|t| t.as_str().map(str::to_owned))
+            .collect();
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                // like Telegram, updates stay pending until a later offset acknowledges them
+                state
+                    .updates
+                    .retain(|update| update["update_id"].as_i64().unwrap_or_default() >= offset);
+                if !state.updates.is_empty() || timeout.is_zero() {
+                    return json!({"ok": true, "result": Vec::from(state.updates.clone())});
+                }
+            }
+            let pushed = async { self.pushed_recv.recv().await.is_ok() }.or(async {
+                smol::Timer::after(timeout).await;
+                false
+            });
+            if !pushed.await {
+                return json!({"ok": true, "result": []});
+            }
+        }
+    }
+}
+
+impl Transport for MockTelegram {
+    fn call<'a>(
+        &'a self,
+        _token: &'a str,
+        method: &'a str,
+        args: &'a Value,
+    ) -> TransportFuture<'a> {
+        Box::pin(async move {
+            if method == "getUpdates" {
+                return Ok(self.get_updates(args).await);
+            }
+            Ok(self.answer(method, args.clone()))
+        })
+    }
+
+    fn upload<'a>(
+        &'a self,
+        _token: &'a str,
+        method: &'a str,
+        args: &'a Value,
+        uploads: &'a [Upload],
+    ) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let mut args = args.clone();
+            for upload in uploads {
+                args[&upload.field] =
+                    json!({"file_name": upload.file_name, "size": upload.data.len()});
+            }
+            Ok(self.answer(method, args))
+        })
+    }
+
+    fn download<'a>(&'a self, _token: &'a str, file_path: &'a str) -> DownloadFuture<'a> {
+        Box::pin(async move {
+            let data = self
+                .state
+                .lock()
+                .unwrap()
+                .files
+                .get(file_path)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("no file at {file_path}"))?;
+            Ok(Download {
+                size: Some(data.len() as u64),
+                body: Box::pin(smol::io::Cursor::new(data)),
+            })
+        })
+    }
+}
@@ -0,0 +1,250 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::BotHandle;
+
+/// The permissions of sending anything to a chat, which muting takes away.
+const SEND_PERMISSIONS: &[&str] = &[
+    "can_send_messages",
+    "can_send_audios",
+    "can_send_documents",
+    "can_send_photos",
+    "can_send_videos",
+    "can_send_video_notes",
+    "can_send_voice_notes",
+    "can_send_polls",
+    "can_send_other_messages",
+    "can_add_web_page_previews",
+];
+
+/// Parses a duration like `90s`, `15m`, `2h30m`, `1d`, or `2w`. Bare numbers are minutes.
+pub fn parse_duration(text: &str) -> anyhow::Result<Duration> {
+    let text = text.trim();
+    if let Ok(minutes) = text.parse::<u64>() {
+        let secs = minutes
+            .checked_mul(60)
+            .with_context(|| format!("duration {text:?} is too long"))?;
+        return Ok(Duration::from_secs(secs));
+    }
+    let mut total: u64 = 0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let count: u64 = rest[..digits]
+            .parse()
+            .with_context(|| format!("invalid duration {text:?}"))?;
+        let unit = rest[digits..].chars().next();
+        let secs = match unit {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 3600,
+            Some('d') => 86400,
+            Some('w') => 7 * 86400,
+            _ => anyhow::bail!(
+                "invalid duration {text:?}, expected units like 30s, 15m, 2h, 1d, or 1w"
+            ),
+        };
+        total = count
+            .checked_mul(secs)
+            .and_then(|secs| total.checked_add(secs))
+            .with_context(|| format!("duration {text:?} is too long"))?;
+        rest = &rest[digits + 1..];
+    }
+    if total == 0 {
+        anyhow::bail!("invalid duration {text:?}")
+    }
+    Ok(Duration::from_secs(total))
+}
+
+// The `until_date` for a restriction lasting the given duration from now.
+fn until_date(duration: &str) -> anyhow::Result<u64> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let until = now
+        .checked_add(parse_duration(duration)?)
+        .with_context(|| format!("duration {duration:?} is too long"))?;
+    Ok(until.as_secs())
+}
+
+impl BotHandle {
+    /// Bans a channel from posting in a group or channel on its own behalf, such as in a linked discussion group. Unlike banning a user, the channel's owner can still post as themselves. The bot must be an administrator with the right to restrict members.
+    pub async fn ban_sender_chat(&self, chat_id: i64, sender_chat_id: i64) -> anyhow::Result<()> {
+        self.moderate(
+            "banChatSenderChat",
+            json!({ "chat_id": chat_id, "sender_chat_id": sender_chat_id }),
+        )
+        .await
+    }
+
+    /// Lets a channel banned with [BotHandle::ban_sender_chat] post again.
+    pub async fn unban_sender_chat(&self, chat_id: i64, sender_chat_id: i64) -> anyhow::Result<()> {
+        self.moderate(
+            "unbanChatSenderChat",
+            json!({ "chat_id": chat_id, "sender_chat_id": sender_chat_id }),
+        )
+        .await
+    }
+
+    /// Sets the sticker set of a supergroup, after checking with getChat that the group can have one, which needs enough members or boosts. The bot must be an administrator with the right to change the group's info.
+    pub async fn set_chat_sticker_set(
+        &self,
+        chat_id: i64,
+        sticker_set_name: &str,
+    ) -> anyhow::Result<()> {
+        self.check_can_set_sticker_set(chat_id).await?;
+        self.moderate(
+            "setChatStickerSet",
+            json!({ "chat_id": chat_id, "sticker_set_name": sticker_set_name }),
+        )
+        .await
+    }
+
+    /// Removes the sticker set of a supergroup, after checking with getChat that the group can have one.
+    pub async fn delete_chat_sticker_set(&self, chat_id: i64) -> anyhow::Result<()> {
+        self.check_can_set_sticker_set(chat_id).await?;
+        self.moderate("deleteChatStickerSet", json!({ "chat_id": chat_id }))
+            .await
+    }
+
+    /// Sets the custom title shown next to the name of an administrator the bot promoted, up to 16 characters without emoji. An empty title removes it.
+    pub async fn set_admin_title(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        custom_title: &str,
+    ) -> anyhow::Result<()> {
+        let len = custom_title.chars().count();
+        if len > 16 {
+            anyhow::bail!("admin title is {len} characters, more than Telegram's limit of 16")
+        }
+        self.moderate(
+            "setChatAdministratorCustomTitle",
+            json!({
+                "chat_id": chat_id,
+                "user_id": user_id,
+                "custom_title": custom_title,
+            }),
+        )
+        .await
+    }
+
+    /// Applies the given `ChatPermissions`, as raw JSON, to a member of a supergroup for a duration like `2h30m`. Telegram treats durations under 30 seconds or over 366 days as forever.
+    pub async fn restrict_for(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        duration: &str,
+        permissions: Value,
+    ) -> anyhow::Result<()> {
+        self.moderate(
+            "restrictChatMember",
+            json!({
+                "chat_id": chat_id,
+                "user_id": user_id,
+                "permissions": permissions,
+                "until_date": until_date(duration)?,
+            }),
+        )
+        .await
+    }
+
+    /// Stops a member of a supergroup from sending anything for a duration like `2h30m`.
+    pub async fn mute_for(&self, chat_id: i64, user_id: i64, duration: &str) -> anyhow::Result<()> {
+        let permissions: serde_json::Map<String, Value> = SEND_PERMISSIONS
+            .iter()
+            .map(|name| (name.to_string(), json!(false)))
+            .collect();
+        self.restrict_for(chat_id, user_id, duration, permissions.into())
+            .await
+    }
+
+    /// Lets a muted member send messages again, within the chat's default permissions.
+    pub async fn unmute(&self, chat_id: i64, user_id: i64) -> anyhow::Result<()> {
+        let permissions: serde_json::Map<String, Value> = SEND_PERMISSIONS
+            .iter()
+            .map(|name| (name.to_string(), json!(true)))
+            .collect();
+        self.moderate(
+            "restrictChatMember",
+            json!({
+                "chat_id": chat_id,
+                "user_id": user_id,
+                "permissions": permissions,
+            }),
+        )
+        .await
+    }
+
+    /// Bans a user from a group or channel for a duration like `1d`, after which they may rejoin.
+    pub async fn ban_for(&self, chat_id: i64, user_id: i64, duration: &str) -> anyhow::Result<()> {
+        self.moderate(
+            "banChatMember",
+            json!({
+                "chat_id": chat_id,
+                "user_id": user_id,
+                "until_date": until_date(duration)?,
+            }),
+        )
+        .await
+    }
+
+    async fn check_can_set_sticker_set(&self, chat_id: i64) -> anyhow::Result<()> {
+        let chat = self
+            .call_api("getChat", json!({ "chat_id": chat_id }))
+            .await
+            .with_context(|| format!("cannot get chat {chat_id}"))?;
+        if chat["can_set_sticker_set"].as_bool() != Some(true) {
+            anyhow::bail!(
+                "chat {chat_id} cannot have a group sticker set, or the bot cannot change it"
+            )
+        }
+        Ok(())
+    }
+
+    async fn moderate(&self, method: &str, args: Value) -> anyhow::Result<()> {
+        self.call_api(method, args)
+            .await
+            .with_context(|| format!("cannot call {method}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(
+            parse_duration(" 15 ").unwrap(),
+            Duration::from_secs(15 * 60)
+        );
+        assert_eq!(
+            parse_duration("2h30m").unwrap(),
+            Duration::from_secs(2 * 3600 + 30 * 60)
+        );
+        assert_eq!(
+            parse_duration("1w1d").unwrap(),
+            Duration::from_secs(8 * 86400)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for text in ["", "0m", "5x", "h", "1h30", "-5m"] {
+            assert!(parse_duration(text).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert!(parse_duration("99999999999999999w").is_err());
+        assert!(parse_duration("999999999999999999").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+        assert!(until_date("18446744073709551615s").is_err());
+    }
+}
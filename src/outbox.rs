@@ -0,0 +1,68 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::{ephemeral::unix_now, Inner};
+
+/// How long a send is remembered under its idempotency key.
+const REMEMBER_FOR: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often expired sends are pruned from the store, since that reads every entry.
+const PRUNE_EVERY: Duration = Duration::from_secs(60 * 60);
+
+// When expired sends were last pruned, as a Unix timestamp.
+#[derive(Default)]
+pub(crate) struct PruneClock(AtomicU64);
+
+impl Inner {
+    // The message already sent under the idempotency key, if any.
+    pub(crate) fn already_sent(&self, key: &str) -> anyhow::Result<Option<Value>> {
+        Ok(self
+            .store
+            .get(&format!("outbox/{key}"))?
+            .filter(|entry| {
+                entry["sent_at"].as_u64().unwrap_or_default() + REMEMBER_FOR.as_secs() > unix_now()
+            })
+            .map(|entry| entry["sent"].clone()))
+    }
+
+    // Records that a message was sent under the idempotency key. Failing to record it is only logged, since the message did go out and failing would invite the very retry that sends it twice.
+    pub(crate) fn record_sent(&self, key: &str, sent: &Value) {
+        let now = unix_now();
+        let recorded = self
+            .store
+            .set(
+                &format!("outbox/{key}"),
+                json!({"sent": sent, "sent_at": now}),
+            )
+            .and_then(|_| self.forget_expired_sends(now));
+        if let Err(err) = recorded {
+            tracing::warn!("cannot record send of {key} in the outbox: {:?}", err);
+        }
+    }
+
+    fn forget_expired_sends(&self, now: u64) -> anyhow::Result<()> {
+        let last = self.outbox_pruned.0.load(Ordering::Relaxed);
+        let due = last + PRUNE_EVERY.as_secs() <= now
+            && self
+                .outbox_pruned
+                .0
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok();
+        if !due {
+            return Ok(());
+        }
+        for key in self.store.keys("outbox/")? {
+            let expired = self.store.get(&key)?.is_some_and(|entry| {
+                entry["sent_at"].as_u64().unwrap_or_default() + REMEMBER_FOR.as_secs() <= now
+            });
+            if expired {
+                self.store.remove(&key)?;
+            }
+        }
+        Ok(())
+    }
+}
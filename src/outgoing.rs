@@ -0,0 +1,737 @@
+use serde_json::{json, Value};
+
+use crate::{
+    entities::{utf16_len, utf16_to_byte},
+    format::ParseMode,
+    transport::Upload,
+    Response,
+};
+
+/// The most characters Telegram allows in a media caption.
+pub(crate) const MAX_CAPTION_LEN: usize = 1024;
+
+/// The most characters Telegram allows in a text message.
+pub(crate) const MAX_TEXT_LEN: usize = 4096;
+
+/// A file to send to Telegram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputFile {
+    /// A new file, uploaded from memory under the given file name.
+    Bytes { file_name: String, data: Vec<u8> },
+    /// A file already on Telegram's servers, by its `file_id`.
+    FileId(String),
+    /// A file on the web, which Telegram downloads itself. Photos may be up to 5 MB and other files up to 20 MB.
+    Url(String),
+}
+
+impl InputFile {
+    /// A new file, uploaded from memory.
+    pub fn bytes(file_name: &str, data: impl Into<Vec<u8>>) -> Self {
+        Self::Bytes {
+            file_name: file_name.to_owned(),
+            data: data.into(),
+        }
+    }
+
+    /// A file already on Telegram's servers.
+    pub fn file_id(file_id: &str) -> Self {
+        Self::FileId(file_id.to_owned())
+    }
+
+    /// A file on the web, by its HTTP or HTTPS URL.
+    pub fn url(url: &str) -> Self {
+        Self::Url(url.to_owned())
+    }
+
+    // Puts the file into the arguments under the field, or into the uploads if it has to be uploaded.
+    fn attach(&self, field: &str, args: &mut Value, uploads: &mut Vec<Upload>) {
+        match self {
+            Self::Bytes { file_name, data } => uploads.push(Upload {
+                field: field.to_owned(),
+                file_name: file_name.clone(),
+                data: data.clone(),
+            }),
+            Self::FileId(file_id) => args[field] = json!(file_id),
+            Self::Url(url) => args[field] = json!(url),
+        }
+    }
+
+    // Like attach, but a new file is uploaded under another name and referenced with `attach://`, as Telegram requires for thumbnails.
+    fn attach_by_reference(&self, field: &str, args: &mut Value, uploads: &mut Vec<Upload>) {
+        args[field] = self.reference(&format!("{field}_file"), uploads);
+    }
+
+    // The value referring to the file, uploading a new file under the given name and referencing it with `attach://`.
+    pub(crate) fn reference(&self, attach_name: &str, uploads: &mut Vec<Upload>) -> Value {
+        match self {
+            Self::Bytes { file_name, data } => {
+                uploads.push(Upload {
+                    field: attach_name.to_owned(),
+                    file_name: file_name.clone(),
+                    data: data.clone(),
+                });
+                json!(format!("attach://{attach_name}"))
+            }
+            Self::FileId(file_id) => json!(file_id),
+            Self::Url(url) => json!(url),
+        }
+    }
+}
+
+/// The kinds of media that are sent as a single file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaKind {
+    Photo,
+    Document,
+    Video,
+    Audio,
+    Voice,
+    Animation,
+    Sticker,
+}
+
+impl MediaKind {
+    // The name of the field the file goes in, which is also the suffix of the send method.
+    fn field(self) -> &'static str {
+        match self {
+            Self::Photo => "photo",
+            Self::Document => "document",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Voice => "voice",
+            Self::Animation => "animation",
+            Self::Sticker => "sticker",
+        }
+    }
+
+    fn can_have_spoiler(self) -> bool {
+        matches!(self, Self::Photo | Self::Video | Self::Animation)
+    }
+
+    fn has_thumbnail(self) -> bool {
+        matches!(
+            self,
+            Self::Document | Self::Video | Self::Audio | Self::Animation
+        )
+    }
+
+    fn method(self) -> &'static str {
+        match self {
+            Self::Photo => "sendPhoto",
+            Self::Document => "sendDocument",
+            Self::Video => "sendVideo",
+            Self::Audio => "sendAudio",
+            Self::Voice => "sendVoice",
+            Self::Animation => "sendAnimation",
+            Self::Sticker => "sendSticker",
+        }
+    }
+}
+
+/// What an [OutgoingMessage] contains.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageContent {
+    Text {
+        text: String,
+        disable_link_preview: bool,
+    },
+    /// A photo, document, or other single file. Stickers ignore the caption.
+    Media {
+        kind: MediaKind,
+        file: InputFile,
+        caption: Option<String>,
+        /// A small preview image for documents, videos, audio, and animations. Telegram only accepts new uploads here, not file_ids or URLs.
+        thumbnail: Option<InputFile>,
+        /// Hides a photo, video, or animation behind a spoiler animation until tapped.
+        has_spoiler: bool,
+    },
+    /// Several photos and videos, or several documents, or several audio files, sent as an album of 2 to 10 items. The caption of the first item becomes the album's caption.
+    Album(Vec<InputMedia>),
+    Location {
+        latitude: f64,
+        longitude: f64,
+    },
+    Poll {
+        question: String,
+        options: Vec<String>,
+        is_anonymous: bool,
+        allows_multiple_answers: bool,
+    },
+}
+
+/// An item of an album.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputMedia {
+    /// A photo, video, document, or audio file. Other kinds cannot go in albums.
+    pub kind: MediaKind,
+    pub file: InputFile,
+    pub caption: Option<String>,
+    /// Hides a photo or video behind a spoiler animation until tapped.
+    pub has_spoiler: bool,
+}
+
+impl InputMedia {
+    /// An album item without a caption.
+    pub fn new(kind: MediaKind, file: InputFile) -> Self {
+        Self {
+            kind,
+            file,
+            caption: None,
+            has_spoiler: false,
+        }
+    }
+
+    /// Sets the caption of the item.
+    pub fn caption(mut self, caption: &str) -> Self {
+        self.caption = Some(caption.to_owned());
+        self
+    }
+
+    /// Hides a photo or video behind a spoiler animation until tapped.
+    pub fn spoiler(mut self) -> Self {
+        self.has_spoiler = true;
+        self
+    }
+
+    // The item as Telegram expects it in the `media` argument, uploading a new file under the given name.
+    fn to_json(
+        &self,
+        attach_name: &str,
+        parse_mode: Option<ParseMode>,
+        uploads: &mut Vec<Upload>,
+    ) -> Value {
+        let mut media = json!({
+            "type": self.kind.field(),
+            "media": self.file.reference(attach_name, uploads),
+        });
+        if let Some(caption) = &self.caption {
+            media["caption"] = json!(caption);
+            if let Some(parse_mode) = parse_mode {
+                media["parse_mode"] = json!(parse_mode.as_str());
+            }
+        }
+        if self.has_spoiler && self.kind.can_have_spoiler() {
+            media["has_spoiler"] = json!(true);
+        }
+        media
+    }
+}
+
+/// What to do with a caption longer than Telegram allows.
+///
+/// Only plain captions are shortened; formatted ones are left alone, since cutting them could break their markup.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaptionOverflow {
+    /// Send the caption as is, so Telegram rejects the message.
+    #[default]
+    Keep,
+    /// Cut the caption short, ending it with an ellipsis.
+    Truncate,
+    /// Send as much of the caption as fits, and the rest in text messages right after.
+    FollowUp,
+}
+
+/// Options shared by all kinds of outgoing messages.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SendOptions {
+    pub reply_to_message_id: Option<i64>,
+    /// How the text or caption is formatted. Plain text if None.
+    pub parse_mode: Option<ParseMode>,
+    /// Sends the message without a notification sound.
+    pub disable_notification: bool,
+    /// Prevents the message from being forwarded or saved.
+    pub protect_content: bool,
+    /// An inline keyboard, custom reply keyboard, or similar, as raw JSON.
+    pub reply_markup: Option<Value>,
+    pub caption_overflow: CaptionOverflow,
+    /// An animated effect, like confetti, played when the message arrives. Only works in private chats.
+    pub message_effect_id: Option<String>,
+    /// Tags the message so that sending it again with the same key, such as when retrying after an error, does not send it twice once it went through. See [crate::TelegramBot::send].
+    pub idempotency_key: Option<String>,
+}
+
+/// A message for the bot to send, of any kind.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutgoingMessage {
+    pub chat_id: i64,
+    pub content: MessageContent,
+    pub options: SendOptions,
+}
+
+// A single API call, ready to be made.
+pub(crate) struct ApiRequest {
+    pub method: &'static str,
+    pub args: Value,
+    pub uploads: Vec<Upload>,
+    pub idempotency_key: Option<String>,
+}
+
+impl OutgoingMessage {
+    /// A message with the given content and default options.
+    pub fn new(chat_id: i64, content: MessageContent) -> Self {
+        Self {
+            chat_id,
+            content,
+            options: SendOptions::default(),
+        }
+    }
+
+    /// A plain text message.
+    pub fn text(chat_id: i64, text: &str) -> Self {
+        Self::new(
+            chat_id,
+            MessageContent::Text {
+                text: text.to_owned(),
+                disable_link_preview: false,
+            },
+        )
+    }
+
+    /// A photo, document, or other single file, without a caption.
+    pub fn media(chat_id: i64, kind: MediaKind, file: InputFile) -> Self {
+        Self::new(
+            chat_id,
+            MessageContent::Media {
+                kind,
+                file,
+                caption: None,
+                thumbnail: None,
+                has_spoiler: false,
+            },
+        )
+    }
+
+    /// An album of 2 to 10 photos, videos, documents, or audio files.
+    pub fn album(chat_id: i64, items: Vec<InputMedia>) -> Self {
+        Self::new(chat_id, MessageContent::Album(items))
+    }
+
+    /// A photo, without a caption.
+    pub fn photo(chat_id: i64, photo: InputFile) -> Self {
+        Self::media(chat_id, MediaKind::Photo, photo)
+    }
+
+    /// A document, without a caption.
+    pub fn document(chat_id: i64, document: InputFile) -> Self {
+        Self::media(chat_id, MediaKind::Document, document)
+    }
+
+    /// A sticker.
+    pub fn sticker(chat_id: i64, sticker: InputFile) -> Self {
+        Self::media(chat_id, MediaKind::Sticker, sticker)
+    }
+
+    /// A point on the map.
+    pub fn location(chat_id: i64, latitude: f64, longitude: f64) -> Self {
+        Self::new(
+            chat_id,
+            MessageContent::Location {
+                latitude,
+                longitude,
+            },
+        )
+    }
+
+    /// An anonymous single-answer poll.
+    pub fn poll(chat_id: i64, question: &str, options: &[&str]) -> Self {
+        Self::new(
+            chat_id,
+            MessageContent::Poll {
+                question: question.to_owned(),
+                options: options.iter().map(|option| option.to_string()).collect(),
+                is_anonymous: true,
+                allows_multiple_answers: false,
+            },
+        )
+    }
+
+    // Turns the message into the API calls that send it, which are several if the caption overflows into follow-up messages.
+    pub(crate) fn to_requests(&self) -> Vec<ApiRequest> {
+        let mut requests = self.split_requests();
+        if let Some(key) = &self.options.idempotency_key {
+            // each part is tagged separately, so a retry only sends the parts that did not go through
+            for (i, req) in requests.iter_mut().enumerate() {
+                req.idempotency_key = Some(format!("{key}/{i}"));
+            }
+        }
+        requests
+    }
+
+    fn split_requests(&self) -> Vec<ApiRequest> {
+        let overflowing = match &self.content {
+            MessageContent::Media {
+                kind,
+                caption: Some(caption),
+                ..
+            } if *kind != MediaKind::Sticker
+                && self.options.parse_mode.is_none()
+                && utf16_len(caption) > MAX_CAPTION_LEN =>
+            {
+                caption
+            }
+            _ => return vec![self.to_request(None)],
+        };
+        match self.options.caption_overflow {
+            CaptionOverflow::Keep => vec![self.to_request(None)],
+            CaptionOverflow::Truncate => {
+                let (head, _) = split_text(overflowing, MAX_CAPTION_LEN - 1);
+                vec![self.to_request(Some(&format!("{head}…")))]
+            }
+            CaptionOverflow::FollowUp => {
+                let (head, mut rest) = split_text(overflowing, MAX_CAPTION_LEN);
+                let mut requests = vec![self.to_request(Some(head))];
+                while !rest.is_empty() {
+                    let (chunk, next) = split_text(rest, MAX_TEXT_LEN);
+                    let mut follow_up = Self::text(self.chat_id, chunk);
+                    follow_up.options.disable_notification = self.options.disable_notification;
+                    follow_up.options.protect_content = self.options.protect_content;
+                    requests.push(follow_up.to_request(None));
+                    rest = next;
+                }
+                requests
+            }
+        }
+    }
+
+    // Turns a text message into the call that edits an earlier message to match it. Other kinds of message cannot be edited into.
+    pub(crate) fn to_edit_request(&self, message_id: i64) -> Option<ApiRequest> {
+        let MessageContent::Text {
+            text,
+            disable_link_preview,
+        } = &self.content
+        else {
+            return None;
+        };
+        let mut args = json!({
+            "chat_id": self.chat_id,
+            "message_id": message_id,
+            "text": text,
+        });
+        if *disable_link_preview {
+            args["link_preview_options"] = json!({"is_disabled": true});
+        }
+        if let Some(parse_mode) = self.options.parse_mode {
+            args["parse_mode"] = json!(parse_mode.as_str());
+        }
+        if let Some(reply_markup) = &self.options.reply_markup {
+            args["reply_markup"] = reply_markup.clone();
+        }
+        Some(ApiRequest {
+            method: "editMessageText",
+            args,
+            uploads: vec![],
+            idempotency_key: None,
+        })
+    }
+
+    // Turns the message into the API call that sends it, optionally with a different caption.
+    fn to_request(&self, caption_override: Option<&str>) -> ApiRequest {
+        let mut args = json!({"chat_id": self.chat_id});
+        let mut uploads = vec![];
+        let method = match &self.content {
+            MessageContent::Text {
+                text,
+                disable_link_preview,
+            } => {
+                args["text"] = json!(text);
+                if *disable_link_preview {
+                    args["link_preview_options"] = json!({"is_disabled": true});
+                }
+                "sendMessage"
+            }
+            MessageContent::Media {
+                kind,
+                file,
+                caption,
+                thumbnail,
+                has_spoiler,
+            } => {
+                file.attach(kind.field(), &mut args, &mut uploads);
+                if *has_spoiler && kind.can_have_spoiler() {
+                    args["has_spoiler"] = json!(true);
+                }
+                match thumbnail.as_ref().filter(|_| kind.has_thumbnail()) {
+                    Some(thumbnail @ InputFile::Bytes { .. }) => {
+                        thumbnail.attach_by_reference("thumbnail", &mut args, &mut uploads)
+                    }
+                    Some(_) => tracing::warn!("leaving out a thumbnail that is not an upload"),
+                    None => {}
+                }
+                if let Some(caption) = caption_override
+                    .or(caption.as_deref())
+                    .filter(|_| *kind != MediaKind::Sticker)
+                {
+                    args["caption"] = json!(caption);
+                }
+                kind.method()
+            }
+            MessageContent::Album(items) => {
+                args["media"] = items
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, item)| {
+                        item.to_json(&format!("file{idx}"), self.options.parse_mode, &mut uploads)
+                    })
+                    .collect();
+                "sendMediaGroup"
+            }
+            MessageContent::Location {
+                latitude,
+                longitude,
+            } => {
+                args["latitude"] = json!(latitude);
+                args["longitude"] = json!(longitude);
+                "sendLocation"
+            }
+            MessageContent::Poll {
+                question,
+                options,
+                is_anonymous,
+                allows_multiple_answers,
+            } => {
+                args["question"] = json!(question);
+                args["options"] = options.iter().map(|text| json!({"text": text})).collect();
+                args["is_anonymous"] = json!(is_anonymous);
+                args["allows_multiple_answers"] = json!(allows_multiple_answers);
+                "sendPoll"
+            }
+        };
+        let options = &self.options;
+        if let Some(reply_to_message_id) = options.reply_to_message_id {
+            args["reply_to_message_id"] = json!(reply_to_message_id);
+        }
+        // album items carry their own parse mode
+        if let Some(parse_mode) = options.parse_mode.filter(|_| method != "sendMediaGroup") {
+            args["parse_mode"] = json!(parse_mode.as_str());
+        }
+        if options.disable_notification {
+            args["disable_notification"] = json!(true);
+        }
+        if options.protect_content {
+            args["protect_content"] = json!(true);
+        }
+        if let Some(reply_markup) = &options.reply_markup {
+            args["reply_markup"] = reply_markup.clone();
+        }
+        if let Some(effect_id) = &options.message_effect_id {
+            args["message_effect_id"] = json!(effect_id);
+        }
+        ApiRequest {
+            method,
+            args,
+            uploads,
+            idempotency_key: None,
+        }
+    }
+}
+
+// Splits text into at most the given number of UTF-16 code units and the rest, preferring to split at whitespace.
+pub(crate) fn split_text(text: &str, limit: usize) -> (&str, &str) {
+    if utf16_len(text) <= limit {
+        return (text, "");
+    }
+    let mut end = (0..=limit)
+        .rev()
+        .find_map(|offset| utf16_to_byte(text, offset))
+        .unwrap_or_default();
+    if let Some(space) = text[..end].rfind(char::is_whitespace) {
+        if space > end / 2 {
+            end = space;
+        }
+    }
+    (text[..end].trim_end(), text[end..].trim_start())
+}
+
+impl From<Response> for OutgoingMessage {
+    fn from(resp: Response) -> Self {
+        let mut msg = Self::text(resp.chat_id, &resp.text);
+        msg.options.reply_to_message_id = resp.reply_to_message_id;
+        msg
+    }
+}
+
+impl Response {
+    /// Starts building a message to the chat with several options, like `Response::builder(chat_id).text("<b>hi</b>").html().silent().build()`.
+    pub fn builder(chat_id: i64) -> ResponseBuilder {
+        ResponseBuilder {
+            msg: OutgoingMessage::text(chat_id, ""),
+        }
+    }
+}
+
+/// A builder for an [OutgoingMessage], created by [Response::builder].
+#[derive(Clone, Debug)]
+pub struct ResponseBuilder {
+    msg: OutgoingMessage,
+}
+
+impl ResponseBuilder {
+    /// Makes this a text message with the given text.
+    pub fn text(mut self, text: &str) -> Self {
+        self.msg.content = MessageContent::Text {
+            text: text.to_owned(),
+            disable_link_preview: false,
+        };
+        self
+    }
+
+    /// Makes this a photo, document, or other single file.
+    pub fn media(mut self, kind: MediaKind, file: InputFile) -> Self {
+        self.msg.content = MessageContent::Media {
+            kind,
+            file,
+            caption: None,
+            thumbnail: None,
+            has_spoiler: false,
+        };
+        self
+    }
+
+    /// Sets the caption of a media message.
+    pub fn caption(mut self, text: &str) -> Self {
+        if let MessageContent::Media { caption, .. } = &mut self.msg.content {
+            *caption = Some(text.to_owned());
+        }
+        self
+    }
+
+    /// Sets the thumbnail of a document, video, audio, or animation message, uploaded from memory, since Telegram takes no file IDs or URLs for thumbnails.
+    pub fn thumbnail(mut self, file_name: &str, data: impl Into<Vec<u8>>) -> Self {
+        if let MessageContent::Media { thumbnail, .. } = &mut self.msg.content {
+            *thumbnail = Some(InputFile::bytes(file_name, data));
+        }
+        self
+    }
+
+    /// Hides a photo, video, or animation behind a spoiler animation until tapped.
+    pub fn spoiler(mut self) -> Self {
+        if let MessageContent::Media { has_spoiler, .. } = &mut self.msg.content {
+            *has_spoiler = true;
+        }
+        self
+    }
+
+    /// Plays an animated effect, by its ID, when the message arrives. Only works in private chats.
+    pub fn effect(mut self, effect_id: &str) -> Self {
+        self.msg.options.message_effect_id = Some(effect_id.to_owned());
+        self
+    }
+
+    /// Tags the message so that sending it again with the same key does not send it twice. See [SendOptions::idempotency_key].
+    pub fn idempotency_key(mut self, key: &str) -> Self {
+        self.msg.options.idempotency_key = Some(key.to_owned());
+        self
+    }
+
+    /// Formats the text or caption as HTML.
+    pub fn html(self) -> Self {
+        self.parse_mode(ParseMode::Html)
+    }
+
+    /// Formats the text or caption as MarkdownV2.
+    pub fn markdown_v2(self) -> Self {
+        self.parse_mode(ParseMode::MarkdownV2)
+    }
+
+    pub fn parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.msg.options.parse_mode = Some(parse_mode);
+        self
+    }
+
+    /// Sends the message as a reply to the given message.
+    pub fn reply_to(mut self, message_id: i64) -> Self {
+        self.msg.options.reply_to_message_id = Some(message_id);
+        self
+    }
+
+    /// Sends the message without a notification sound.
+    pub fn silent(mut self) -> Self {
+        self.msg.options.disable_notification = true;
+        self
+    }
+
+    /// Prevents the message from being forwarded or saved.
+    pub fn protected(mut self) -> Self {
+        self.msg.options.protect_content = true;
+        self
+    }
+
+    /// Disables the link preview of a text message.
+    pub fn no_link_preview(mut self) -> Self {
+        if let MessageContent::Text {
+            disable_link_preview,
+            ..
+        } = &mut self.msg.content
+        {
+            *disable_link_preview = true;
+        }
+        self
+    }
+
+    /// Sets what to do with a caption longer than Telegram allows.
+    pub fn caption_overflow(mut self, overflow: CaptionOverflow) -> Self {
+        self.msg.options.caption_overflow = overflow;
+        self
+    }
+
+    /// Attaches a keyboard, such as an [crate::keyboard::InlineKeyboard].
+    pub fn keyboard(mut self, keyboard: impl Into<Value>) -> Self {
+        self.msg.options.reply_markup = Some(keyboard.into());
+        self
+    }
+
+    pub fn build(self) -> OutgoingMessage {
+        self.msg
+    }
+}
+
+impl From<ResponseBuilder> for OutgoingMessage {
+    fn from(builder: ResponseBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_not_split() {
+        assert_eq!(split_text("hello", 5), ("hello", ""));
+    }
+
+    #[test]
+    fn splits_at_whitespace_within_the_limit() {
+        assert_eq!(
+            split_text("hello there world", 12),
+            ("hello there", "world")
+        );
+        // whitespace too early in the text is not worth wasting most of the message on
+        assert_eq!(split_text("a bcdefghijkl", 8), ("a bcdefg", "hijkl"));
+    }
+
+    #[test]
+    fn splits_by_utf16_code_units_without_breaking_characters() {
+        let text = "😀".repeat(5);
+        // each emoji is two code units, so an odd limit ends before the emoji it would split
+        let (chunk, rest) = split_text(&text, 5);
+        assert_eq!(chunk, "😀😀");
+        assert_eq!(rest, "😀😀😀");
+        assert!(utf16_len(chunk) <= 5);
+    }
+
+    #[test]
+    fn overflowing_captions_follow_up_within_the_limits() {
+        let mut msg = OutgoingMessage::photo(1, InputFile::file_id("photo"));
+        if let MessageContent::Media { caption, .. } = &mut msg.content {
+            *caption = Some("😀 ".repeat(2000));
+        }
+        msg.options.caption_overflow = CaptionOverflow::FollowUp;
+        let requests = msg.to_requests();
+        assert_eq!(requests.len(), 3);
+        assert!(utf16_len(requests[0].args["caption"].as_str().unwrap()) <= MAX_CAPTION_LEN);
+        for req in &requests[1..] {
+            assert!(utf16_len(req.args["text"].as_str().unwrap()) <= MAX_TEXT_LEN);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use smol::channel::{Receiver, Sender};
+
+use crate::TelegramBot;
+
+// Whether polling is paused, with a way for the polling task to wait for changes.
+pub(crate) struct PauseSwitch {
+    paused: AtomicBool,
+    changed_send: Sender<()>,
+    changed_recv: Receiver<()>,
+}
+
+impl Default for PauseSwitch {
+    fn default() -> Self {
+        let (changed_send, changed_recv) = smol::channel::bounded(1);
+        Self {
+            paused: AtomicBool::new(false),
+            changed_send,
+            changed_recv,
+        }
+    }
+}
+
+impl PauseSwitch {
+    pub fn set(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+        // a full channel already has a wakeup pending
+        let _ = self.changed_send.try_send(());
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    // Waits until the switch is in the given state.
+    pub async fn wait_until(&self, paused: bool) {
+        while self.is_paused() != paused {
+            let _ = self.changed_recv.recv().await;
+        }
+    }
+}
+
+impl TelegramBot {
+    /// Stops fetching updates, leaving new ones queued at Telegram until [TelegramBot::resume] is called. Updates already fetched are still handled.
+    pub fn pause(&self) {
+        self.inner.pause.set(true)
+    }
+
+    /// Resumes fetching updates after [TelegramBot::pause].
+    pub fn resume(&self) {
+        self.inner.pause.set(false)
+    }
+}
@@ -0,0 +1,57 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    transport::{HttpOptions, Transport},
+    TelegramBot, TelegramBotBuilder,
+};
+
+/// Several bots run from one process, sharing one HTTP client and its connections to Telegram. Each bot has its own token, options, and handler.
+pub struct BotPool {
+    transport: Arc<dyn Transport>,
+    bots: BTreeMap<String, TelegramBot>,
+}
+
+impl BotPool {
+    /// Creates an empty pool whose bots share a default HTTPS transport.
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            transport: HttpOptions::default().build_transport()?,
+            bots: BTreeMap::new(),
+        })
+    }
+
+    /// Creates an empty pool whose bots share the given transport.
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            bots: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a builder for a bot using the pool's shared transport. Add the built bot with [BotPool::add].
+    pub fn builder(&self, bot_token: &str) -> TelegramBotBuilder {
+        let mut builder = TelegramBot::builder(bot_token);
+        builder.transport = Some(self.transport.clone());
+        builder
+    }
+
+    /// Adds a running bot under a name, replacing and stopping any bot of the same name.
+    pub fn add(&mut self, name: &str, bot: TelegramBot) {
+        self.bots.insert(name.to_owned(), bot);
+    }
+
+    /// Removes a bot from the pool and returns it. It keeps handling updates until the returned bot is dropped.
+    pub fn remove(&mut self, name: &str) -> Option<TelegramBot> {
+        self.bots.remove(name)
+    }
+
+    /// Gets a bot by name.
+    pub fn get(&self, name: &str) -> Option<&TelegramBot> {
+        self.bots.get(name)
+    }
+
+    /// All bots, by name.
+    pub fn bots(&self) -> impl Iterator<Item = (&str, &TelegramBot)> {
+        self.bots.iter().map(|(name, bot)| (name.as_str(), bot))
+    }
+}
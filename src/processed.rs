@@ -0,0 +1,70 @@
+use serde_json::{json, Value};
+
+use crate::Inner;
+
+/// The most update IDs remembered as processed, for update sources without acknowledgements to prune them by.
+const MAX_REMEMBERED: usize = 1000;
+
+// The offset store: which updates Telegram was last told were handled, and which updates after that the bot already processed. Telegram delivers updates again until a getUpdates call acknowledges them, so after a restart the bot gets back whatever it processed since the last acknowledgement.
+const ACKNOWLEDGED_KEY: &str = "update_offset/acknowledged";
+const PROCESSED_KEY: &str = "update_offset/processed";
+
+impl Inner {
+    // Whether the update was already processed, such as before a restart. Errors reading the store count as not processed, since not handling an update at all is worse.
+    pub(crate) fn already_processed(&self, update_id: i64) -> bool {
+        self.remember_processed
+            && self
+                .processed_updates()
+                .inspect_err(|err| tracing::warn!("cannot read processed updates: {:?}", err))
+                .is_ok_and(|processed| processed.contains(&update_id))
+    }
+
+    // Records that the update was processed, forgetting the updates Telegram will no longer deliver again.
+    pub(crate) fn record_processed(&self, update_id: i64) {
+        if !self.remember_processed {
+            return;
+        }
+        let recorded = self.processed_updates().and_then(|mut processed| {
+            let acknowledged = self.store.get(ACKNOWLEDGED_KEY)?.and_then(|id| id.as_i64());
+            processed.retain(|&id| acknowledged.is_none_or(|acknowledged| id > acknowledged));
+            processed.push(update_id);
+            if processed.len() > MAX_REMEMBERED {
+                processed.drain(..processed.len() - MAX_REMEMBERED);
+            }
+            self.store.set(PROCESSED_KEY, json!(processed))
+        });
+        if let Err(err) = recorded {
+            tracing::warn!("cannot record update {update_id} as processed: {:?}", err);
+        }
+    }
+
+    // Lets a processed update be handled again, as when redriving it from the dead-letter store.
+    pub(crate) fn forget_processed(&self, update_id: i64) -> anyhow::Result<()> {
+        if !self.remember_processed {
+            return Ok(());
+        }
+        let mut processed = self.processed_updates()?;
+        processed.retain(|&id| id != update_id);
+        self.store.set(PROCESSED_KEY, json!(processed))
+    }
+
+    // Records that a getUpdates call acknowledged every update up to and including the given one.
+    pub(crate) fn record_acknowledged(&self, update_id: i64) {
+        if !self.remember_processed {
+            return;
+        }
+        if let Err(err) = self.store.set(ACKNOWLEDGED_KEY, json!(update_id)) {
+            tracing::warn!("cannot record acknowledged updates: {:?}", err);
+        }
+    }
+
+    fn processed_updates(&self) -> anyhow::Result<Vec<i64>> {
+        Ok(self
+            .store
+            .get(PROCESSED_KEY)?
+            .as_ref()
+            .and_then(Value::as_array)
+            .map(|ids| ids.iter().filter_map(Value::as_i64).collect())
+            .unwrap_or_default())
+    }
+}
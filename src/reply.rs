@@ -0,0 +1,102 @@
+use serde_json::Value;
+
+use crate::entities::MessageEntity;
+
+/// Where a forwarded or externally replied-to message originally came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MessageOrigin {
+    User {
+        user_id: i64,
+    },
+    /// A user who hides their account in forwards; only their name is known.
+    HiddenUser {
+        name: String,
+    },
+    /// A message sent on behalf of a group, such as by an anonymous admin.
+    Chat {
+        chat_id: i64,
+        author_signature: Option<String>,
+    },
+    Channel {
+        chat_id: i64,
+        message_id: i64,
+        author_signature: Option<String>,
+    },
+}
+
+impl MessageOrigin {
+    /// Parses a message origin from its JSON representation.
+    pub fn from_json(origin: &Value) -> Option<Self> {
+        let author_signature = origin["author_signature"].as_str().map(str::to_owned);
+        Some(match origin["type"].as_str()? {
+            "user" => Self::User {
+                user_id: origin["sender_user"]["id"].as_i64()?,
+            },
+            "hidden_user" => Self::HiddenUser {
+                name: origin["sender_user_name"].as_str()?.to_owned(),
+            },
+            "chat" => Self::Chat {
+                chat_id: origin["sender_chat"]["id"].as_i64()?,
+                author_signature,
+            },
+            "channel" => Self::Channel {
+                chat_id: origin["chat"]["id"].as_i64()?,
+                message_id: origin["message_id"].as_i64()?,
+                author_signature,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// A message replied to from another chat or forum topic, from the `external_reply` field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalReply {
+    pub origin: MessageOrigin,
+    /// The chat of the original message, if the bot can see it.
+    pub chat_id: Option<i64>,
+    pub message_id: Option<i64>,
+    /// The whole `external_reply` object, for the media it may describe.
+    pub raw: Value,
+}
+
+impl ExternalReply {
+    /// Parses an external reply from its JSON representation.
+    pub fn from_json(reply: &Value) -> Option<Self> {
+        Some(Self {
+            origin: MessageOrigin::from_json(&reply["origin"])?,
+            chat_id: reply["chat"]["id"].as_i64(),
+            message_id: reply["message_id"].as_i64(),
+            raw: reply.clone(),
+        })
+    }
+}
+
+/// The part of the replied-to message a user quoted, from the `quote` field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextQuote {
+    pub text: String,
+    /// Formatting within the quoted text, with offsets relative to it.
+    pub entities: Vec<MessageEntity>,
+    /// Where the quote starts in the original message, in UTF-16 code units.
+    pub position: usize,
+    /// Whether the user picked the quote themselves, rather than Telegram adding it.
+    pub is_manual: bool,
+}
+
+impl TextQuote {
+    /// Parses a quote from its JSON representation.
+    pub fn from_json(quote: &Value) -> Option<Self> {
+        Some(Self {
+            text: quote["text"].as_str()?.to_owned(),
+            entities: quote["entities"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(MessageEntity::from_json)
+                .collect(),
+            position: quote["position"].as_u64()? as usize,
+            is_manual: quote["is_manual"].as_bool() == Some(true),
+        })
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    fs::File,
+    future::Future,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use serde_json::{json, Value};
+use smol::{
+    channel::{Receiver, Sender},
+    future::FutureExt,
+};
+
+use crate::Inner;
+
+/// How long each getUpdates call waits for new updates on Telegram's side.
+pub(crate) const LONG_POLL_SECS: u64 = 120;
+
+/// A future returned by an [UpdateSource].
+pub type SourceFuture<'a> =
+    Pin<Box<dyn Future<Output = anyhow::Result<Option<Vec<Value>>>> + Send + 'a>>;
+
+/// Where a bot gets its updates from, instead of polling Telegram with getUpdates.
+pub trait UpdateSource: Send + Sync {
+    /// Waits for the next batch of updates, or returns None once the source is exhausted, which stops the bot.
+    fn next_batch(&mut self) -> SourceFuture<'_>;
+}
+
+/// An update source that never yields updates, for bots that only send, such as scripts sharing a token with a running bot whose getUpdates they must not steal.
+pub struct NoUpdates;
+
+impl UpdateSource for NoUpdates {
+    fn next_batch(&mut self) -> SourceFuture<'_> {
+        Box::pin(smol::future::pending())
+    }
+}
+
+/// An update source that replays updates recorded with [crate::TelegramBotBuilder::record_updates], for reproducing problems.
+pub struct ReplaySource {
+    updates: Option<Vec<Value>>,
+}
+
+impl ReplaySource {
+    /// Reads recorded updates from a JSONL file, one update per line.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        let mut updates = vec![];
+        for (idx, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            updates.push(serde_json::from_str(&line).with_context(|| {
+                format!(
+                    "cannot parse update on line {} of {}",
+                    idx + 1,
+                    path.display()
+                )
+            })?);
+        }
+        Ok(Self {
+            updates: Some(updates),
+        })
+    }
+}
+
+impl UpdateSource for ReplaySource {
+    fn next_batch(&mut self) -> SourceFuture<'_> {
+        let updates = self.updates.take();
+        Box::pin(async move { Ok(updates) })
+    }
+}
+
+// Fetches updates, from the source if there is one and otherwise by polling Telegram, into the queue handlers take them from. Waits while the queue is full, and stops once the source is exhausted or handling stops. When given the IDs of handled updates, it waits for each polled batch to be handled before polling again, since polling acknowledges the batch.
+pub(crate) async fn fetch_updates(
+    inner: Arc<Inner>,
+    mut source: Option<Box<dyn UpdateSource>>,
+    mut recording: Option<File>,
+    allowed_updates: Vec<String>,
+    queue: Sender<Value>,
+    handled: Option<Receiver<i64>>,
+) {
+    let mut counter = 0;
+    let mut acknowledged = 0;
+    let mut queue_full = false;
+    loop {
+        let updates = match &mut source {
+            Some(source) => match source.next_batch().await {
+                Ok(Some(updates)) => updates,
+                Ok(None) => {
+                    tracing::info!("update source exhausted, stopping");
+                    return;
+                }
+                Err(err) => {
+                    tracing::error!("error getting updates from source: {:?}", err);
+                    smol::Timer::after(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            None => match poll_updates(&inner, counter + 1, &allowed_updates).await {
+                Some(updates) => {
+                    if counter > acknowledged {
+                        inner.record_acknowledged(counter);
+                        acknowledged = counter;
+                    }
+                    updates
+                }
+                None => continue,
+            },
+        };
+        let got_updates = !updates.is_empty();
+        for update in updates {
+            counter = counter.max(update["update_id"].as_i64().unwrap_or_default());
+            inner
+                .status
+                .update(|status| status.last_update_id = Some(counter));
+            if let Some(recording) = &mut recording {
+                if let Err(err) = record_update(recording, &update) {
+                    tracing::error!("cannot record update: {:?}", err);
+                }
+            }
+            // under sustained load the queue stays full, so log only when it fills up
+            let full = queue.is_full();
+            if full && !queue_full {
+                tracing::debug!("update queue full, waiting for handlers");
+            }
+            queue_full = full;
+            if queue.send(update).await.is_err() {
+                return;
+            }
+            inner
+                .status
+                .update(|status| status.queued_updates = queue.len());
+        }
+        if let (Some(handled), true) = (&handled, got_updates) {
+            loop {
+                match handled.recv().await {
+                    Ok(update_id) if update_id >= counter => break,
+                    Ok(_) => {}
+                    Err(_) => return,
+                }
+            }
+        }
+    }
+}
+
+// Appends a raw update to a recording as a line of JSON.
+pub(crate) fn record_update(recording: &mut File, update: &Value) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(update)?;
+    line.push(b'\n');
+    recording.write_all(&line)?;
+    Ok(())
+}
+
+// Long-polls Telegram for updates after the offset, returning None if the call failed or was abandoned and should be retried.
+pub(crate) async fn poll_updates(
+    inner: &Inner,
+    offset: i64,
+    allowed_updates: &[String],
+) -> Option<Vec<Value>> {
+    if inner.pause.is_paused() {
+        tracing::info!("polling paused");
+        inner.pause.wait_until(false).await;
+        tracing::info!("polling resumed");
+    }
+    tracing::info!("getting updates at {offset}");
+    let updates = async {
+        let updates = inner
+            .call_api(
+                "getUpdates",
+                json!({
+                    "timeout": LONG_POLL_SECS,
+                    "offset": offset,
+                    "allowed_updates": allowed_updates,
+                }),
+            )
+            .await
+            .context("cannot call telegram for updates")?;
+        match updates {
+            Value::Array(updates) => anyhow::Ok(updates),
+            other => anyhow::bail!("getUpdates returned a non-array result: {other}"),
+        }
+    };
+    // pausing abandons the in-flight getUpdates, whose updates Telegram then keeps since they were never acknowledged
+    let updates = async { Some(updates.await) }.or(async {
+        inner.pause.wait_until(true).await;
+        None
+    });
+    match updates.await? {
+        Ok(updates) => {
+            inner.status.update(|status| {
+                status.last_poll = Some(Instant::now());
+                status.consecutive_errors = 0;
+                status.backoff = Duration::ZERO;
+            });
+            Some(updates)
+        }
+        Err(err) => {
+            tracing::error!("error getting updates: {:?}", err);
+            let mut backoff = Duration::ZERO;
+            inner.status.update(|status| {
+                status.consecutive_errors += 1;
+                backoff = poll_backoff(status.consecutive_errors);
+                status.backoff = backoff;
+            });
+            tracing::info!("retrying getUpdates in {:?}", backoff);
+            smol::Timer::after(backoff).await;
+            None
+        }
+    }
+}
+
+// How long to wait before retrying getUpdates after the given number of consecutive failures.
+fn poll_backoff(consecutive_errors: u32) -> Duration {
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+    // 1s, 2s, 4s, ... capped at a minute
+    Duration::from_secs(1 << consecutive_errors.saturating_sub(1).min(6)).min(MAX_BACKOFF)
+}
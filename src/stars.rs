@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::BotHandle;
+
+/// A payment in Telegram Stars, from the `successful_payment` field of a message, with its subscription details if it pays for one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StarPayment {
+    /// The ID to refund the payment or manage its subscription with.
+    pub telegram_payment_charge_id: String,
+    pub invoice_payload: String,
+    pub total_amount: i64,
+    /// When the subscription this pays for runs out, as a Unix timestamp, if it is a subscription payment.
+    pub subscription_expiration_date: Option<i64>,
+    /// Whether the payment is for a subscription that renews.
+    pub is_recurring: bool,
+    /// Whether this is the first payment of the subscription.
+    pub is_first_recurring: bool,
+}
+
+impl StarPayment {
+    /// Parses a payment from the JSON `successful_payment` object.
+    pub fn from_json(payment: &Value) -> Option<Self> {
+        Some(Self {
+            telegram_payment_charge_id: payment["telegram_payment_charge_id"].as_str()?.to_owned(),
+            invoice_payload: payment["invoice_payload"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned(),
+            total_amount: payment["total_amount"].as_i64()?,
+            subscription_expiration_date: payment["subscription_expiration_date"].as_i64(),
+            is_recurring: payment["is_recurring"].as_bool() == Some(true),
+            is_first_recurring: payment["is_first_recurring"].as_bool() == Some(true),
+        })
+    }
+}
+
+/// A transaction of the bot's Stars balance, as returned by getStarTransactions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StarTransaction {
+    pub id: String,
+    pub amount: i64,
+    /// When the transaction happened, as a Unix timestamp.
+    pub date: i64,
+    /// The user who paid, or was refunded, if the other party is a user.
+    pub user_id: Option<i64>,
+    /// How often the subscription this pays for renews, if it is a subscription payment.
+    pub subscription_period: Option<Duration>,
+    pub invoice_payload: Option<String>,
+}
+
+impl StarTransaction {
+    /// Parses a transaction from its JSON representation.
+    pub fn from_json(transaction: &Value) -> Option<Self> {
+        // incoming payments have a source, refunds and withdrawals a receiver
+        let partner = match &transaction["source"] {
+            Value::Null => &transaction["receiver"],
+            source => source,
+        };
+        let is_user = partner["type"] == "user";
+        Some(Self {
+            id: transaction["id"].as_str()?.to_owned(),
+            amount: transaction["amount"].as_i64()?,
+            date: transaction["date"].as_i64()?,
+            user_id: partner["user"]["id"].as_i64().filter(|_| is_user),
+            subscription_period: partner["subscription_period"]
+                .as_u64()
+                .map(Duration::from_secs),
+            invoice_payload: partner["invoice_payload"].as_str().map(str::to_owned),
+        })
+    }
+}
+
+impl BotHandle {
+    /// Cancels a user's Stars subscription to the bot, so it does not renew, or reactivates a subscription the bot canceled.
+    pub async fn edit_user_star_subscription(
+        &self,
+        user_id: i64,
+        telegram_payment_charge_id: &str,
+        is_canceled: bool,
+    ) -> anyhow::Result<()> {
+        self.call_api(
+            "editUserStarSubscription",
+            json!({
+                "user_id": user_id,
+                "telegram_payment_charge_id": telegram_payment_charge_id,
+                "is_canceled": is_canceled,
+            }),
+        )
+        .await
+        .context("cannot edit star subscription")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use serde_json::{json, Value};
+
+use crate::{store::Store, TelegramBot};
+
+/// How many days of active users are kept for each chat.
+const ACTIVE_USER_DAYS: i64 = 30;
+
+/// Usage statistics of the bot in one chat.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChatStats {
+    pub messages: u64,
+    pub commands: u64,
+    /// The number of distinct users active on each day (`YYYY-MM-DD`, UTC), oldest first.
+    pub daily_active_users: Vec<(String, usize)>,
+}
+
+impl ChatStats {
+    /// Formats the statistics as a message, as sent in reply to `/stats`.
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "Messages seen: {}\nCommands run: {}",
+            self.messages, self.commands
+        );
+        if !self.daily_active_users.is_empty() {
+            out.push_str("\nActive users per day:");
+            for (day, count) in &self.daily_active_users {
+                out.push_str(&format!("\n{day}: {count}"));
+            }
+        }
+        out
+    }
+}
+
+fn stats_key(chat_id: i64) -> String {
+    format!("stats/{chat_id}")
+}
+
+// Records a message update in the statistics of its chat.
+pub(crate) fn record_update(store: &dyn Store, update: &Value) -> anyhow::Result<()> {
+    let message = &update["message"];
+    let Some(chat_id) = message["chat"]["id"].as_i64() else {
+        return Ok(());
+    };
+    let key = stats_key(chat_id);
+    let mut stats = store.get(&key)?.unwrap_or_else(|| json!({}));
+    stats["messages"] = json!(stats["messages"].as_u64().unwrap_or_default() + 1);
+    if message["text"]
+        .as_str()
+        .is_some_and(|text| text.starts_with('/'))
+    {
+        stats["commands"] = json!(stats["commands"].as_u64().unwrap_or_default() + 1);
+    }
+    if let (Some(user_id), Some(date)) = (message["from"]["id"].as_i64(), message["date"].as_i64())
+    {
+        let today = date.div_euclid(86400);
+        let mut active: serde_json::Map<String, Value> = stats["active_users"]
+            .as_object()
+            .cloned()
+            .unwrap_or_default();
+        active.retain(|day, _| {
+            day.parse::<i64>()
+                .is_ok_and(|day| day > today - ACTIVE_USER_DAYS)
+        });
+        let users = active.entry(today.to_string()).or_insert_with(|| json!([]));
+        if let Some(users) = users.as_array_mut() {
+            if !users.contains(&json!(user_id)) {
+                users.push(json!(user_id));
+            }
+        }
+        stats["active_users"] = Value::Object(active);
+    }
+    store.set(&key, stats)
+}
+
+impl TelegramBot {
+    /// Gets the statistics recorded for a chat. Statistics are only recorded when enabled on the builder.
+    pub fn chat_stats(&self, chat_id: i64) -> anyhow::Result<ChatStats> {
+        load_stats(&*self.inner.store, chat_id)
+    }
+}
+
+pub(crate) fn load_stats(store: &dyn Store, chat_id: i64) -> anyhow::Result<ChatStats> {
+    let stats = store.get(&stats_key(chat_id))?.unwrap_or_default();
+    let mut daily_active_users: Vec<(i64, usize)> = stats["active_users"]
+        .as_object()
+        .map(|active| {
+            active
+                .iter()
+                .filter_map(|(day, users)| Some((day.parse().ok()?, users.as_array()?.len())))
+                .collect()
+        })
+        .unwrap_or_default();
+    daily_active_users.sort();
+    Ok(ChatStats {
+        messages: stats["messages"].as_u64().unwrap_or_default(),
+        commands: stats["commands"].as_u64().unwrap_or_default(),
+        daily_active_users: daily_active_users
+            .into_iter()
+            .map(|(day, count)| (format_day(day), count))
+            .collect(),
+    })
+}
+
+// Formats days since the Unix epoch as `YYYY-MM-DD`.
+fn format_day(days: i64) -> String {
+    // Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::Value;
+
+use crate::{update_type, TelegramBot};
+
+/// A snapshot of the health of a bot's update handling, for detecting a wedged bot.
+#[derive(Clone, Debug, Default)]
+pub struct BotStatus {
+    /// When getUpdates last succeeded.
+    pub last_poll: Option<Instant>,
+    /// The ID of the last update received.
+    pub last_update_id: Option<i64>,
+    /// How many getUpdates calls in a row have failed.
+    pub consecutive_errors: u32,
+    /// How long the bot is waiting before retrying a failed getUpdates, or zero if the last one succeeded.
+    pub backoff: Duration,
+    /// Updates received but not yet handled.
+    pub queued_updates: usize,
+    /// Responses returned by the handler that are not sent yet.
+    pub queued_responses: usize,
+    /// Updates currently being handled.
+    pub handlers_in_flight: usize,
+    /// Whether polling is paused.
+    pub paused: bool,
+    /// How long after it was sent or edited the bot got to the last update that says when that was. A lag that keeps growing means the bot is falling behind.
+    pub update_lag: Option<Duration>,
+}
+
+#[derive(Default)]
+pub(crate) struct StatusTracker {
+    status: Mutex<BotStatus>,
+}
+
+impl StatusTracker {
+    pub fn update(&self, f: impl FnOnce(&mut BotStatus)) {
+        f(&mut self.status.lock().unwrap())
+    }
+
+    pub fn get(&self) -> BotStatus {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+/// How many updates of each type the bot has received, and how many of those nothing handled, such as callback queries without an [crate::TelegramBotBuilder::on_callback_query] handler.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateCounts {
+    pub received: BTreeMap<String, u64>,
+    pub unhandled: BTreeMap<String, u64>,
+}
+
+#[derive(Default)]
+pub(crate) struct UpdateCounter {
+    counts: Mutex<UpdateCounts>,
+}
+
+impl UpdateCounter {
+    pub fn received(&self, update_type: &str) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .received
+            .entry(update_type.to_owned())
+            .or_default() += 1;
+    }
+
+    pub fn unhandled(&self, update_type: &str) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .unhandled
+            .entry(update_type.to_owned())
+            .or_default() += 1;
+    }
+}
+
+impl TelegramBot {
+    /// Returns how many updates of each type the bot has received and left unhandled since it started.
+    pub fn update_counts(&self) -> UpdateCounts {
+        self.inner.update_counts.counts.lock().unwrap().clone()
+    }
+
+    /// Returns the current status of update handling.
+    pub fn status(&self) -> BotStatus {
+        BotStatus {
+            paused: self.inner.pause.is_paused(),
+            ..self.inner.status.get()
+        }
+    }
+}
+
+// How long ago the update's message or post was sent or edited, by its own timestamp, which only has second precision.
+pub(crate) fn update_lag(update: &Value) -> Option<Duration> {
+    let body = &update[update_type(update)];
+    let date = body["edit_date"]
+        .as_u64()
+        .or_else(|| body["date"].as_u64())?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(Duration::from_secs(now.saturating_sub(date)))
+}
+
+// Counts responses as queued until they are sent or dropped, so failed and timed-out sends don't leave stale counts.
+pub(crate) struct QueuedResponses<'a> {
+    tracker: &'a StatusTracker,
+    remaining: usize,
+}
+
+impl<'a> QueuedResponses<'a> {
+    pub fn new(tracker: &'a StatusTracker, count: usize) -> Self {
+        tracker.update(|status| status.queued_responses += count);
+        Self {
+            tracker,
+            remaining: count,
+        }
+    }
+
+    pub fn sent_one(&mut self) {
+        self.remaining -= 1;
+        self.tracker.update(|status| status.queued_responses -= 1);
+    }
+}
+
+impl Drop for QueuedResponses<'_> {
+    fn drop(&mut self) {
+        let remaining = self.remaining;
+        self.tracker
+            .update(|status| status.queued_responses -= remaining);
+    }
+}
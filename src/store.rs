@@ -0,0 +1,147 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use serde_json::Value;
+
+/// A key-value store for bot state that should outlive a single update, like per-chat sessions and statistics.
+pub trait Store: Send + Sync {
+    /// Gets the value stored under the key.
+    fn get(&self, key: &str) -> anyhow::Result<Option<Value>>;
+
+    /// Stores a value under the key, replacing any previous value.
+    fn set(&self, key: &str, value: Value) -> anyhow::Result<()>;
+
+    /// Removes the value stored under the key, if any.
+    fn remove(&self, key: &str) -> anyhow::Result<()>;
+
+    /// Lists all keys starting with the prefix.
+    fn keys(&self, prefix: &str) -> anyhow::Result<Vec<String>>;
+}
+
+impl<T: Store + ?Sized> Store for Arc<T> {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Value>> {
+        (**self).get(key)
+    }
+
+    fn set(&self, key: &str, value: Value) -> anyhow::Result<()> {
+        (**self).set(key, value)
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        (**self).remove(key)
+    }
+
+    fn keys(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        (**self).keys(prefix)
+    }
+}
+
+/// A store that keeps everything in memory, losing it on restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<BTreeMap<String, Value>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Value>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: Value) -> anyhow::Result<()> {
+        self.entries.lock().unwrap().insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+/// A store that keeps everything in a single JSON file, rewritten on every change. Suitable for small bots.
+pub struct JsonFileStore {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, Value>>,
+}
+
+impl JsonFileStore {
+    /// Opens the store at the given path, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let entries = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("cannot parse store at {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("cannot read store at {}", path.display()))
+            }
+        };
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    // Writes the entries to a temporary file, then renames it over the store, so a crash never leaves a half-written store.
+    fn flush(&self, entries: &BTreeMap<String, Value>) -> anyhow::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(entries)?)
+            .with_context(|| format!("cannot write store at {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("cannot replace store at {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+impl Store for JsonFileStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Value>> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: Value) -> anyhow::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_owned(), value);
+        self.flush(&entries)
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.remove(key).is_some() {
+            self.flush(&entries)?;
+        }
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::{format::ParseMode, outgoing::InputFile, transport::Upload, BotHandle};
+
+/// What a story shows. Telegram only accepts new uploads for stories, not file_ids or URLs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputStoryContent {
+    Photo(InputFile),
+    Video {
+        file: InputFile,
+        /// The length of the video in seconds, at most 60.
+        duration: Option<f64>,
+        /// Which moment of the video, in seconds, to show as the cover.
+        cover_frame_timestamp: Option<f64>,
+        /// Whether the video has no sound.
+        is_animation: bool,
+    },
+}
+
+impl InputStoryContent {
+    /// A video story.
+    pub fn video(file: InputFile) -> Self {
+        Self::Video {
+            file,
+            duration: None,
+            cover_frame_timestamp: None,
+            is_animation: false,
+        }
+    }
+}
+
+/// A story to post or edit into an existing one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputStory {
+    pub content: InputStoryContent,
+    pub caption: Option<String>,
+    pub parse_mode: Option<ParseMode>,
+    /// Clickable areas on the story, as raw JSON `StoryArea` objects.
+    pub areas: Vec<Value>,
+    /// How long the story stays visible: 6, 12, 24, or 48 hours. Not changed by editing.
+    pub active_period: Duration,
+    /// Keeps the story on the account's profile page after it expires. Not changed by editing.
+    pub post_to_chat_page: bool,
+    /// Prevents the story from being forwarded or saved. Not changed by editing.
+    pub protect_content: bool,
+}
+
+impl InputStory {
+    /// A story visible for 24 hours, without a caption.
+    pub fn new(content: InputStoryContent) -> Self {
+        Self {
+            content,
+            caption: None,
+            parse_mode: None,
+            areas: vec![],
+            active_period: Duration::from_secs(24 * 3600),
+            post_to_chat_page: false,
+            protect_content: false,
+        }
+    }
+
+    /// Sets the caption of the story.
+    pub fn caption(mut self, caption: &str) -> Self {
+        self.caption = Some(caption.to_owned());
+        self
+    }
+
+    /// Sets how long the story stays visible: 6, 12, 24, or 48 hours.
+    pub fn active_period(mut self, period: Duration) -> Self {
+        self.active_period = period;
+        self
+    }
+
+    // The arguments for postStory or editStory, with the content's file in the uploads.
+    fn to_args(&self, uploads: &mut Vec<Upload>) -> Value {
+        let content = match &self.content {
+            InputStoryContent::Photo(file) => json!({
+                "type": "photo",
+                "photo": file.reference("story_file", uploads),
+            }),
+            InputStoryContent::Video {
+                file,
+                duration,
+                cover_frame_timestamp,
+                is_animation,
+            } => {
+                let mut content = json!({
+                    "type": "video",
+                    "video": file.reference("story_file", uploads),
+                    "is_animation": is_animation,
+                });
+                if let Some(duration) = duration {
+                    content["duration"] = json!(duration);
+                }
+                if let Some(timestamp) = cover_frame_timestamp {
+                    content["cover_frame_timestamp"] = json!(timestamp);
+                }
+                content
+            }
+        };
+        let mut args = json!({ "content": content });
+        if let Some(caption) = &self.caption {
+            args["caption"] = json!(caption);
+        }
+        if let Some(parse_mode) = self.parse_mode {
+            args["parse_mode"] = json!(parse_mode.as_str());
+        }
+        if !self.areas.is_empty() {
+            args["areas"] = json!(self.areas);
+        }
+        args
+    }
+}
+
+/// A posted story.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Story {
+    /// The chat of the business account that posted it.
+    pub chat_id: i64,
+    pub id: i64,
+}
+
+impl Story {
+    /// Parses a story from its JSON representation.
+    pub fn from_json(story: &Value) -> Option<Self> {
+        Some(Self {
+            chat_id: story["chat"]["id"].as_i64()?,
+            id: story["id"].as_i64()?,
+        })
+    }
+}
+
+impl BotHandle {
+    /// Posts a story on behalf of a connected business account. The bot needs the `can_manage_stories` business right.
+    pub async fn post_story(
+        &self,
+        business_connection_id: &str,
+        story: &InputStory,
+    ) -> anyhow::Result<Story> {
+        let mut uploads = vec![];
+        let mut args = story.to_args(&mut uploads);
+        args["business_connection_id"] = json!(business_connection_id);
+        args["active_period"] = json!(story.active_period.as_secs());
+        if story.post_to_chat_page {
+            args["post_to_chat_page"] = json!(true);
+        }
+        if story.protect_content {
+            args["protect_content"] = json!(true);
+        }
+        let posted = self
+            .inner
+            .call_api_with_uploads("postStory", args, &uploads)
+            .await
+            .context("cannot post story")?;
+        Story::from_json(&posted).context("postStory returned an invalid story")
+    }
+
+    /// Replaces the content, caption, and areas of a story posted on behalf of a connected business account.
+    pub async fn edit_story(
+        &self,
+        business_connection_id: &str,
+        story_id: i64,
+        story: &InputStory,
+    ) -> anyhow::Result<Story> {
+        let mut uploads = vec![];
+        let mut args = story.to_args(&mut uploads);
+        args["business_connection_id"] = json!(business_connection_id);
+        args["story_id"] = json!(story_id);
+        let edited = self
+            .inner
+            .call_api_with_uploads("editStory", args, &uploads)
+            .await
+            .context("cannot edit story")?;
+        Story::from_json(&edited).context("editStory returned an invalid story")
+    }
+
+    /// Deletes a story posted on behalf of a connected business account.
+    pub async fn delete_story(
+        &self,
+        business_connection_id: &str,
+        story_id: i64,
+    ) -> anyhow::Result<()> {
+        self.call_api(
+            "deleteStory",
+            json!({
+                "business_connection_id": business_connection_id,
+                "story_id": story_id,
+            }),
+        )
+        .await
+        .context("cannot delete story")?;
+        Ok(())
+    }
+}
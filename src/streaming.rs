@@ -0,0 +1,223 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::{
+    entities::utf16_len,
+    outgoing::{split_text, MAX_TEXT_LEN},
+    ApiError, BotHandle, Ctx, OutgoingMessage,
+};
+
+/// A message that is sent once, then edited as more text arrives, such as output generated progressively by a language model.
+///
+/// Edits happen at most once per interval, and are postponed when Telegram asks to slow down. Text beyond the length limit of a message continues in a new one.
+pub struct StreamingMessage {
+    bot: BotHandle,
+    chat_id: i64,
+    reply_to_message_id: Option<i64>,
+    interval: Duration,
+    message_id: Option<i64>,
+    text: String,
+    sent_text: String,
+    next_edit: Instant,
+}
+
+impl StreamingMessage {
+    /// Creates a streaming message to the chat. Nothing is sent until the first non-empty text is pushed.
+    pub fn new(bot: BotHandle, chat_id: i64) -> Self {
+        Self {
+            bot,
+            chat_id,
+            reply_to_message_id: None,
+            interval: Duration::from_secs(1),
+            message_id: None,
+            text: String::new(),
+            sent_text: String::new(),
+            next_edit: Instant::now(),
+        }
+    }
+
+    /// Sends the message as a reply to the given message.
+    pub fn reply_to(mut self, message_id: i64) -> Self {
+        self.reply_to_message_id = Some(message_id);
+        self
+    }
+
+    /// Sets the least time between edits. Defaults to one second.
+    pub fn edit_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The text of the current message so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Appends text, sending or editing the message if enough time has passed since the last edit.
+    pub async fn push(&mut self, chunk: &str) -> anyhow::Result<()> {
+        self.text.push_str(chunk);
+        while utf16_len(&self.text) > MAX_TEXT_LEN {
+            // finish this message at the limit and continue in a new one
+            let (text, overflow) = split_text(&self.text, MAX_TEXT_LEN);
+            let overflow = overflow.to_owned();
+            self.text = text.to_owned();
+            self.settle().await?;
+            self.message_id = None;
+            self.sent_text.clear();
+            self.text = overflow;
+        }
+        if Instant::now() >= self.next_edit {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the remaining text, waiting out any flood limit.
+    pub async fn finish(mut self) -> anyhow::Result<()> {
+        self.settle().await
+    }
+
+    // Flushes until the message is up to date with the text, waiting out any flood limit.
+    async fn settle(&mut self) -> anyhow::Result<()> {
+        while self.text != self.sent_text {
+            smol::Timer::at(self.next_edit).await;
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    // Brings the message up to date with the text, unless Telegram asks to slow down.
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        if self.text.trim().is_empty() {
+            // Telegram rejects blank messages, so there is nothing to send yet
+            self.sent_text.clone_from(&self.text);
+            return Ok(());
+        }
+        if self.text == self.sent_text {
+            return Ok(());
+        }
+        let res = match self.message_id {
+            None => {
+                let mut msg = OutgoingMessage::text(self.chat_id, &self.text);
+                msg.options.reply_to_message_id = self.reply_to_message_id;
+                self.bot.send(msg).await.and_then(|sent| {
+                    self.message_id = Some(
+                        sent["message_id"]
+                            .as_i64()
+                            .context("sent message without an id")?,
+                    );
+                    Ok(())
+                })
+            }
+            Some(message_id) => self
+                .bot
+                .call_api(
+                    "editMessageText",
+                    json!({"chat_id": self.chat_id, "message_id": message_id, "text": self.text}),
+                )
+                .await
+                .map(|_| ()),
+        };
+        self.next_edit = Instant::now() + self.interval;
+        if let Err(err) = res {
+            match err.downcast_ref::<ApiError>() {
+                Some(api_err) if api_err.description.contains("message is not modified") => {}
+                Some(api_err) if api_err.error_code == 429 => {
+                    let retry_after = api_err.parameters["retry_after"].as_u64().unwrap_or(1);
+                    self.next_edit = Instant::now() + Duration::from_secs(retry_after);
+                    return Ok(());
+                }
+                _ => return Err(err),
+            }
+        }
+        self.sent_text.clone_from(&self.text);
+        Ok(())
+    }
+}
+
+impl Ctx {
+    /// Starts a [StreamingMessage] replying to the message.
+    pub fn stream(&self) -> anyhow::Result<StreamingMessage> {
+        let mut stream = StreamingMessage::new(
+            self.bot.clone(),
+            self.chat_id().context("message without a chat id")?,
+        );
+        stream.reply_to_message_id = self.message_id();
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock::MockTelegram, Response, TelegramBot};
+
+    fn bot(mock: &MockTelegram) -> TelegramBot {
+        TelegramBot::builder("1:test")
+            .transport(mock.clone())
+            .build(|_| async { anyhow::Ok(Vec::<Response>::new()) })
+    }
+
+    #[test]
+    fn blank_streams_finish_without_sending() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            let bot = bot(&mock);
+            StreamingMessage::new(bot.handle(), 1)
+                .finish()
+                .await
+                .unwrap();
+            let mut stream = StreamingMessage::new(bot.handle(), 1);
+            stream.push(" \n ").await.unwrap();
+            stream.finish().await.unwrap();
+            assert!(mock.calls_to("sendMessage").is_empty());
+        });
+    }
+
+    #[test]
+    fn overflow_continues_in_a_new_message_within_the_utf16_limit() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            let bot = bot(&mock);
+            let mut stream = StreamingMessage::new(bot.handle(), 1).edit_interval(Duration::ZERO);
+            stream.push(&"😀".repeat(3000)).await.unwrap();
+            stream.push("\n").await.unwrap();
+            stream.finish().await.unwrap();
+            let sent: Vec<String> = mock
+                .calls_to("sendMessage")
+                .iter()
+                .map(|args| args["text"].as_str().unwrap().to_owned())
+                .collect();
+            assert_eq!(sent.len(), 2);
+            assert_eq!(utf16_len(&sent[0]), MAX_TEXT_LEN);
+            assert_eq!(sent.concat(), "😀".repeat(3000));
+        });
+    }
+
+    #[test]
+    fn flood_limits_delay_rather_than_drop_a_full_message() {
+        smol::block_on(async {
+            let mock = MockTelegram::new();
+            let bot = bot(&mock);
+            mock.respond_with_error("sendMessage", 429, "Too Many Requests: retry after 1");
+            let unblock = {
+                let mock = mock.clone();
+                smol::spawn(async move {
+                    smol::Timer::after(Duration::from_millis(200)).await;
+                    mock.respond_with("sendMessage", json!({"message_id": 5, "chat": {"id": 1}}));
+                })
+            };
+            let mut stream = StreamingMessage::new(bot.handle(), 1);
+            stream.push(&"a".repeat(MAX_TEXT_LEN + 10)).await.unwrap();
+            unblock.await;
+            let sent = mock.calls_to("sendMessage");
+            assert_eq!(
+                sent.last().unwrap()["text"].as_str().unwrap(),
+                "a".repeat(MAX_TEXT_LEN)
+            );
+            assert_eq!(stream.text(), "a".repeat(10));
+        });
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::Context;
+use minijinja::{Environment, ErrorKind, UndefinedBehavior};
+use serde_json::Value;
+
+use crate::{format::ParseMode, OutgoingMessage};
+
+/// A set of named message templates in [minijinja](https://docs.rs/minijinja) syntax, rendered with variables from JSON.
+///
+/// Templates support `{{ var }}` and `{{ user.name }}` substitution, `{% if var %}...{% else %}...{% endif %}`, `{% for item in list %}...{% endfor %}`, and the rest of minijinja. Substituted values are escaped for the parse mode unless written as `{{ var | raw }}` (or `| safe`). Missing variables render as nothing.
+#[derive(Clone, Debug)]
+pub struct Templates {
+    parse_mode: ParseMode,
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Creates an empty set of templates whose output is in the given parse mode.
+    pub fn new(parse_mode: ParseMode) -> Self {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Chainable);
+        env.set_formatter(move |out, _, value| {
+            let text = if value.is_undefined() || value.is_none() {
+                String::new()
+            } else if value.is_safe() {
+                value.to_string()
+            } else {
+                parse_mode.escape(&value.to_string())
+            };
+            out.write_str(&text)
+                .map_err(|err| minijinja::Error::new(ErrorKind::WriteFailure, err.to_string()))
+        });
+        env.add_filter("raw", |value: minijinja::Value| {
+            minijinja::Value::from_safe_string(value.to_string())
+        });
+        Self { parse_mode, env }
+    }
+
+    /// Adds a template under a name, failing if its syntax is invalid.
+    pub fn add(mut self, name: &str, source: &str) -> anyhow::Result<Self> {
+        self.env
+            .add_template_owned(name.to_owned(), source.to_owned())
+            .with_context(|| format!("invalid template {name}"))?;
+        Ok(self)
+    }
+
+    /// The parse mode to send rendered templates with.
+    pub fn parse_mode(&self) -> ParseMode {
+        self.parse_mode
+    }
+
+    /// Renders a template with the given variables, usually a JSON object.
+    pub fn render(&self, name: &str, vars: &Value) -> anyhow::Result<String> {
+        let template = self
+            .env
+            .get_template(name)
+            .with_context(|| format!("no template named {name}"))?;
+        template
+            .render(minijinja::Value::from_serialize(vars))
+            .with_context(|| format!("cannot render template {name}"))
+    }
+
+    /// Renders a template into a text message with the right parse mode.
+    pub fn message(
+        &self,
+        chat_id: i64,
+        name: &str,
+        vars: &Value,
+    ) -> anyhow::Result<OutgoingMessage> {
+        let mut msg = OutgoingMessage::text(chat_id, &self.render(name, vars)?);
+        msg.options.parse_mode = Some(self.parse_mode);
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(mode: ParseMode, source: &str, vars: Value) -> String {
+        Templates::new(mode)
+            .add("t", source)
+            .unwrap()
+            .render("t", &vars)
+            .unwrap()
+    }
+
+    #[test]
+    fn substitutes_and_escapes_variables() {
+        let vars = json!({"user": {"name": "<Ann & co>"}, "count": 3, "none": null});
+        assert_eq!(
+            render(
+                ParseMode::Html,
+                "Hi {{ user.name }}, {{count}} new",
+                vars.clone()
+            ),
+            "Hi &lt;Ann &amp; co&gt;, 3 new"
+        );
+        assert_eq!(
+            render(ParseMode::MarkdownV2, "*{{ x }}*", json!({"x": "a_b.c"})),
+            "*a\\_b\\.c*"
+        );
+        assert_eq!(
+            render(
+                ParseMode::Html,
+                "{{ user.name | raw }}{{ user.name | safe }}",
+                vars.clone()
+            ),
+            "<Ann & co><Ann & co>"
+        );
+        assert_eq!(
+            render(ParseMode::Html, "[{{ missing.field }}{{ none }}]", vars),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn renders_conditions_and_loops() {
+        let source = "{% if items %}{% for item in items %}<b>{{ item.name }}</b>{% endfor %}{% else %}none{% endif %}";
+        assert_eq!(
+            render(
+                ParseMode::Html,
+                source,
+                json!({"items": [{"name": "a"}, {"name": "b&c"}]})
+            ),
+            "<b>a</b><b>b&amp;c</b>"
+        );
+        assert_eq!(
+            render(ParseMode::Html, source, json!({"items": []})),
+            "none"
+        );
+        assert_eq!(
+            render(ParseMode::Html, "{{ list[1] }}", json!({"list": [10, 20]})),
+            "20"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for source in [
+            "{{ unclosed",
+            "{% if x %}no end",
+            "{% endif %}",
+            "{% while x %}",
+        ] {
+            assert!(
+                Templates::new(ParseMode::Html).add("t", source).is_err(),
+                "{source}"
+            );
+        }
+        assert!(Templates::new(ParseMode::Html)
+            .render("missing", &json!({}))
+            .is_err());
+    }
+}
@@ -0,0 +1,607 @@
+use std::{future::Future, net::IpAddr, path::PathBuf, pin::Pin, sync::Arc, time::Duration};
+#[cfg(feature = "curl")]
+use std::{
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "curl")]
+use anyhow::Context;
+#[cfg(feature = "curl")]
+use isahc::{
+    config::{CaCertificate, Configurable, Dialer, IpVersion, ResolveMap, VersionNegotiation},
+    http::request::Builder,
+    AsyncReadResponseExt, HttpClient, Request,
+};
+use serde_json::Value;
+use smol::io::AsyncRead;
+
+#[cfg(feature = "rustls")]
+mod rustls;
+#[cfg(feature = "rustls")]
+pub use self::rustls::RustlsTransport;
+
+/// The host of the Telegram bot API.
+#[cfg(any(feature = "curl", feature = "rustls"))]
+const API_HOST: &str = "api.telegram.org";
+
+/// A future returned by a [Transport].
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<Value>> + Send + 'a>>;
+
+/// A future returned by [Transport::download].
+pub type DownloadFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<Download>> + Send + 'a>>;
+
+/// A file being downloaded from Telegram.
+pub struct Download {
+    /// The size of the file, if known before reading it.
+    pub size: Option<u64>,
+    pub body: Pin<Box<dyn AsyncRead + Send>>,
+}
+
+/// How API calls reach Telegram. The default sends them over HTTPS; [crate::mock::MockTelegram] answers them in-process for tests.
+pub trait Transport: Send + Sync {
+    /// Calls a method, returning the raw response object (with `ok`, `result`, and so on).
+    fn call<'a>(&'a self, token: &'a str, method: &'a str, args: &'a Value) -> TransportFuture<'a>;
+
+    /// Calls a method with files attached. Transports that cannot upload files fail.
+    fn upload<'a>(
+        &'a self,
+        token: &'a str,
+        method: &'a str,
+        args: &'a Value,
+        uploads: &'a [Upload],
+    ) -> TransportFuture<'a> {
+        let _ = (token, args, uploads);
+        Box::pin(async move { anyhow::bail!("this transport cannot upload files for {method}") })
+    }
+
+    /// Starts downloading a file by the `file_path` returned by getFile. Transports that cannot download files fail.
+    fn download<'a>(&'a self, token: &'a str, file_path: &'a str) -> DownloadFuture<'a> {
+        let _ = token;
+        Box::pin(async move { anyhow::bail!("this transport cannot download {file_path}") })
+    }
+}
+
+/// A file sent along with an API call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Upload {
+    /// The argument the file is for, like `photo`.
+    pub field: String,
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+/// Which HTTP versions the default transport may use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// Use HTTP/2 when the server offers it during the TLS handshake, and HTTP/1.1 otherwise.
+    #[default]
+    Auto,
+    /// Only use HTTP/1.1.
+    Http1Only,
+    /// Only use HTTP/2, without falling back.
+    Http2Only,
+}
+
+/// Which IP address families the default transport connects over.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpFamily {
+    /// Try IPv6 first and fall back to IPv4 quickly if it fails to connect ("happy eyeballs").
+    #[default]
+    PreferV6,
+    /// Only use IPv4, for hosts with broken IPv6 routes to Telegram.
+    V4Only,
+    /// Only use IPv6.
+    V6Only,
+}
+
+// Settings for the HTTP client of the default transport.
+pub(crate) struct HttpOptions {
+    pub max_connections: usize,
+    pub connect_timeout: Option<Duration>,
+    pub tcp_keepalive: Option<Duration>,
+    pub http_version: HttpVersion,
+    pub ip_family: IpFamily,
+    pub test_environment: bool,
+    pub resolve_to: Option<IpAddr>,
+    pub front_domain: Option<String>,
+    pub ca_certificate: Option<PathBuf>,
+    pub doh_url: Option<String>,
+    #[cfg(feature = "rustls")]
+    pub rustls: bool,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: 4,
+            connect_timeout: None,
+            tcp_keepalive: None,
+            http_version: HttpVersion::Auto,
+            ip_family: IpFamily::PreferV6,
+            test_environment: false,
+            resolve_to: None,
+            front_domain: None,
+            ca_certificate: None,
+            doh_url: None,
+            #[cfg(feature = "rustls")]
+            rustls: false,
+        }
+    }
+}
+
+impl HttpOptions {
+    // Builds the default transport for these options: curl's, unless rustls was asked for or curl is not compiled in.
+    pub fn build_transport(&self) -> anyhow::Result<Arc<dyn Transport>> {
+        #[cfg(feature = "rustls")]
+        if self.rustls || cfg!(not(feature = "curl")) {
+            return Ok(Arc::new(self.build_rustls_transport()?));
+        }
+        #[cfg(feature = "curl")]
+        return Ok(Arc::new(self.build_http_transport()?));
+        #[cfg(not(feature = "curl"))]
+        anyhow::bail!("no HTTP transport is compiled in: enable the curl or rustls feature, or set a transport on the builder")
+    }
+
+    #[cfg(feature = "curl")]
+    pub fn build_http_transport(&self) -> anyhow::Result<HttpTransport> {
+        let mut transport = HttpTransport::new(self.build_client()?);
+        if let Some(domain) = &self.front_domain {
+            transport = transport.front_domain(domain);
+        }
+        if let (Some(url), None) = (&self.doh_url, self.resolve_to) {
+            // the provider is reached with the same CA bundle and IP family as Telegram. The
+            // resolver hands curl a single address with no fallback between families, so it
+            // only looks up IPv6 when IPv4 is ruled out.
+            let resolver_family = match self.ip_family {
+                IpFamily::V6Only => IpFamily::V6Only,
+                IpFamily::PreferV6 | IpFamily::V4Only => IpFamily::V4Only,
+            };
+            let resolver =
+                DohResolver::with_client(url, self.build_client()?).ip_family(resolver_family);
+            transport = transport.dns_over_https(resolver);
+        }
+        Ok(if self.test_environment {
+            transport.test_environment()
+        } else {
+            transport
+        })
+    }
+
+    #[cfg(feature = "curl")]
+    pub fn build_client(&self) -> anyhow::Result<HttpClient> {
+        let mut builder = isahc::HttpClientBuilder::new()
+            .max_connections(self.max_connections)
+            .version_negotiation(match self.http_version {
+                HttpVersion::Auto => VersionNegotiation::latest_compatible(),
+                HttpVersion::Http1Only => VersionNegotiation::http11(),
+                HttpVersion::Http2Only => VersionNegotiation::http2(),
+            })
+            .ip_version(match self.ip_family {
+                IpFamily::PreferV6 => IpVersion::Any,
+                IpFamily::V4Only => IpVersion::V4,
+                IpFamily::V6Only => IpVersion::V6,
+            });
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(addr) = self.resolve_to {
+            let host = self.front_domain.as_deref().unwrap_or(API_HOST);
+            builder = builder.dns_resolve(ResolveMap::new().add(host, 443, addr));
+        }
+        if let Some(path) = &self.ca_certificate {
+            builder = builder.ssl_ca_certificate(CaCertificate::file(path));
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// The default transport, calling the Telegram bot API over HTTPS.
+///
+/// It uses libcurl with the system's TLS library. Builds that cannot link those, like static musl binaries, can turn off the `curl` feature and use the `rustls` feature's [RustlsTransport] instead.
+#[cfg(feature = "curl")]
+pub struct HttpTransport {
+    client: HttpClient,
+    test_environment: bool,
+    front_domain: Option<String>,
+    resolver: Option<DohResolver>,
+}
+
+#[cfg(feature = "curl")]
+impl HttpTransport {
+    /// Creates a transport using the given HTTP client.
+    pub fn new(client: HttpClient) -> Self {
+        Self {
+            client,
+            test_environment: false,
+            front_domain: None,
+            resolver: None,
+        }
+    }
+
+    /// Makes the transport use Telegram's test environment, where bots and users are separate from the production ones.
+    pub fn test_environment(mut self) -> Self {
+        self.test_environment = true;
+        self
+    }
+
+    /// Connects to, and sends as the TLS server name, another domain in front of Telegram, while still asking for api.telegram.org in the Host header.
+    pub fn front_domain(mut self, domain: &str) -> Self {
+        self.front_domain = Some(domain.to_owned());
+        self
+    }
+
+    /// Looks up Telegram's address with the given DNS-over-HTTPS resolver instead of the system's DNS.
+    pub fn dns_over_https(mut self, resolver: DohResolver) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    fn method_url(&self, token: &str, method: &str) -> String {
+        let env = if self.test_environment { "/test" } else { "" };
+        format!("https://{}/bot{token}{env}/{method}", self.connect_host())
+    }
+
+    fn file_url(&self, token: &str, file_path: &str) -> String {
+        let env = if self.test_environment { "/test" } else { "" };
+        format!(
+            "https://{}/file/bot{token}{env}/{file_path}",
+            self.connect_host()
+        )
+    }
+
+    fn connect_host(&self) -> &str {
+        self.front_domain.as_deref().unwrap_or(API_HOST)
+    }
+
+    // Starts a request, naming Telegram in the Host header when fronting and connecting to the address from DNS-over-HTTPS if enabled.
+    async fn request(&self, method: &str, url: String) -> anyhow::Result<Builder> {
+        let mut builder = Request::builder().method(method).uri(url);
+        if self.front_domain.is_some() {
+            builder = builder.header("Host", API_HOST);
+        }
+        if let Some(resolver) = &self.resolver {
+            let addr = resolver.resolve(self.connect_host()).await?;
+            builder = builder.dial(Dialer::ip_socket(SocketAddr::new(addr, 443)));
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(feature = "curl")]
+impl Transport for HttpTransport {
+    fn call<'a>(&'a self, token: &'a str, method: &'a str, args: &'a Value) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let body = self
+                .client
+                .send_async(
+                    self.request("POST", self.method_url(token, method))
+                        .await?
+                        .header("Content-Type", "application/json")
+                        .body(serde_json::to_vec(args)?)?,
+                )
+                .await?
+                .bytes()
+                .await?;
+            decode_response(body)
+        })
+    }
+
+    fn upload<'a>(
+        &'a self,
+        token: &'a str,
+        method: &'a str,
+        args: &'a Value,
+        uploads: &'a [Upload],
+    ) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let boundary = format!(
+                "telegram-bot-{:x}",
+                SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+            );
+            let body = self
+                .client
+                .send_async(
+                    self.request("POST", self.method_url(token, method))
+                        .await?
+                        .header(
+                            "Content-Type",
+                            format!("multipart/form-data; boundary={boundary}"),
+                        )
+                        .body(multipart_body(&boundary, args, uploads))?,
+                )
+                .await?
+                .bytes()
+                .await?;
+            decode_response(body)
+        })
+    }
+
+    fn download<'a>(&'a self, token: &'a str, file_path: &'a str) -> DownloadFuture<'a> {
+        Box::pin(async move {
+            let resp = self
+                .client
+                .send_async(
+                    self.request("GET", self.file_url(token, file_path))
+                        .await?
+                        .body(())?,
+                )
+                .await?;
+            if !resp.status().is_success() {
+                anyhow::bail!("cannot download {file_path}: HTTP {}", resp.status())
+            }
+            let body = resp.into_body();
+            Ok(Download {
+                size: body.len(),
+                body: Box::pin(body),
+            })
+        })
+    }
+}
+
+/// Resolves domains with DNS-over-HTTPS, for networks where plain DNS answers for Telegram are poisoned.
+///
+/// The provider must answer Google-style JSON queries (`?name=...&type=A` with `Accept: application/dns-json`), as Cloudflare, Google, and most public resolvers do.
+#[cfg(feature = "curl")]
+pub struct DohResolver {
+    url: String,
+    client: HttpClient,
+    ip_family: IpFamily,
+    cache: Mutex<Option<(String, IpAddr, Instant)>>,
+}
+
+#[cfg(feature = "curl")]
+impl DohResolver {
+    /// Cloudflare's resolver, addressed by IP so that reaching it needs no DNS.
+    pub const CLOUDFLARE: &'static str = "https://1.1.1.1/dns-query";
+    /// Google's resolver, addressed by IP so that reaching it needs no DNS.
+    pub const GOOGLE: &'static str = "https://8.8.8.8/resolve";
+
+    /// Creates a resolver that queries the given provider URL.
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        Ok(Self::with_client(url, HttpClient::new()?))
+    }
+
+    /// Creates a resolver that queries the given provider URL with the given HTTP client, such as one trusting a custom CA bundle.
+    pub fn with_client(url: &str, client: HttpClient) -> Self {
+        Self {
+            url: url.to_owned(),
+            client,
+            ip_family: IpFamily::V4Only,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Sets which address families to look up. Defaults to IPv4 only; with [IpFamily::PreferV6], an IPv6 address is used if the domain has one, and the A record is looked up if the AAAA query fails or finds nothing.
+    ///
+    /// Only one address is returned, so there is no fallback to IPv4 if the IPv6 address turns out to be unreachable.
+    pub fn ip_family(mut self, ip_family: IpFamily) -> Self {
+        self.ip_family = ip_family;
+        self
+    }
+
+    /// Looks up an address of a domain in the allowed families, reusing the previous answer until its TTL runs out.
+    pub async fn resolve(&self, domain: &str) -> anyhow::Result<IpAddr> {
+        if let Some((cached, addr, expiry)) = self.cache.lock().unwrap().as_ref() {
+            if cached == domain && Instant::now() < *expiry {
+                return Ok(*addr);
+            }
+        }
+        let record_types: &[(&str, u64)] = match self.ip_family {
+            IpFamily::PreferV6 => &[("AAAA", 28), ("A", 1)],
+            IpFamily::V4Only => &[("A", 1)],
+            IpFamily::V6Only => &[("AAAA", 28)],
+        };
+        let mut found = None;
+        let mut last_err = None;
+        for &(record_type, type_code) in record_types {
+            match self.query(domain, record_type, type_code).await {
+                Ok(Some(answer)) => {
+                    found = Some(answer);
+                    break;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::debug!("{record_type} lookup of {domain} failed: {:?}", err);
+                    last_err = Some(err);
+                }
+            }
+        }
+        let (addr, ttl) = match (found, last_err) {
+            (Some(found), _) => found,
+            (None, Some(err)) => return Err(err),
+            (None, None) => anyhow::bail!("DNS-over-HTTPS returned no address for {domain}"),
+        };
+        *self.cache.lock().unwrap() = Some((
+            domain.to_owned(),
+            addr,
+            Instant::now() + Duration::from_secs(ttl),
+        ));
+        Ok(addr)
+    }
+
+    // Looks up one type of address record, returning the first address and its TTL.
+    async fn query(
+        &self,
+        domain: &str,
+        record_type: &str,
+        type_code: u64,
+    ) -> anyhow::Result<Option<(IpAddr, u64)>> {
+        let resp: Value = self
+            .client
+            .send_async(
+                Request::get(format!("{}?name={domain}&type={record_type}", self.url))
+                    .header("Accept", "application/dns-json")
+                    .body(())?,
+            )
+            .await
+            .with_context(|| format!("cannot reach DNS-over-HTTPS provider {}", self.url))?
+            .json()
+            .await?;
+        // CNAMEs in the chain are skipped
+        Ok(resp["Answer"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|answer| answer["type"] == type_code)
+            .find_map(|answer| {
+                let addr = answer["data"].as_str()?.parse().ok()?;
+                Some((addr, answer["TTL"].as_u64().unwrap_or(60)))
+            }))
+    }
+}
+
+// Encodes arguments and files as multipart/form-data. Non-string arguments are sent as JSON, as Telegram expects.
+#[cfg(any(feature = "curl", feature = "rustls"))]
+fn multipart_body(boundary: &str, args: &Value, uploads: &[Upload]) -> Vec<u8> {
+    let mut body = vec![];
+    for (name, value) in args.as_object().into_iter().flatten() {
+        let value = match value {
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        };
+        body.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+            )
+            .as_bytes(),
+        );
+    }
+    for upload in uploads {
+        body.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                upload.field,
+                upload.file_name.replace(['"', '\r', '\n'], "_")
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(&upload.data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+    body
+}
+
+// Parses a raw response body. All responses are parsed here, so this is the one place to swap in a faster JSON parser.
+#[cfg(all(any(feature = "curl", feature = "rustls"), not(feature = "simd-json")))]
+fn decode_response(body: Vec<u8>) -> anyhow::Result<Value> {
+    Ok(serde_json::from_slice(&body)?)
+}
+
+// simd-json parses in place, so it takes the body by value.
+#[cfg(all(any(feature = "curl", feature = "rustls"), feature = "simd-json"))]
+fn decode_response(mut body: Vec<u8>) -> anyhow::Result<Value> {
+    Ok(simd_json::serde::from_slice(&mut body)?)
+}
+
+#[cfg(all(test, any(feature = "curl", feature = "rustls")))]
+mod tests {
+    #[cfg(feature = "curl")]
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    // Serves DoH answers over plain HTTP, failing AAAA queries with a body that is not JSON.
+    #[cfg(feature = "curl")]
+    fn serve_doh() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/resolve", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let body = if request_line.contains("type=AAAA") {
+                    "oops".to_owned()
+                } else {
+                    serde_json::json!({"Answer": [{"type": 1, "data": "149.154.167.220", "TTL": 300}]})
+                        .to_string()
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[cfg(feature = "curl")]
+    #[test]
+    fn doh_falls_back_to_a_records_when_aaaa_fails() {
+        let resolver = DohResolver::new(&serve_doh())
+            .unwrap()
+            .ip_family(IpFamily::PreferV6);
+        let addr = smol::block_on(resolver.resolve(API_HOST)).unwrap();
+        assert_eq!(addr, "149.154.167.220".parse::<IpAddr>().unwrap());
+        let v6_only = DohResolver::new(&serve_doh())
+            .unwrap()
+            .ip_family(IpFamily::V6Only);
+        assert!(smol::block_on(v6_only.resolve(API_HOST)).is_err());
+    }
+
+    #[cfg(feature = "curl")]
+    #[test]
+    fn doh_resolver_only_looks_up_ipv6_when_ipv4_is_ruled_out() {
+        let options = HttpOptions {
+            doh_url: Some(DohResolver::CLOUDFLARE.to_owned()),
+            ..Default::default()
+        };
+        let family = |options: &HttpOptions| {
+            options
+                .build_http_transport()
+                .unwrap()
+                .resolver
+                .map(|resolver| resolver.ip_family)
+        };
+        assert_eq!(family(&options), Some(IpFamily::V4Only));
+        let options = HttpOptions {
+            ip_family: IpFamily::V6Only,
+            ..options
+        };
+        assert_eq!(family(&options), Some(IpFamily::V6Only));
+    }
+
+    #[test]
+    fn decodes_responses() {
+        let body = br#"{"ok":true,"result":[{"update_id":1,"message":{"text":"h\u00e9 \ud83d\ude00","date":1700000000}}]}"#;
+        let resp = decode_response(body.to_vec()).unwrap();
+        assert_eq!(resp["result"][0]["message"]["text"], "hé 😀");
+        assert_eq!(resp["result"][0]["message"]["date"], 1700000000);
+        assert!(decode_response(b"{\"ok\":".to_vec()).is_err());
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn rustls_transport_rejects_curl_only_options() {
+        assert!(HttpOptions::default().build_rustls_transport().is_ok());
+        let options = HttpOptions {
+            front_domain: Some("example.com".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.build_rustls_transport().err().unwrap().to_string(),
+            "the rustls transport does not support domain fronting"
+        );
+        let options = HttpOptions {
+            http_version: HttpVersion::Http2Only,
+            ..Default::default()
+        };
+        assert!(options.build_rustls_transport().is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    io::{self, Read},
+    net::{SocketAddr, ToSocketAddrs},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde_json::Value;
+
+use super::{
+    decode_response, multipart_body, Download, DownloadFuture, HttpOptions, HttpVersion, IpFamily,
+    Transport, TransportFuture, Upload, API_HOST,
+};
+
+/// A transport calling the Telegram bot API over HTTPS with rustls and Mozilla's root certificates, for builds without libcurl or OpenSSL, like static musl binaries.
+///
+/// It speaks HTTP/1.1 only, and its requests block a thread of smol's blocking pool while they run.
+#[derive(Clone)]
+pub struct RustlsTransport {
+    agent: ureq::Agent,
+    test_environment: bool,
+}
+
+impl RustlsTransport {
+    /// Creates a transport with default settings.
+    pub fn new() -> Self {
+        Self::with_agent(ureq::Agent::new())
+    }
+
+    /// Creates a transport that makes requests with the given ureq agent.
+    pub fn with_agent(agent: ureq::Agent) -> Self {
+        Self {
+            agent,
+            test_environment: false,
+        }
+    }
+
+    /// Makes the transport use Telegram's test environment, where bots and users are separate from the production ones.
+    pub fn test_environment(mut self) -> Self {
+        self.test_environment = true;
+        self
+    }
+
+    fn method_url(&self, token: &str, method: &str) -> String {
+        let env = if self.test_environment { "/test" } else { "" };
+        format!("https://{API_HOST}/bot{token}{env}/{method}")
+    }
+
+    fn file_url(&self, token: &str, file_path: &str) -> String {
+        let env = if self.test_environment { "/test" } else { "" };
+        format!("https://{API_HOST}/file/bot{token}{env}/{file_path}")
+    }
+}
+
+impl Default for RustlsTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for RustlsTransport {
+    fn call<'a>(&'a self, token: &'a str, method: &'a str, args: &'a Value) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let request = self
+                .agent
+                .post(&self.method_url(token, method))
+                .set("Content-Type", "application/json");
+            decode_response(send(request, serde_json::to_vec(args)?).await?)
+        })
+    }
+
+    fn upload<'a>(
+        &'a self,
+        token: &'a str,
+        method: &'a str,
+        args: &'a Value,
+        uploads: &'a [Upload],
+    ) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let boundary = format!(
+                "telegram-bot-{:x}",
+                SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+            );
+            let request = self.agent.post(&self.method_url(token, method)).set(
+                "Content-Type",
+                &format!("multipart/form-data; boundary={boundary}"),
+            );
+            decode_response(send(request, multipart_body(&boundary, args, uploads)).await?)
+        })
+    }
+
+    fn download<'a>(&'a self, token: &'a str, file_path: &'a str) -> DownloadFuture<'a> {
+        Box::pin(async move {
+            let request = self.agent.get(&self.file_url(token, file_path));
+            let resp = smol::unblock(move || match request.call() {
+                Ok(resp) => Ok(resp),
+                Err(ureq::Error::Status(status, _)) => Err(anyhow::anyhow!("HTTP {status}")),
+                Err(err) => Err(err.into()),
+            })
+            .await
+            .with_context(|| format!("cannot download {file_path}"))?;
+            Ok(Download {
+                size: resp
+                    .header("Content-Length")
+                    .and_then(|len| len.parse().ok()),
+                body: Box::pin(smol::Unblock::new(resp.into_reader())),
+            })
+        })
+    }
+}
+
+// Sends a request on the blocking pool and reads the whole response, including that of an error status, since Telegram explains its errors in the body.
+async fn send(request: ureq::Request, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    smol::unblock(move || {
+        let resp = match request.send_bytes(&body) {
+            Ok(resp) | Err(ureq::Error::Status(_, resp)) => resp,
+            Err(err) => return Err(err.into()),
+        };
+        let mut body = vec![];
+        resp.into_reader().read_to_end(&mut body)?;
+        Ok(body)
+    })
+    .await
+}
+
+impl HttpOptions {
+    // Builds a rustls transport, failing on options that only the curl transport supports.
+    pub fn build_rustls_transport(&self) -> anyhow::Result<RustlsTransport> {
+        let unsupported = [
+            (self.http_version == HttpVersion::Http2Only, "HTTP/2"),
+            (self.tcp_keepalive.is_some(), "TCP keepalive"),
+            (self.front_domain.is_some(), "domain fronting"),
+            (self.ca_certificate.is_some(), "a custom CA certificate"),
+            (self.doh_url.is_some(), "DNS-over-HTTPS"),
+        ];
+        if let Some((_, option)) = unsupported.iter().find(|(set, _)| *set) {
+            anyhow::bail!("the rustls transport does not support {option}")
+        }
+        let (resolve_to, ip_family) = (self.resolve_to, self.ip_family);
+        let mut builder = ureq::AgentBuilder::new()
+            .max_idle_connections_per_host(self.max_connections)
+            .resolver(move |netloc: &str| -> io::Result<Vec<SocketAddr>> {
+                let mut addrs: Vec<SocketAddr> = match resolve_to {
+                    Some(addr) if netloc == format!("{API_HOST}:443") => {
+                        vec![SocketAddr::new(addr, 443)]
+                    }
+                    _ => netloc.to_socket_addrs()?.collect(),
+                };
+                addrs.retain(|addr| match ip_family {
+                    IpFamily::PreferV6 => true,
+                    IpFamily::V4Only => addr.is_ipv4(),
+                    IpFamily::V6Only => addr.is_ipv6(),
+                });
+                // ureq tries addresses in order
+                addrs.sort_by_key(|addr| addr.is_ipv4());
+                Ok(addrs)
+            });
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.timeout_connect(timeout);
+        }
+        let transport = RustlsTransport::with_agent(builder.build());
+        Ok(if self.test_environment {
+            transport.test_environment()
+        } else {
+            transport
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{ApiError, Inner};
+
+/// Why Telegram refused to deliver a message to a chat, permanently until the user or chat acts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnreachableReason {
+    /// The user blocked the bot.
+    Blocked,
+    /// The user deleted their account.
+    Deactivated,
+    /// The bot was removed from the group or channel.
+    Kicked,
+}
+
+/// The error returned when sending to a chat that cannot receive messages from the bot, so that broadcast lists can drop it.
+#[derive(Clone, Debug)]
+pub struct Unreachable {
+    pub chat_id: i64,
+    pub reason: UnreachableReason,
+    /// Telegram's original error.
+    pub error: ApiError,
+}
+
+impl std::fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "chat {} is unreachable: {}", self.chat_id, self.error)
+    }
+}
+
+impl std::error::Error for Unreachable {}
+
+impl UnreachableReason {
+    // Recognizes the 403 errors Telegram returns for chats the bot can no longer message.
+    fn from_error(err: &ApiError) -> Option<Self> {
+        if err.error_code != 403 {
+            return None;
+        }
+        let description = err.description.as_str();
+        if description.contains("bot was blocked by the user") {
+            Some(Self::Blocked)
+        } else if description.contains("user is deactivated") {
+            Some(Self::Deactivated)
+        } else if description.contains("bot was kicked")
+            || description.contains("bot is not a member")
+        {
+            Some(Self::Kicked)
+        } else {
+            None
+        }
+    }
+}
+
+impl Inner {
+    // Turns an error sending to a chat into an [Unreachable] if the chat cannot be messaged, calling the hook for it.
+    pub(crate) fn check_unreachable(
+        &self,
+        chat_id: Option<i64>,
+        err: anyhow::Error,
+    ) -> anyhow::Error {
+        let (Some(chat_id), Some(api_err)) = (chat_id, err.downcast_ref::<ApiError>()) else {
+            return err;
+        };
+        let Some(reason) = UnreachableReason::from_error(api_err) else {
+            return err;
+        };
+        if let Some(on_unreachable) = &self.on_unreachable {
+            on_unreachable(chat_id, reason);
+        }
+        Unreachable {
+            chat_id,
+            reason,
+            error: api_err.clone(),
+        }
+        .into()
+    }
+}
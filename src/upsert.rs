@@ -0,0 +1,136 @@
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::{outgoing::MessageContent, ApiError, BotHandle, Inner, OutgoingMessage, TelegramBot};
+
+impl Inner {
+    // Edits a message to match a text message, returning None for "message is not modified" unless the bot reports those.
+    async fn try_edit(
+        &self,
+        message_id: i64,
+        msg: &OutgoingMessage,
+    ) -> anyhow::Result<Option<Value>> {
+        let req = msg
+            .to_edit_request(message_id)
+            .context("only text messages can be edited into")?;
+        match self
+            .call_api_rate_limited(req.method, req.args, &req.uploads)
+            .await
+        {
+            Err(err) if !self.report_unmodified && is_unmodified(&err) => Ok(None),
+            res => res.map(Some),
+        }
+    }
+
+    async fn edit(&self, message_id: i64, msg: &OutgoingMessage) -> anyhow::Result<Value> {
+        Ok(self
+            .try_edit(message_id, msg)
+            .await?
+            .unwrap_or_else(|| unmodified_message(message_id, msg)))
+    }
+
+    async fn upsert(&self, key: &str, msg: OutgoingMessage) -> anyhow::Result<Value> {
+        let store_key = format!("upsert/{key}");
+        let existing = self
+            .store
+            .get(&store_key)?
+            .filter(|existing| existing["chat_id"] == msg.chat_id);
+        let message_id = existing
+            .as_ref()
+            .and_then(|existing| existing["message_id"].as_i64());
+        if let Some(message_id) =
+            message_id.filter(|_| matches!(msg.content, MessageContent::Text { .. }))
+        {
+            match self.try_edit(message_id, &msg).await {
+                Ok(Some(edited)) => {
+                    self.store.set(&store_key, upsert_entry(&edited))?;
+                    return Ok(edited);
+                }
+                // the message already says this, so it is still the one last sent or edited
+                Ok(None) => {
+                    return Ok(existing
+                        .map(|existing| existing["message"].clone())
+                        .filter(Value::is_object)
+                        .unwrap_or_else(|| unmodified_message(message_id, &msg)))
+                }
+                // the message was deleted or is too old to edit, so a new one takes its place
+                Err(err) if is_gone(&err) => {
+                    tracing::debug!("cannot edit message for {key}, sending it again: {err}")
+                }
+                Err(err) => return Err(err).context("cannot edit message"),
+            }
+        }
+        let sent = self.send(msg).await?;
+        self.store.set(&store_key, upsert_entry(&sent))?;
+        Ok(sent)
+    }
+}
+
+fn upsert_entry(message: &Value) -> Value {
+    json!({
+        "chat_id": message["chat"]["id"],
+        "message_id": message["message_id"],
+        "message": message,
+    })
+}
+
+// Stands in for the message an unmodified edit returns none of, with what is known of it.
+fn unmodified_message(message_id: i64, msg: &OutgoingMessage) -> Value {
+    let mut message = json!({"message_id": message_id, "chat": {"id": msg.chat_id}});
+    if let MessageContent::Text { text, .. } = &msg.content {
+        message["text"] = json!(text);
+    }
+    message
+}
+
+fn is_unmodified(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<ApiError>()
+        .is_some_and(|err| err.description.contains("message is not modified"))
+}
+
+fn is_gone(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<ApiError>().is_some_and(|err| {
+        err.description.contains("message to edit not found")
+            || err.description.contains("message can't be edited")
+    })
+}
+
+impl TelegramBot {
+    /// Edits a message to match a text message, including its parse mode and keyboard. Editing a message to what it already says succeeds, unless [crate::TelegramBotBuilder::report_unmodified_edits] is set, returning just the message's ID, chat and text since Telegram sends back no message then.
+    pub async fn edit(
+        &self,
+        message_id: i64,
+        msg: impl Into<OutgoingMessage>,
+    ) -> anyhow::Result<Value> {
+        self.inner.edit(message_id, &msg.into()).await
+    }
+
+    /// Keeps one message per key up to date, such as a progress dashboard: the first call sends the message and later ones edit it, sending it again if it was deleted. Only text messages are edited in place; other kinds are sent anew each time.
+    pub async fn upsert(
+        &self,
+        key: &str,
+        msg: impl Into<OutgoingMessage>,
+    ) -> anyhow::Result<Value> {
+        self.inner.upsert(key, msg.into()).await
+    }
+}
+
+impl BotHandle {
+    /// Edits a message to match a text message. See [TelegramBot::edit].
+    pub async fn edit(
+        &self,
+        message_id: i64,
+        msg: impl Into<OutgoingMessage>,
+    ) -> anyhow::Result<Value> {
+        self.inner.edit(message_id, &msg.into()).await
+    }
+
+    /// Sends or edits the message kept under a key. See [TelegramBot::upsert].
+    pub async fn upsert(
+        &self,
+        key: &str,
+        msg: impl Into<OutgoingMessage>,
+    ) -> anyhow::Result<Value> {
+        self.inner.upsert(key, msg.into()).await
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::Context;
+use serde_json::{json, Value};
+
+use crate::BotHandle;
+
+impl BotHandle {
+    /// Verifies a user on behalf of the organization the bot represents, optionally with a description of up to 70 characters instead of the organization's default one.
+    pub async fn verify_user(
+        &self,
+        user_id: i64,
+        custom_description: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.verify(
+            "verifyUser",
+            json!({ "user_id": user_id }),
+            custom_description,
+        )
+        .await
+    }
+
+    /// Verifies a chat on behalf of the organization the bot represents, optionally with a custom description.
+    pub async fn verify_chat(
+        &self,
+        chat_id: i64,
+        custom_description: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.verify(
+            "verifyChat",
+            json!({ "chat_id": chat_id }),
+            custom_description,
+        )
+        .await
+    }
+
+    /// Removes the verification of a user that the organization the bot represents gave.
+    pub async fn remove_user_verification(&self, user_id: i64) -> anyhow::Result<()> {
+        self.verify(
+            "removeUserVerification",
+            json!({ "user_id": user_id }),
+            None,
+        )
+        .await
+    }
+
+    /// Removes the verification of a chat that the organization the bot represents gave.
+    pub async fn remove_chat_verification(&self, chat_id: i64) -> anyhow::Result<()> {
+        self.verify(
+            "removeChatVerification",
+            json!({ "chat_id": chat_id }),
+            None,
+        )
+        .await
+    }
+
+    async fn verify(
+        &self,
+        method: &str,
+        mut args: Value,
+        custom_description: Option<&str>,
+    ) -> anyhow::Result<()> {
+        if let Some(description) = custom_description {
+            args["custom_description"] = json!(description);
+        }
+        self.call_api(method, args)
+            .await
+            .with_context(|| format!("cannot call {method}"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,534 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use anyhow::Context;
+use serde_json::{json, Value};
+use smol::channel::{Receiver, Sender};
+
+use crate::{
+    source::{SourceFuture, UpdateSource},
+    transport::Upload,
+    TelegramBot,
+};
+
+/// The header Telegram puts the webhook's secret token in.
+pub const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+/// Options for [TelegramBot::set_webhook].
+#[derive(Clone, Debug, Default)]
+pub struct WebhookOptions {
+    certificate: Option<Vec<u8>>,
+    ip_address: Option<String>,
+    max_connections: Option<u32>,
+    allowed_updates: Option<Vec<String>>,
+    drop_pending_updates: bool,
+    secret_token: Option<String>,
+}
+
+impl WebhookOptions {
+    /// Creates options that leave everything at Telegram's defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uploads the public key of a self-signed certificate, in PEM format, so Telegram trusts the webhook server.
+    pub fn certificate(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.certificate = Some(pem.into());
+        self
+    }
+
+    /// Sends updates to this IP address instead of the one the webhook URL resolves to.
+    pub fn ip_address(mut self, ip_address: &str) -> Self {
+        self.ip_address = Some(ip_address.to_owned());
+        self
+    }
+
+    /// Sets how many connections Telegram may open to the webhook at once, from 1 to 100. Defaults to 40.
+    pub fn max_connections(mut self, max: u32) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Sets which update types to receive, like `["message", "callback_query"]`.
+    pub fn allowed_updates(mut self, types: &[&str]) -> Self {
+        self.allowed_updates = Some(types.iter().map(|t| t.to_string()).collect());
+        self
+    }
+
+    /// Makes Telegram send the token in the [SECRET_TOKEN_HEADER] header of every webhook request, so a [WebhookReceiver] with the same token can reject spoofed ones.
+    pub fn secret_token(mut self, token: &str) -> Self {
+        self.secret_token = Some(token.to_owned());
+        self
+    }
+
+    /// Drops updates that arrived before the webhook was set.
+    pub fn drop_pending_updates(mut self) -> Self {
+        self.drop_pending_updates = true;
+        self
+    }
+}
+
+/// The current webhook of a bot, as returned by getWebhookInfo.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WebhookInfo {
+    /// The webhook URL, or empty if the bot uses getUpdates.
+    pub url: String,
+    pub has_custom_certificate: bool,
+    pub pending_update_count: i64,
+    pub ip_address: Option<String>,
+    /// When the most recent delivery error happened, as a Unix timestamp, and what it was.
+    pub last_error: Option<(i64, String)>,
+    pub last_synchronization_error_date: Option<i64>,
+    pub max_connections: Option<i64>,
+    pub allowed_updates: Vec<String>,
+}
+
+impl WebhookInfo {
+    /// Parses a WebhookInfo object.
+    pub fn from_json(info: &Value) -> anyhow::Result<Self> {
+        Ok(Self {
+            url: info["url"]
+                .as_str()
+                .context("webhook info without a url")?
+                .to_owned(),
+            has_custom_certificate: info["has_custom_certificate"].as_bool().unwrap_or_default(),
+            pending_update_count: info["pending_update_count"].as_i64().unwrap_or_default(),
+            ip_address: info["ip_address"].as_str().map(str::to_owned),
+            last_error: info["last_error_date"].as_i64().map(|date| {
+                let message = info["last_error_message"].as_str().unwrap_or_default();
+                (date, message.to_owned())
+            }),
+            last_synchronization_error_date: info["last_synchronization_error_date"].as_i64(),
+            max_connections: info["max_connections"].as_i64(),
+            allowed_updates: info["allowed_updates"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|t| t.as_str().map(str::to_owned))
+                .collect(),
+        })
+    }
+}
+
+impl TelegramBot {
+    /// Makes Telegram send updates to the given HTTPS URL instead of answering getUpdates. The bot should then get its updates from a webhook source rather than polling.
+    pub async fn set_webhook(&self, url: &str, opts: WebhookOptions) -> anyhow::Result<()> {
+        let mut args = json!({ "url": url });
+        if let Some(ip_address) = &opts.ip_address {
+            args["ip_address"] = json!(ip_address);
+        }
+        if let Some(max) = opts.max_connections {
+            args["max_connections"] = json!(max);
+        }
+        if let Some(types) = &opts.allowed_updates {
+            args["allowed_updates"] = json!(types);
+        }
+        if opts.drop_pending_updates {
+            args["drop_pending_updates"] = json!(true);
+        }
+        if let Some(token) = &opts.secret_token {
+            check_secret_token(token)?;
+            args["secret_token"] = json!(token);
+        }
+        let uploads: Vec<Upload> = opts
+            .certificate
+            .map(|pem| Upload {
+                field: "certificate".to_owned(),
+                file_name: "certificate.pem".to_owned(),
+                data: pem,
+            })
+            .into_iter()
+            .collect();
+        self.inner
+            .call_api_with_uploads("setWebhook", args, &uploads)
+            .await
+            .context("cannot set webhook")?;
+        Ok(())
+    }
+
+    /// Removes the webhook, so updates can be polled again. Optionally drops the updates waiting to be delivered.
+    pub async fn delete_webhook(&self, drop_pending_updates: bool) -> anyhow::Result<()> {
+        self.inner
+            .call_api(
+                "deleteWebhook",
+                json!({ "drop_pending_updates": drop_pending_updates }),
+            )
+            .await
+            .context("cannot delete webhook")?;
+        Ok(())
+    }
+
+    /// Gets the current webhook, which has an empty URL if there is none.
+    pub async fn get_webhook_info(&self) -> anyhow::Result<WebhookInfo> {
+        WebhookInfo::from_json(&self.inner.call_api("getWebhookInfo", json!({})).await?)
+    }
+}
+
+/// The networks Telegram sends webhook requests from, as published in its documentation.
+pub const TELEGRAM_NETWORKS: &[&str] = &["149.154.160.0/20", "91.108.4.0/22"];
+
+/// A range of IP addresses, like `149.154.160.0/20`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Whether the address is in the network. IPv4 addresses mapped into IPv6 count as IPv4.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (addr, prefix) = s.split_once('/').unwrap_or((s, ""));
+        let addr: IpAddr = addr
+            .parse()
+            .with_context(|| format!("invalid address in network {s:?}"))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = if prefix.is_empty() {
+            max_prefix
+        } else {
+            prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .with_context(|| format!("invalid prefix length in network {s:?}"))?
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+/// Generates a random secret token for [WebhookOptions::secret_token], 64 hex characters long.
+pub fn generate_secret_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes)
+        .map_err(|err| anyhow::anyhow!("cannot get random bytes from the OS: {err}"))?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+// Checks a secret token against what Telegram accepts: 1 to 256 of `A-Z`, `a-z`, `0-9`, `_` and `-`.
+fn check_secret_token(token: &str) -> anyhow::Result<()> {
+    if !(1..=256).contains(&token.len()) {
+        anyhow::bail!(
+            "webhook secret token is {} characters, but must be 1 to 256",
+            token.len()
+        )
+    }
+    if let Some(c) = token
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '_' && *c != '-')
+    {
+        anyhow::bail!("webhook secret token contains invalid character {c:?}")
+    }
+    Ok(())
+}
+
+/// Why a webhook request was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebhookError {
+    /// The secret token header was missing or wrong.
+    Unauthorized,
+    /// The request came from outside the allowed networks, or its address was not given.
+    Forbidden,
+    /// The body was not a valid update.
+    BadRequest(String),
+    /// The bot has stopped taking updates.
+    Closed,
+}
+
+impl WebhookError {
+    /// The HTTP status code to answer the request with.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Self::Unauthorized => 401,
+            Self::Forbidden => 403,
+            Self::BadRequest(_) => 400,
+            Self::Closed => 503,
+        }
+    }
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unauthorized => {
+                f.write_str("webhook request has a missing or wrong secret token")
+            }
+            Self::Forbidden => {
+                f.write_str("webhook request is from an address outside the allowed networks")
+            }
+            Self::BadRequest(err) => write!(f, "webhook request is not a valid update: {err}"),
+            Self::Closed => f.write_str("bot is no longer taking webhook updates"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+/// An update source fed by webhook requests, which an HTTP server passes to its [WebhookReceiver].
+pub struct WebhookSource {
+    updates: Receiver<Value>,
+}
+
+/// Accepts webhook requests for a [WebhookSource]. Cloneable, so it can be shared with HTTP server handlers.
+///
+/// It does not depend on any HTTP framework, but with the `axum` feature, [WebhookReceiver::router] mounts it in an axum app:
+///
+/// ```ignore
+/// let (source, receiver) = WebhookSource::new(Some(&secret));
+/// let bot = TelegramBot::builder(token).update_source(source).build(handler);
+/// let app = Router::new().merge(receiver.router("/telegram"));
+/// ```
+#[derive(Clone)]
+pub struct WebhookReceiver {
+    updates: Sender<Value>,
+    secret_token: Option<String>,
+    allowed_networks: Option<Vec<IpNetwork>>,
+}
+
+impl WebhookSource {
+    /// Creates a source and the receiver that feeds it. If a secret token is given, requests without it are rejected.
+    pub fn new(secret_token: Option<&str>) -> (Self, WebhookReceiver) {
+        let (send, recv) = smol::channel::unbounded();
+        (
+            Self { updates: recv },
+            WebhookReceiver {
+                updates: send,
+                secret_token: secret_token.map(str::to_owned),
+                allowed_networks: None,
+            },
+        )
+    }
+}
+
+impl UpdateSource for WebhookSource {
+    fn next_batch(&mut self) -> SourceFuture<'_> {
+        Box::pin(async move {
+            let Ok(first) = self.updates.recv().await else {
+                return Ok(None);
+            };
+            let mut batch = vec![first];
+            while let Ok(update) = self.updates.try_recv() {
+                batch.push(update);
+            }
+            Ok(Some(batch))
+        })
+    }
+}
+
+impl WebhookReceiver {
+    /// Only accepts requests from [TELEGRAM_NETWORKS], which must then be handled with [WebhookReceiver::receive_from]. Only useful when requests reach the server directly, not through a reverse proxy.
+    pub fn telegram_networks_only(self) -> Self {
+        let networks: Vec<IpNetwork> = TELEGRAM_NETWORKS
+            .iter()
+            .map(|network| network.parse().expect("built-in networks are valid"))
+            .collect();
+        self.allowed_networks(&networks)
+    }
+
+    /// Only accepts requests from the given networks, which must then be handled with [WebhookReceiver::receive_from].
+    pub fn allowed_networks(mut self, networks: &[IpNetwork]) -> Self {
+        self.allowed_networks = Some(networks.to_vec());
+        self
+    }
+
+    /// Handles a webhook request, given the value of its [SECRET_TOKEN_HEADER] header and its body. On success, the server should answer with status 200.
+    pub fn receive(&self, secret_header: Option<&str>, body: &[u8]) -> Result<(), WebhookError> {
+        self.receive_inner(None, secret_header, body)
+    }
+
+    /// Like [WebhookReceiver::receive], also checking the address the request came from against the allowed networks.
+    pub fn receive_from(
+        &self,
+        peer: IpAddr,
+        secret_header: Option<&str>,
+        body: &[u8],
+    ) -> Result<(), WebhookError> {
+        self.receive_inner(Some(peer), secret_header, body)
+    }
+
+    fn receive_inner(
+        &self,
+        peer: Option<IpAddr>,
+        secret_header: Option<&str>,
+        body: &[u8],
+    ) -> Result<(), WebhookError> {
+        if let Some(networks) = &self.allowed_networks {
+            if !peer.is_some_and(|peer| networks.iter().any(|network| network.contains(peer))) {
+                return Err(WebhookError::Forbidden);
+            }
+        }
+        if let Some(expected) = &self.secret_token {
+            if !secret_header
+                .is_some_and(|got| constant_time_eq(got.as_bytes(), expected.as_bytes()))
+            {
+                return Err(WebhookError::Unauthorized);
+            }
+        }
+        let update: Value = serde_json::from_slice(body)
+            .map_err(|err| WebhookError::BadRequest(err.to_string()))?;
+        if !update["update_id"].is_i64() {
+            return Err(WebhookError::BadRequest("no update_id".to_owned()));
+        }
+        self.updates
+            .try_send(update)
+            .map_err(|_| WebhookError::Closed)
+    }
+}
+
+/// The most a webhook request body may be. Updates are far smaller.
+#[cfg(feature = "axum")]
+const MAX_WEBHOOK_BODY: usize = 1 << 20;
+
+#[cfg(feature = "axum")]
+impl WebhookReceiver {
+    /// An axum router that takes webhook requests with POST at the given path, for merging into an existing app.
+    ///
+    /// With allowed networks, serve the app with `into_make_service_with_connect_info::<SocketAddr>()`, since requests whose address is unknown are rejected.
+    pub fn router<S: Clone + Send + Sync + 'static>(self, path: &str) -> axum::Router<S> {
+        use axum::{
+            extract::{ConnectInfo, Request},
+            http::StatusCode,
+        };
+        axum::Router::new().route(
+            path,
+            axum::routing::post(move |request: Request| async move {
+                let peer = request
+                    .extensions()
+                    .get::<ConnectInfo<std::net::SocketAddr>>()
+                    .map(|info| info.0.ip());
+                let secret = request
+                    .headers()
+                    .get(SECRET_TOKEN_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_owned);
+                let Ok(body) = axum::body::to_bytes(request.into_body(), MAX_WEBHOOK_BODY).await
+                else {
+                    return StatusCode::PAYLOAD_TOO_LARGE;
+                };
+                match self.receive_inner(peer, secret.as_deref(), &body) {
+                    Ok(()) => StatusCode::OK,
+                    Err(err) => {
+                        tracing::warn!("rejected webhook request: {err}");
+                        StatusCode::from_u16(err.status_code())
+                            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+                    }
+                }
+            }),
+        )
+    }
+}
+
+// Compares secrets without leaking how much of them matched through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contains(network: &str, addr: &str) -> bool {
+        network
+            .parse::<IpNetwork>()
+            .unwrap()
+            .contains(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn ipv4_networks_contain_their_addresses() {
+        assert!(contains("149.154.160.0/20", "149.154.167.220"));
+        assert!(!contains("149.154.160.0/20", "149.154.176.1"));
+        assert!(contains("91.108.4.0/22", "91.108.7.255"));
+        assert!(contains("10.0.0.1", "10.0.0.1"));
+        assert!(!contains("10.0.0.1", "10.0.0.2"));
+        assert!(contains("0.0.0.0/0", "8.8.8.8"));
+    }
+
+    #[test]
+    fn mapped_ipv4_addresses_count_as_ipv4() {
+        assert!(contains("149.154.160.0/20", "::ffff:149.154.161.1"));
+        assert!(!contains("::/0", "149.154.161.1"));
+    }
+
+    #[test]
+    fn ipv6_networks_contain_their_addresses() {
+        assert!(contains("2001:67c:4e8::/48", "2001:67c:4e8:f004::9"));
+        assert!(!contains("2001:67c:4e8::/48", "2001:67c:4e9::1"));
+        assert!(contains("::/0", "::1"));
+    }
+
+    #[test]
+    fn rejects_invalid_networks() {
+        for network in ["10.0.0.0/33", "::/129", "10.0.0/8", "10.0.0.0/x"] {
+            assert!(network.parse::<IpNetwork>().is_err(), "{network}");
+        }
+    }
+
+    #[test]
+    fn secret_tokens_are_random_and_valid() {
+        let token = generate_secret_token().unwrap();
+        assert_eq!(token.len(), 64);
+        assert!(check_secret_token(&token).is_ok());
+        assert_ne!(token, generate_secret_token().unwrap());
+    }
+
+    #[test]
+    fn rejects_secret_tokens_telegram_would() {
+        assert!(check_secret_token("abc_DEF-123").is_ok());
+        assert!(check_secret_token(&"a".repeat(256)).is_ok());
+        for token in ["", "has space", "slash/", "émoji", &"a".repeat(257)] {
+            assert!(check_secret_token(token).is_err(), "{token:?}");
+        }
+    }
+
+    #[cfg(feature = "axum")]
+    #[test]
+    fn router_feeds_the_webhook_source() {
+        use axum::{body::Body, extract::ConnectInfo, http::Request};
+        use tower::ServiceExt;
+
+        smol::block_on(async {
+            let (mut source, receiver) = WebhookSource::new(Some("secret"));
+            let router: axum::Router = receiver.clone().router("/telegram");
+            let request = |secret: &str| {
+                Request::post("/telegram")
+                    .header(SECRET_TOKEN_HEADER, secret)
+                    .body(Body::from(r#"{"update_id": 5}"#))
+                    .unwrap()
+            };
+            let resp = router.clone().oneshot(request("secret")).await.unwrap();
+            assert_eq!(resp.status(), 200);
+            let resp = router.oneshot(request("wrong")).await.unwrap();
+            assert_eq!(resp.status(), 401);
+            let batch = source.next_batch().await.unwrap().unwrap();
+            assert_eq!(batch, [json!({"update_id": 5})]);
+
+            let router: axum::Router = receiver
+                .allowed_networks(&["10.0.0.0/8".parse().unwrap()])
+                .router("/telegram");
+            let resp = router.clone().oneshot(request("secret")).await.unwrap();
+            assert_eq!(resp.status(), 403);
+            let mut from_network = request("secret");
+            from_network.extensions_mut().insert(ConnectInfo(
+                "10.1.2.3:443".parse::<std::net::SocketAddr>().unwrap(),
+            ));
+            let resp = router.oneshot(from_network).await.unwrap();
+            assert_eq!(resp.status(), 200);
+        });
+    }
+}